@@ -0,0 +1,135 @@
+//! "Analisi" panel of the GUI: annotates the game played so far in the background and saves it as PGN
+
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use eframe::egui;
+
+use crate::annotate::{eval_text, review_game, GameReview};
+use crate::pgn::pgn_date;
+use crate::search::SearchLimits;
+use crate::selfplay::game_result;
+use crate::Board;
+
+/// An analysis running on its own thread
+struct AnalysisJob {
+    progress: Arc<AtomicUsize>,  // Positions searched so far
+    total: usize,
+    receiver: Receiver<GameReview>,
+    start: Board,
+    result: String,              // PGN result of the game when the analysis started
+}
+
+pub struct AnalysisPanel {
+    movetime_ms: u64,             // Search time per position
+    output_path: String,          // Where the annotated game is saved
+    job: Option<AnalysisJob>,
+    review: Option<GameReview>,   // Last finished analysis
+    message: String,
+}
+
+impl Default for AnalysisPanel {
+    fn default() -> Self {
+        AnalysisPanel {
+            movetime_ms: 500,
+            output_path: "partita_analizzata.pgn".to_string(),
+            job: None,
+            review: None,
+            message: String::new(),
+        }
+    }
+}
+
+impl AnalysisPanel {
+    /// Starts annotating the moves played on `board`
+    fn start(&mut self, board: &Board, ctx: &egui::Context) {
+        let mut start = board.clone();
+        while start.unmake_move().is_some() {}
+        let moves: Vec<_> = board.history.iter().map(|undo| undo.mv).collect();
+        let result = game_result(board).map_or("*", |result| result.pgn_text()).to_string();
+
+        let progress = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = channel();
+        let limits = SearchLimits { movetime: Some(self.movetime_ms), ..Default::default() };
+        let (thread_start, thread_progress, ctx) = (start.clone(), progress.clone(), ctx.clone());
+        thread::spawn(move || {
+            let review = review_game(&thread_start, &moves, &limits, &thread_progress);
+            let _ = sender.send(review);
+            ctx.request_repaint();
+        });
+        self.job = Some(AnalysisJob { progress, total: board.history.len() + 1, receiver, start, result });
+        self.review = None;
+        self.message.clear();
+    }
+
+    /// Saves a finished analysis
+    fn finish(&mut self, job: AnalysisJob, review: GameReview) {
+        let tags = [
+            ("Event", "Partita GK".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", pgn_date()),
+            ("Round", "-".to_string()),
+            ("White", "?".to_string()),
+            ("Black", "?".to_string()),
+            ("Result", job.result.clone()),
+        ];
+        let text = review.to_pgn(&tags, &job.start, &job.result);
+        self.message = match fs::write(self.output_path.trim(), text) {
+            Ok(()) => format!("Partita annotata salvata in {}", self.output_path.trim()),
+            Err(err) => format!("Impossibile scrivere {}: {}", self.output_path.trim(), err),
+        };
+        self.review = Some(review);
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, board: &Board) {
+        ui.add(egui::Slider::new(&mut self.movetime_ms, 100..=5000).text("ms per posizione"));
+        ui.horizontal(|ui| {
+            ui.label("File PGN:");
+            ui.text_edit_singleline(&mut self.output_path);
+        });
+
+        if let Some(job) = &self.job {
+            match job.receiver.try_recv() {
+                Ok(review) => {
+                    let job = self.job.take().unwrap();
+                    self.finish(job, review);
+                },
+                Err(_) => {
+                    let done = job.progress.load(Ordering::SeqCst);
+                    ui.add(egui::ProgressBar::new(done as f32 / job.total as f32).text(format!("{}/{} posizioni", done, job.total)));
+                    ui.ctx().request_repaint_after(Duration::from_millis(200));
+                },
+            }
+        } else if ui.add_enabled(!board.history.is_empty(), egui::Button::new("Analizza partita")).clicked() {
+            self.start(board, ui.ctx());
+        }
+        if !self.message.is_empty() {
+            ui.label(&self.message);
+        }
+
+        let Some(review) = &self.review else { return };
+        for line in review.summary() {
+            ui.label(line);
+        }
+        egui::ScrollArea::vertical().id_source("analysis_moves").max_height(200.0).show(ui, |ui| {
+            for item in review.moves.iter().filter(|item| item.judgement.is_some()) {
+                let judgement = item.judgement.unwrap();
+                let number = if item.white_to_move { format!("{}.", item.fullmove_number) } else { format!("{}...", item.fullmove_number) };
+                ui.label(format!(
+                    "{} {}{} {} (-{} cp, precisione {:.0}%), valutazione {}",
+                    number,
+                    item.san,
+                    judgement.symbol(),
+                    judgement.name(),
+                    item.loss,
+                    item.accuracy,
+                    eval_text(item.white_eval)
+                ));
+            }
+        });
+    }
+}
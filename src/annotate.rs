@@ -0,0 +1,302 @@
+//! Game analysis: searches every position of a game, judges each move by the evaluation it loses and
+//! writes the annotated PGN: `gk_chess_engine annotate`
+
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::movegen::Move;
+use crate::pgn::{format_line, parse_pgn, write_game, PgnMove};
+use crate::search::{self, EndgameTables, SearchControl, SearchLimits, MATE_BOUND, MATE_SCORE};
+use crate::tt::TranspositionTable;
+use crate::Board;
+
+/// Centipawns lost (side to move's point of view) from which a move is an inaccuracy, a mistake, a blunder
+const INACCURACY_LOSS: i32 = 50;
+const MISTAKE_LOSS: i32 = 100;
+const BLUNDER_LOSS: i32 = 300;
+/// Scores beyond this (mates included) count as this much when measuring losses
+const MAX_JUDGED_SCORE: i32 = 1000;
+/// Moves of the better line shown as a variation
+const VARIATION_PLIES: usize = 8;
+
+/// How bad a move is
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    fn from_loss(loss: i32) -> Option<Judgement> {
+        match loss {
+            loss if loss >= BLUNDER_LOSS => Some(Judgement::Blunder),
+            loss if loss >= MISTAKE_LOSS => Some(Judgement::Mistake),
+            loss if loss >= INACCURACY_LOSS => Some(Judgement::Inaccuracy),
+            _ => None,
+        }
+    }
+
+    /// Numeric annotation glyph: $6 = ?!, $2 = ?, $4 = ??
+    fn nag(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "$6",
+            Judgement::Mistake => "$2",
+            Judgement::Blunder => "$4",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "Imprecisione",
+            Judgement::Mistake => "Errore",
+            Judgement::Blunder => "Errore grave",
+        }
+    }
+}
+
+/// Analysis of one move of the game
+#[derive(Clone)]
+pub struct MoveReview {
+    pub mv: Move,
+    pub san: String,
+    pub white_to_move: bool,
+    pub fullmove_number: u32,
+    pub best_line: Vec<Move>,            // Engine line from the position before the move
+    pub white_eval: i32,                 // Evaluation after the move, White's point of view
+    pub loss: i32,                       // Centipawns the move gave away compared to the best one
+    pub judgement: Option<Judgement>,
+    pub accuracy: f64,                   // 0 to 100, from the drop in winning chances
+    before: Board,                       // Position the move was played in
+}
+
+/// Analysis of a whole game, with per-player totals (White, Black)
+#[derive(Clone)]
+pub struct GameReview {
+    pub moves: Vec<MoveReview>,
+    pub accuracy: [Option<f64>; 2],
+    pub judgements: [[usize; 3]; 2],  // Inaccuracies, mistakes, blunders
+}
+
+/// A score as judged by the analysis tools: big advantages and mates all count as `MAX_JUDGED_SCORE`
+pub fn judged(score: i32) -> i32 {
+    score.clamp(-MAX_JUDGED_SCORE, MAX_JUDGED_SCORE)
+}
+
+/// Winning chances (0 to 100) of a score, as used for accuracy figures
+fn win_percent(score: i32) -> f64 {
+    let score = judged(score) as f64;
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.003_682_08 * score).exp()) - 1.0)
+}
+
+/// Accuracy of a move from the winning chances of its side before and after it
+fn move_accuracy(before: i32, after: i32) -> f64 {
+    let drop = (win_percent(before) - win_percent(after)).max(0.0);
+    (103.166_8 * (-0.043_54 * drop).exp() - 3.166_9 + 1.0).clamp(0.0, 100.0)
+}
+
+/// Score and line of `board` from the side to move's point of view (game-over positions need no search)
+fn analyse(board: &Board, limits: &SearchLimits, tt: &mut TranspositionTable) -> (i32, Vec<Move>) {
+    if board.legal_moves().is_empty() {
+        return (if board.in_check() { -MATE_SCORE } else { 0 }, Vec::new());
+    }
+    let control = SearchControl::default();
+    let result = search::search(board, limits, tt, EndgameTables::default(), None, &control, &mut |_| {});
+    (result.score, result.pv)
+}
+
+/// Searches every position of the game; `progress` counts the positions done
+pub fn review_game(start: &Board, moves: &[Move], limits: &SearchLimits, progress: &AtomicUsize) -> GameReview {
+    let mut tt = TranspositionTable::new(64);
+    let mut board = start.clone();
+    let mut analysis = vec![analyse(&board, limits, &mut tt)];
+    progress.fetch_add(1, Ordering::SeqCst);
+    for &mv in moves {
+        board.apply_move(mv);
+        analysis.push(analyse(&board, limits, &mut tt));
+        progress.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let mut review = GameReview { moves: Vec::new(), accuracy: [None; 2], judgements: [[0; 3]; 2] };
+    let mut accuracy_sums = [(0.0, 0); 2];
+    let mut board = start.clone();
+    for (index, &mv) in moves.iter().enumerate() {
+        let (best_score, best_line) = analysis[index].clone();
+        let after = -analysis[index + 1].0;
+        // The engine's own choice loses nothing, whatever the next search says
+        let loss = if best_line.first() == Some(&mv) { 0 } else { (judged(best_score) - judged(after)).max(0) };
+        let judgement = Judgement::from_loss(loss);
+        let side = usize::from(!board.white_to_move);
+        let accuracy = move_accuracy(best_score, after);
+        accuracy_sums[side].0 += accuracy;
+        accuracy_sums[side].1 += 1;
+        if let Some(judgement) = judgement {
+            review.judgements[side][judgement as usize] += 1;
+        }
+        review.moves.push(MoveReview {
+            mv,
+            san: board.to_san(mv),
+            white_to_move: board.white_to_move,
+            fullmove_number: board.fullmove_number,
+            best_line,
+            white_eval: if board.white_to_move { after } else { -after },
+            loss,
+            judgement,
+            accuracy,
+            before: board.clone(),
+        });
+        board.apply_move(mv);
+    }
+    review.accuracy = accuracy_sums.map(|(sum, count)| (count > 0).then(|| sum / count as f64));
+    review
+}
+
+/// Evaluation in the `[%eval]` format: pawns ("0.35", "-1.20") or moves to mate for the side that
+/// mates ("#3" White, "#-2" Black)
+pub fn eval_text(white_eval: i32) -> String {
+    let mate_moves = (MATE_SCORE - white_eval.abs() + 1) / 2;
+    if white_eval >= MATE_BOUND {
+        format!("#{}", mate_moves)
+    } else if white_eval <= -MATE_BOUND {
+        format!("#-{}", mate_moves)
+    } else {
+        format!("{:.2}", white_eval as f64 / 100.0)
+    }
+}
+
+impl GameReview {
+    /// Per-player summary: accuracy and how many moves of each kind
+    pub fn summary(&self) -> Vec<String> {
+        ["Bianco", "Nero"]
+            .iter()
+            .enumerate()
+            .map(|(side, name)| {
+                let [inaccuracies, mistakes, blunders] = self.judgements[side];
+                let accuracy = self.accuracy[side].map_or("-".to_string(), |accuracy| format!("{:.1}%", accuracy));
+                format!("{}: precisione {}, imprecisioni {}, errori {}, errori gravi {}", name, accuracy, inaccuracies, mistakes, blunders)
+            })
+            .collect()
+    }
+
+    /// The game with NAGs, `[%eval]` comments and the better line after every judged move
+    pub fn to_pgn(&self, tags: &[(&str, String)], start: &Board, result: &str) -> String {
+        let mut moves: Vec<PgnMove> = self
+            .moves
+            .iter()
+            .map(|review| {
+                let mut annotation = String::new();
+                let mut comment = Vec::new();
+                // A checkmate on the board needs no evaluation
+                if review.white_eval.abs() != MATE_SCORE {
+                    comment.push(format!("[%eval {}]", eval_text(review.white_eval)));
+                }
+                if let Some(judgement) = review.judgement {
+                    annotation.push_str(judgement.nag());
+                    if let Some(&best) = review.best_line.first() {
+                        comment.push(format!("{}. Era meglio {}.", judgement.name(), review.before.to_san(best)));
+                    }
+                }
+                if !comment.is_empty() {
+                    annotation.push_str(&format!(" {{{}}}", comment.join(" ")));
+                }
+                if review.judgement.is_some() && !review.best_line.is_empty() {
+                    let line = &review.best_line[..review.best_line.len().min(VARIATION_PLIES)];
+                    annotation.push_str(&format!(" ({})", format_line(&review.before, line)));
+                }
+                PgnMove { mv: review.mv, annotation }
+            })
+            .collect();
+        if let Some(last) = moves.last_mut() {
+            let accuracy = |side: usize| self.accuracy[side].map_or("-".to_string(), |accuracy| format!("{:.1}%", accuracy));
+            last.annotation.push_str(&format!(" {{Precisione: Bianco {}, Nero {}}}", accuracy(0), accuracy(1)));
+        }
+        write_game(tags, start, &moves, result)
+    }
+}
+
+/// `annotate <games.pgn> <output.pgn> [--movetime ms | --depth N]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "Uso: gk_chess_engine annotate <partite.pgn> <uscita.pgn> [--movetime ms | --depth N]";
+    let (limits, paths) = search::parse_search_limits(args, 500)?;
+    let [input, output] = paths[..] else { return Err(USAGE.to_string()) };
+
+    let text = fs::read_to_string(input).map_err(|err| format!("Impossibile leggere {}: {}", input, err))?;
+    let games = parse_pgn(&text);
+    let mut annotated = String::new();
+    for (number, game) in games.iter().enumerate() {
+        let start = match game.tag("FEN") {
+            Some(fen) => Board::from_fen(fen).map_err(|err| format!("Partita {}: {}", number + 1, err))?,
+            None => Board::new(),
+        };
+        let mut board = start.clone();
+        let mut moves = Vec::new();
+        for san in &game.moves {
+            let mv = board.parse_san(san).ok_or_else(|| format!("Partita {}: mossa non valida {}", number + 1, san))?;
+            board.apply_move(mv);
+            moves.push(mv);
+        }
+
+        let review = review_game(&start, &moves, &limits, &AtomicUsize::new(0));
+        let white = game.tag("White").unwrap_or("?");
+        let black = game.tag("Black").unwrap_or("?");
+        println!("Partita {}/{}: {} - {}", number + 1, games.len(), white, black);
+        for line in review.summary() {
+            println!("  {}", line);
+        }
+        // The start position is written again by `write_game` when needed
+        let tags: Vec<(&str, String)> = game
+            .tags
+            .iter()
+            .filter(|(name, _)| name != "SetUp" && name != "FEN")
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        let result = if game.result.is_empty() { "*" } else { game.result.as_str() };
+        annotated.push_str(&review.to_pgn(&tags, &start, result));
+    }
+    fs::write(output, annotated).map_err(|err| format!("Impossibile scrivere {}: {}", output, err))?;
+    println!("Partite annotate scritte in {}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hanging_the_queen_is_a_blunder_with_the_better_line() {
+        // 1. Qd3?? Qxd3 instead of 1. Qxd5
+        let start = Board::from_fen("4k3/8/8/3q4/8/8/3Q4/4K3 w - - 0 1").unwrap();
+        let mut board = start.clone();
+        let moves: Vec<Move> = ["d2d3", "d5d3"]
+            .iter()
+            .map(|text| {
+                let mv = board.parse_uci_move(text).unwrap();
+                board.apply_move(mv);
+                mv
+            })
+            .collect();
+        let limits = SearchLimits { depth: Some(4), ..Default::default() };
+        let review = review_game(&start, &moves, &limits, &AtomicUsize::new(0));
+
+        let blunder = &review.moves[0];
+        assert_eq!(blunder.judgement, Some(Judgement::Blunder));
+        assert_eq!(blunder.judgement.unwrap().symbol(), "??");
+        assert_eq!(start.to_san(blunder.best_line[0]), "Qxd5");
+        // Black's reply is the refutation and loses nothing
+        assert_eq!(review.moves[1].best_line.first(), Some(&moves[1]));
+        assert_eq!(review.moves[1].judgement, None);
+        assert_eq!(review.judgements, [[0, 0, 1], [0, 0, 0]]);
+
+        let pgn = review.to_pgn(&[], &start, "0-1");
+        assert!(pgn.contains("Qd3 $4"), "{}", pgn);
+        assert!(pgn.contains("(1. Qxd5"), "{}", pgn);
+    }
+}
@@ -0,0 +1,177 @@
+//! Chess board widget painted with `egui::Painter`: fills the space it is given, shows coordinates and
+//! draws the pieces as glyphs with layered overlays. It only knows FEN letters and squares, so any
+//! egui app can embed it
+
+use eframe::egui::{self, Align2, Color32, FontId, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2};
+
+/// A square as (row, col): row 0 = rank 8, col 0 = file a
+pub type Square = (usize, usize);
+
+const PIECE_LETTERS: &str = "PNBRQKpnbrqk";
+/// Glyphs of the pieces, in `PIECE_LETTERS` order
+const PIECE_GLYPHS: [&str; 12] = ["♙", "♘", "♗", "♖", "♕", "♔", "♟", "♞", "♝", "♜", "♛", "♚"];
+const LIGHT_SQUARE: Color32 = Color32::from_rgb(240, 217, 181);
+const DARK_SQUARE: Color32 = Color32::from_rgb(181, 136, 99);
+const GLYPH_COLOR: Color32 = Color32::from_gray(20);
+/// The board never gets smaller than this (points)
+const MIN_SIZE: f32 = 160.0;
+
+/// Something drawn on the board. Highlights lie under the pieces, the rest above them; overlays of
+/// the same kind are painted in the order given
+#[derive(Copy, Clone, Debug)]
+pub enum Overlay {
+    Highlight(Square, Color32),       // Fills the square
+    Circle(Square, Color32),          // Ring along the edge of the square
+    Dot(Square, Color32),             // Small disc in the middle (e.g. legal targets)
+    Arrow(Square, Square, Color32),   // From the centre of a square to another
+}
+
+/// What the user did with the board this frame
+pub struct BoardResponse {
+    pub clicked: Option<Square>,       // Pressed and released without moving
+    pub drag_started: Option<Square>,  // Square where a drag began
+    pub drag_released: bool,
+    pub hovered: Option<Square>,       // Square under the pointer (the drop target while dragging)
+}
+
+/// The board for one frame: build it, then call `show`
+pub struct BoardWidget<'a> {
+    pieces: [[Option<char>; 8]; 8],  // FEN letters
+    overlays: &'a [Overlay],
+    flipped: bool,                   // Black at the bottom
+    coordinates: bool,
+    dragging: Option<Square>,        // This piece follows the pointer instead of its square
+}
+
+impl<'a> BoardWidget<'a> {
+    pub fn new(pieces: [[Option<char>; 8]; 8]) -> Self {
+        BoardWidget { pieces, overlays: &[], flipped: false, coordinates: true, dragging: None }
+    }
+
+    pub fn overlays(mut self, overlays: &'a [Overlay]) -> Self {
+        self.overlays = overlays;
+        self
+    }
+
+    pub fn flipped(mut self, flipped: bool) -> Self {
+        self.flipped = flipped;
+        self
+    }
+
+    pub fn coordinates(mut self, coordinates: bool) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+
+    pub fn dragging(mut self, square: Option<Square>) -> Self {
+        self.dragging = square;
+        self
+    }
+
+    /// Screen rectangle of a square
+    fn square_rect(&self, board: Rect, (row, col): Square) -> Rect {
+        let size = board.width() / 8.0;
+        let (row, col) = if self.flipped { (7 - row, 7 - col) } else { (row, col) };
+        Rect::from_min_size(board.min + Vec2::new(col as f32, row as f32) * size, Vec2::splat(size))
+    }
+
+    /// Square under a screen position
+    fn square_at(&self, board: Rect, pos: Pos2) -> Option<Square> {
+        if !board.contains(pos) {
+            return None;
+        }
+        let size = board.width() / 8.0;
+        let row = (((pos.y - board.min.y) / size) as usize).min(7);
+        let col = (((pos.x - board.min.x) / size) as usize).min(7);
+        Some(if self.flipped { (7 - row, 7 - col) } else { (row, col) })
+    }
+
+    fn paint_piece(&self, painter: &egui::Painter, rect: Rect, letter: char) {
+        let Some(index) = PIECE_LETTERS.find(letter) else { return };
+        painter.text(rect.center(), Align2::CENTER_CENTER, PIECE_GLYPHS[index], FontId::proportional(rect.height() * 0.75), GLYPH_COLOR);
+    }
+
+    /// Draws the board as large as the space left in `ui` allows
+    pub fn show(self, ui: &mut Ui) -> BoardResponse {
+        let available = ui.available_size();
+        let side = available.x.min(available.y).max(MIN_SIZE);
+        let (board, response) = ui.allocate_exact_size(Vec2::splat(side), Sense::click_and_drag());
+        let painter = ui.painter_at(board);
+        let square = side / 8.0;
+        let squares = (0..8).flat_map(|row| (0..8).map(move |col| (row, col)));
+
+        for (row, col) in squares.clone() {
+            let color = if (row + col) % 2 == 0 { LIGHT_SQUARE } else { DARK_SQUARE };
+            painter.rect_filled(self.square_rect(board, (row, col)), 0.0, color);
+        }
+        for overlay in self.overlays {
+            if let Overlay::Highlight(at, color) = *overlay {
+                painter.rect_filled(self.square_rect(board, at), 0.0, color);
+            }
+        }
+
+        // Rank numbers in the left column, file letters along the bottom, in the other square colour
+        if self.coordinates {
+            let font = FontId::proportional(square * 0.2);
+            for index in 0..8 {
+                let (row, col) = if self.flipped { (7 - index, 7) } else { (index, 0) };
+                let rect = self.square_rect(board, (row, col)).shrink(square * 0.05);
+                let color = if (row + col) % 2 == 0 { DARK_SQUARE } else { LIGHT_SQUARE };
+                painter.text(rect.left_top(), Align2::LEFT_TOP, (8 - row).to_string(), font.clone(), color);
+
+                let (row, col) = if self.flipped { (0, 7 - index) } else { (7, index) };
+                let rect = self.square_rect(board, (row, col)).shrink(square * 0.05);
+                let color = if (row + col) % 2 == 0 { DARK_SQUARE } else { LIGHT_SQUARE };
+                painter.text(rect.right_bottom(), Align2::RIGHT_BOTTOM, ((b'a' + col as u8) as char).to_string(), font.clone(), color);
+            }
+        }
+
+        for (row, col) in squares {
+            if let Some(letter) = self.pieces[row][col].filter(|_| self.dragging != Some((row, col))) {
+                self.paint_piece(&painter, self.square_rect(board, (row, col)), letter);
+            }
+        }
+
+        for overlay in self.overlays {
+            match *overlay {
+                Overlay::Circle(at, color) => {
+                    let rect = self.square_rect(board, at);
+                    painter.circle_stroke(rect.center(), square * 0.45, Stroke::new(square * 0.07, color));
+                },
+                Overlay::Dot(at, color) => {
+                    painter.circle_filled(self.square_rect(board, at).center(), square * 0.15, color);
+                },
+                _ => {},
+            }
+        }
+        for overlay in self.overlays {
+            if let Overlay::Arrow(from, to, color) = *overlay {
+                let (start, end) = (self.square_rect(board, from).center(), self.square_rect(board, to).center());
+                let direction = (end - start).normalized();
+                let head = square * 0.4;
+                let base = end - direction * head;
+                painter.line_segment([start, base], Stroke::new(square * 0.15, color));
+                let side = direction.rot90() * head * 0.6;
+                painter.add(Shape::convex_polygon(vec![end, base + side, base - side], color, Stroke::NONE));
+            }
+        }
+
+        // The dragged piece goes above everything else, even outside the board
+        let pointer = ui.ctx().pointer_interact_pos();
+        if let (Some((row, col)), Some(pos)) = (self.dragging, pointer) {
+            if let Some(letter) = self.pieces[row][col] {
+                ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+                let layer = egui::LayerId::new(egui::Order::Tooltip, response.id.with("dragged_piece"));
+                self.paint_piece(&ui.ctx().layer_painter(layer), Rect::from_center_size(pos, Vec2::splat(square)), letter);
+            }
+        }
+
+        let press_origin = ui.input(|input| input.pointer.press_origin());
+        BoardResponse {
+            clicked: response.interact_pointer_pos().filter(|_| response.clicked()).and_then(|pos| self.square_at(board, pos)),
+            drag_started: press_origin.filter(|_| response.drag_started()).and_then(|pos| self.square_at(board, pos)),
+            drag_released: response.drag_released(),
+            hovered: pointer.and_then(|pos| self.square_at(board, pos)),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Builds Polyglot books from PGN collections: `gk_chess_engine makebook`
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::book::{encode_move, polyglot_key, BookEntry, OpeningBook};
+use crate::pgn::{parse_pgn, PgnGame};
+use crate::Board;
+
+/// Which games and moves make it into the book
+#[derive(Copy, Clone, Debug)]
+pub struct BuildOptions {
+    pub min_games: u32,  // A move must have been played at least this often
+    pub min_elo: u32,    // Moves of players rated below this are ignored (0 = no filter)
+    pub max_ply: u32,    // Only the first plies of each game are used
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions { min_games: 3, min_elo: 0, max_ply: 40 }
+    }
+}
+
+/// How often a move was played in a position and how it scored for the mover
+#[derive(Copy, Clone, Default, Debug)]
+struct MoveStats {
+    games: u32,
+    points: u32,  // Half points: 2 per win, 1 per draw
+}
+
+/// Accumulates move statistics over many games
+pub struct BookBuilder {
+    options: BuildOptions,
+    stats: HashMap<(u64, u16), MoveStats>,  // (Polyglot key, Polyglot move)
+    pub games_read: usize,
+    pub games_used: usize,
+}
+
+impl BookBuilder {
+    pub fn new(options: BuildOptions) -> Self {
+        BookBuilder { options, stats: HashMap::new(), games_read: 0, games_used: 0 }
+    }
+
+    /// Replays a game and counts its moves; games without a result are skipped
+    pub fn add_game(&mut self, game: &PgnGame) {
+        self.games_read += 1;
+        // Half points for White and Black
+        let points = match game.result.as_str() {
+            "1-0" => [2, 0],
+            "0-1" => [0, 2],
+            "1/2-1/2" => [1, 1],
+            _ => return,
+        };
+        let mut board = match game.tag("FEN") {
+            Some(fen) => match Board::from_fen(fen) {
+                Ok(board) => board,
+                Err(_) => return,
+            },
+            None => Board::new(),
+        };
+        let elo = |tag: &str| game.tag(tag).and_then(|value| value.parse::<u32>().ok()).unwrap_or(0);
+        let ratings = [elo("WhiteElo"), elo("BlackElo")];
+
+        self.games_used += 1;
+        for san in game.moves.iter().take(self.options.max_ply as usize) {
+            // Moves after an unreadable one cannot be replayed
+            let Some(mv) = board.parse_san(san) else { break };
+            let side = usize::from(!board.white_to_move);
+            if ratings[side] >= self.options.min_elo {
+                let stats = self.stats.entry((polyglot_key(&board), encode_move(&board, mv))).or_default();
+                stats.games += 1;
+                stats.points += points[side];
+            }
+            board.apply_move(mv);
+        }
+    }
+
+    /// Number of distinct positions seen so far
+    pub fn positions(&self) -> usize {
+        self.stats.keys().map(|(key, _)| key).collect::<HashSet<_>>().len()
+    }
+
+    /// Keeps the moves played often enough, weighted by their score; moves that never
+    /// scored a point are left out
+    pub fn build(&self) -> OpeningBook {
+        let selected: Vec<(u64, u16, u32)> = self
+            .stats
+            .iter()
+            .filter(|(_, stats)| stats.games >= self.options.min_games && stats.points > 0)
+            .map(|(&(key, raw_move), stats)| (key, raw_move, stats.points))
+            .collect();
+
+        // Weights are 16 bit: scale everything down if the most played move does not fit
+        let max_points = selected.iter().map(|&(_, _, points)| points).max().unwrap_or(0);
+        let scale = |points: u32| {
+            if max_points <= u16::MAX as u32 {
+                points as u16
+            } else {
+                ((points as u64 * u16::MAX as u64 / max_points as u64) as u16).max(1)
+            }
+        };
+        let entries = selected
+            .into_iter()
+            .map(|(key, raw_move, points)| BookEntry { key, raw_move, weight: scale(points), learn: 0 })
+            .collect();
+        OpeningBook::from_entries(entries)
+    }
+}
+
+/// `makebook <book.bin> <games.pgn>... [--min-games N] [--min-elo N] [--max-ply N]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut options = BuildOptions::default();
+    let mut paths = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let target = match args[i].as_str() {
+            "--min-games" => &mut options.min_games,
+            "--min-elo" => &mut options.min_elo,
+            "--max-ply" => &mut options.max_ply,
+            path => {
+                paths.push(path);
+                i += 1;
+                continue;
+            },
+        };
+        *target = args
+            .get(i + 1)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("Valore mancante o non valido per {}", args[i]))?;
+        i += 2;
+    }
+    let Some((output, inputs)) = paths.split_first().filter(|(_, inputs)| !inputs.is_empty()) else {
+        return Err("Uso: gk_chess_engine makebook <libro.bin> <partite.pgn>... [--min-games N] [--min-elo N] [--max-ply N]".to_string());
+    };
+
+    let mut builder = BookBuilder::new(options);
+    for path in inputs {
+        let data = fs::read(path).map_err(|err| format!("Impossibile leggere {}: {}", path, err))?;
+        for game in parse_pgn(&String::from_utf8_lossy(&data)) {
+            builder.add_game(&game);
+        }
+    }
+    let book = builder.build();
+    book.save(output)?;
+    println!(
+        "Partite lette: {} (usate {}), posizioni: {}, voci scritte in {}: {}",
+        builder.games_read,
+        builder.games_used,
+        builder.positions(),
+        output,
+        book.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: &str = r#"[WhiteElo "2500"]
+[BlackElo "2400"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+
+[WhiteElo "2500"]
+[BlackElo "2400"]
+
+1. e4 e5 2. Nf3 Nc6 1/2-1/2
+
+[WhiteElo "2500"]
+[BlackElo "2400"]
+
+1. e4 c5 0-1
+
+[WhiteElo "1500"]
+[BlackElo "1500"]
+
+1. d4 d5 1-0
+
+[WhiteElo "1500"]
+[BlackElo "1500"]
+
+1. d4 d5 1-0
+
+[Event "Senza risultato"]
+
+1. e4 e5 *
+"#;
+
+    /// Book moves (UCI) and weights after the given SAN moves
+    fn probe(book: &OpeningBook, moves: &[&str]) -> Vec<(String, u16)> {
+        let mut board = Board::new();
+        for text in moves {
+            let mv = board.parse_san(text).unwrap();
+            board.apply_move(mv);
+        }
+        book.probe(&board).iter().map(|book_move| (book_move.mv.to_uci(), book_move.weight)).collect()
+    }
+
+    #[test]
+    fn filters_and_weights() {
+        let mut builder = BookBuilder::new(BuildOptions { min_games: 2, min_elo: 2000, max_ply: 3 });
+        for game in parse_pgn(GAMES) {
+            builder.add_game(&game);
+        }
+        assert_eq!((builder.games_read, builder.games_used), (6, 5));
+
+        let book = builder.build();
+        // 1. e4: two wins' worth of half points plus a draw; 1. d4 comes from players below min_elo
+        assert_eq!(probe(&book, &[]), [("e2e4".to_string(), 3)]);
+        // 1... e5 scored a draw in two games; 1... c5 was played once only
+        assert_eq!(probe(&book, &["e4"]), [("e7e5".to_string(), 1)]);
+        assert_eq!(probe(&book, &["e4", "e5"]), [("g1f3".to_string(), 3)]);
+        // 2... Nc6 is the fourth ply, beyond max_ply
+        assert!(probe(&book, &["e4", "e5", "Nf3"]).is_empty());
+        assert_eq!(builder.positions(), 3);
+    }
+}
@@ -0,0 +1,105 @@
+//! "Opening book" panel of the GUI: loads a Polyglot book, shows its moves and feeds the engine
+
+use eframe::egui;
+
+use crate::book::{game_ply, BookStrategy, OpeningBook, DEFAULT_BOOK_DEPTH};
+use crate::movegen::Move;
+use crate::rng::Rng;
+use crate::Board;
+
+pub struct BookPanel {
+    path: String,               // Contents of the file field
+    book: Option<OpeningBook>,
+    strategy: BookStrategy,
+    depth: u32,                 // No book moves after this many plies
+    engine_uses_book: bool,     // The engine plays book moves instantly while in book
+    message: String,            // Outcome of the last load
+    rng: Rng,
+}
+
+impl Default for BookPanel {
+    fn default() -> Self {
+        BookPanel {
+            path: String::new(),
+            book: None,
+            strategy: BookStrategy::Weighted,
+            depth: DEFAULT_BOOK_DEPTH,
+            engine_uses_book: true,
+            message: String::new(),
+            rng: Rng::new(Rng::time_seed()),
+        }
+    }
+}
+
+impl BookPanel {
+    /// Book move for the engine, if a book is loaded and the engine may use it
+    pub fn engine_move(&mut self, board: &Board) -> Option<Move> {
+        let book = self.book.as_ref().filter(|_| self.engine_uses_book)?;
+        book.choose(board, self.strategy, self.depth, &mut self.rng)
+    }
+
+    fn load(&mut self) {
+        match OpeningBook::open(self.path.trim()) {
+            Ok(book) => {
+                self.message = format!("Libro caricato: {} voci", book.len());
+                self.book = Some(book);
+            },
+            Err(err) => {
+                self.message = err;
+                self.book = None;
+            },
+        }
+    }
+
+    /// Draws the panel; returns the book move the user clicked, if `can_play` allows it
+    pub fn ui(&mut self, ui: &mut egui::Ui, board: &Board, can_play: bool) -> Option<Move> {
+        ui.heading("Libro di aperture");
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.text_edit_singleline(&mut self.path);
+            if ui.button("Carica").clicked() {
+                self.load();
+            }
+        });
+        if !self.message.is_empty() {
+            ui.label(&self.message);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Scelta:");
+            ui.radio_value(&mut self.strategy, BookStrategy::Weighted, "Casuale pesata");
+            ui.radio_value(&mut self.strategy, BookStrategy::Best, "Migliore");
+        });
+        ui.add(egui::Slider::new(&mut self.depth, 0..=60).text("profondità massima (semimosse)"));
+        ui.checkbox(&mut self.engine_uses_book, "Il motore usa il libro");
+
+        ui.separator();
+        let book = self.book.as_ref()?;
+        let moves = book.probe(board);
+        if moves.is_empty() {
+            ui.label("Posizione fuori dal libro");
+            return None;
+        }
+        if game_ply(board) >= self.depth {
+            ui.label("Oltre la profondità massima: il motore non userà il libro");
+        }
+
+        let total: u32 = moves.iter().map(|book_move| book_move.weight as u32).sum();
+        let mut played = None;
+        egui::Grid::new("book_moves").striped(true).show(ui, |ui| {
+            ui.label("Mossa");
+            ui.label("Peso");
+            ui.label("%");
+            ui.end_row();
+            for book_move in &moves {
+                if ui.add_enabled(can_play, egui::Button::new(book_move.mv.to_uci())).clicked() {
+                    played = Some(book_move.mv);
+                }
+                ui.label(book_move.weight.to_string());
+                ui.label(format!("{:.1}", 100.0 * book_move.weight as f32 / total.max(1) as f32));
+                ui.end_row();
+            }
+        });
+        played
+    }
+}
@@ -0,0 +1,238 @@
+//! Training data from self-play: `gk_chess_engine datagen`
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::book::{BookStrategy, OpeningBook, DEFAULT_BOOK_DEPTH};
+use crate::rng::Rng;
+use crate::search::{self, EndgameTables, SearchControl, SearchLimits, MATE_BOUND};
+use crate::selfplay::{game_result, GameResult, MAX_GAME_PLIES};
+use crate::tt::TranspositionTable;
+use crate::Board;
+
+/// Games are adjudicated as won once both sides agree on a score this large, for the same side, for
+/// `ADJUDICATION_PLIES` plies in a row
+const ADJUDICATION_SCORE: i32 = 1500;
+const ADJUDICATION_PLIES: i32 = 8;
+
+/// Settings of a `datagen` run
+struct DatagenOptions {
+    games: usize,
+    threads: usize,
+    seed: u64,
+    limits: SearchLimits,        // Fixed nodes or fixed depth per move
+    random_plies: usize,         // Random moves played after the book moves
+    book: Option<OpeningBook>,
+}
+
+/// Starting position of a game: book moves first (if any), then random legal moves
+fn opening(options: &DatagenOptions, rng: &mut Rng) -> Board {
+    let mut board = Board::new();
+    if let Some(book) = &options.book {
+        // The depth limit also ends books whose lines come back to an earlier position
+        while let Some(mv) = book.choose(&board, BookStrategy::Weighted, DEFAULT_BOOK_DEPTH, rng) {
+            board.apply_move(mv);
+        }
+    }
+    for _ in 0..options.random_plies {
+        let moves = board.legal_moves();
+        if moves.is_empty() {
+            break;
+        }
+        board.apply_move(moves[rng.below(moves.len() as u64) as usize]);
+    }
+    board
+}
+
+/// Plies in a row with a decisive score, positive while White is winning and negative while Black
+/// is; a score below the threshold or for the other side starts over
+fn decisive_streak(streak: i32, white_score: i32) -> i32 {
+    if white_score >= ADJUDICATION_SCORE {
+        streak.max(0) + 1
+    } else if white_score <= -ADJUDICATION_SCORE {
+        streak.min(0) - 1
+    } else {
+        0
+    }
+}
+
+/// Plays game `index` and returns its records: `<FEN> [<result>] <score>`, the result from White's
+/// point of view (1.0, 0.5, 0.0) and the search score in centipawns for White
+fn play(options: &DatagenOptions, index: usize) -> Vec<String> {
+    let mut rng = Rng::new(options.seed ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    let mut board = opening(options, &mut rng);
+    let mut tt = TranspositionTable::new(4);
+    let control = SearchControl::default();
+    let mut positions = Vec::new();
+    let mut decisive_plies = 0;
+    let mut result = None;
+
+    for _ in 0..MAX_GAME_PLIES {
+        result = game_result(&board);
+        if result.is_some() {
+            break;
+        }
+        let outcome = search::search(&board, &options.limits, &mut tt, EndgameTables::default(), None, &control, &mut |_| {});
+        let Some(mv) = outcome.best_move else { break };
+        let white_score = if board.white_to_move { outcome.score } else { -outcome.score };
+
+        // Only quiet positions are useful labels: no check, no tactics pending, no mate found
+        let quiet = !board.in_check() && board.squares[mv.to_row][mv.to_col].is_empty() && mv.promotion.is_none();
+        let en_passant = board.game_state.en_passant_target == Some((mv.to_row, mv.to_col));
+        if quiet && !en_passant && outcome.score.abs() < MATE_BOUND {
+            positions.push((board.to_fen(), white_score));
+        }
+
+        decisive_plies = decisive_streak(decisive_plies, white_score);
+        if decisive_plies.abs() >= ADJUDICATION_PLIES {
+            result = Some(if decisive_plies > 0 { GameResult::WhiteWins } else { GameResult::BlackWins });
+            break;
+        }
+        board.apply_move(mv);
+    }
+
+    let label = match result.unwrap_or(GameResult::Draw) {
+        GameResult::WhiteWins => "1.0",
+        GameResult::Draw => "0.5",
+        GameResult::BlackWins => "0.0",
+    };
+    positions.into_iter().map(|(fen, score)| format!("{} [{}] {}", fen, label, score)).collect()
+}
+
+/// Finished games waiting for the ones before them, so that records are written in game order
+struct OrderedOutput<'a, W> {
+    output: &'a mut W,
+    pending: BTreeMap<usize, Vec<String>>,
+    next: usize,       // Next game to write
+    written: usize,    // Records written so far
+}
+
+impl<W: Write> OrderedOutput<'_, W> {
+    fn add(&mut self, game: usize, records: Vec<String>, games: usize) -> Result<(), String> {
+        self.pending.insert(game, records);
+        while let Some(records) = self.pending.remove(&self.next) {
+            for record in &records {
+                writeln!(self.output, "{}", record).map_err(|err| format!("Errore di scrittura: {}", err))?;
+            }
+            self.written += records.len();
+            self.next += 1;
+            if self.next.is_multiple_of(100) || self.next == games {
+                println!("Partite: {}/{}, posizioni: {}", self.next, games, self.written);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plays every game on `threads` threads; the same seed gives the same file whatever the number of
+/// threads. Returns the number of records written
+fn generate<W: Write + Send>(options: &DatagenOptions, output: &mut W) -> Result<usize, String> {
+    let next_game = AtomicUsize::new(0);
+    let ordered = Mutex::new(OrderedOutput { output, pending: BTreeMap::new(), next: 0, written: 0 });
+    let error = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..options.threads.max(1) {
+            scope.spawn(|| loop {
+                let game = next_game.fetch_add(1, Ordering::SeqCst);
+                if game >= options.games {
+                    break;
+                }
+                let records = play(options, game);
+                if let Err(err) = ordered.lock().unwrap().add(game, records, options.games) {
+                    // Let every thread run out of games quickly
+                    next_game.store(options.games, Ordering::SeqCst);
+                    *error.lock().unwrap() = Some(err);
+                }
+            });
+        }
+    });
+    match error.into_inner().unwrap() {
+        Some(err) => Err(err),
+        None => Ok(ordered.into_inner().unwrap().written),
+    }
+}
+
+/// `datagen <output.txt> [--games N] [--threads N] [--seed N] [--nodes N | --depth N] [--random-plies N] [--book book.bin]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "Uso: gk_chess_engine datagen <uscita.txt> [--games N] [--threads N] [--seed N] [--nodes N | --depth N] [--random-plies N] [--book libro.bin]";
+    let mut options = DatagenOptions {
+        games: 100,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        seed: 1,
+        limits: SearchLimits { nodes: Some(5000), ..Default::default() },
+        random_plies: 8,
+        book: None,
+    };
+    let mut path = None;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).ok_or_else(|| format!("Valore mancante per {}", args[i]));
+        let number = || value.clone()?.parse::<u64>().map_err(|_| format!("Valore non valido per {}", args[i]));
+        match args[i].as_str() {
+            "--games" => options.games = number()? as usize,
+            "--threads" => options.threads = number()? as usize,
+            "--seed" => options.seed = number()?,
+            "--nodes" => options.limits = SearchLimits { nodes: Some(number()?), ..Default::default() },
+            "--depth" => options.limits = SearchLimits { depth: Some(number()? as u32), ..Default::default() },
+            "--random-plies" => options.random_plies = number()? as usize,
+            "--book" => options.book = Some(OpeningBook::open(value?)?),
+            other => {
+                path = Some(other);
+                i += 1;
+                continue;
+            },
+        }
+        i += 2;
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let file = File::create(path).map_err(|err| format!("Impossibile creare {}: {}", path, err))?;
+    let mut output = BufWriter::new(file);
+    let positions = generate(&options, &mut output)?;
+    output.flush().map_err(|err| format!("Errore di scrittura: {}", err))?;
+    println!("Partite giocate: {}, posizioni scritte in {}: {}", options.games, path, positions);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjudication_needs_the_same_winner_throughout() {
+        let streak = |scores: &[i32]| scores.iter().fold(0, |streak, &score| decisive_streak(streak, score));
+        assert_eq!(streak(&[1500; 8]), 8);
+        assert_eq!(streak(&[-1600; 8]), -8);
+        // A swing to the other side or a calmer score starts the count again
+        assert_eq!(streak(&[1500, -1500, 1500, -1500, 1500, -1500, 1500, -1500]), -1);
+        assert_eq!(streak(&[1500, 1500, 1500, -2000, -2000]), -2);
+        assert_eq!(streak(&[1500, 1500, 1499, 1500]), 1);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_file() {
+        let options = |threads: usize| DatagenOptions {
+            games: 3,
+            threads,
+            seed: 7,
+            limits: SearchLimits { depth: Some(1), ..Default::default() },
+            random_plies: 8,
+            book: None,
+        };
+        let run = |threads: usize| {
+            let mut output = Vec::new();
+            let written = generate(&options(threads), &mut output).unwrap();
+            (written, output)
+        };
+        let (written, first) = run(1);
+        assert!(written > 0);
+        assert_eq!(first, run(1).1);
+        assert_eq!(first, run(2).1, "il numero di thread non cambia il file");
+        assert_eq!(String::from_utf8(first).unwrap().lines().count(), written);
+    }
+}
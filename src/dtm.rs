@@ -0,0 +1,573 @@
+//! Distance-to-mate tables built by `gk_chess_engine gentb`: indexing, file format and probing
+//!
+//! Squares are numbered `rank * 8 + file` with a1 = 0, as in the KPK bitbase. A table is named
+//! after its material with White first ("KQvKR") and stores one byte per position and side to
+//! move; positions where Black owns the first material are probed with the colours reversed.
+//! En passant captures and castling are not part of the tables.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::eval::PIECE_VALUES;
+use crate::kpk::distance;
+use crate::search::MATE_SCORE;
+use crate::syzygy::RootProbe;
+use crate::{Board, GameState, Piece};
+
+/// Largest material (kings included) the generator accepts
+pub const MAX_PIECES: usize = 5;
+
+/// File extension of a table
+pub const EXTENSION: &str = "gktb";
+
+const MAGIC: &[u8; 4] = b"GKTB";
+const VERSION: u8 = 1;
+
+/// Stored byte of a drawn position; during generation it also means "not decided yet"
+pub const DRAW: u8 = 0;
+/// Stored byte of an index that is not a legal, canonical position
+pub const ILLEGAL: u8 = 255;
+/// Longest distance to mate a byte can hold, in plies (other bytes are plies + 1)
+pub const MAX_PLIES: u32 = 253;
+
+/// Piece letters by type index (see `Piece::type_index`)
+const LETTERS: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+/// Order of the pieces inside a side: "KQRBNP"
+const ORDER: [usize; 6] = [5, 4, 3, 2, 1, 0];
+
+/// Outcome with perfect play, from the side to move's point of view
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Dtm {
+    Draw,
+    Win(u32),   // Mates in this many plies (always odd)
+    Loss(u32),  // Gets mated in this many plies (always even, 0 = checkmated)
+}
+
+impl Dtm {
+    pub fn from_byte(value: u8) -> Option<Dtm> {
+        match value {
+            DRAW => Some(Dtm::Draw),
+            ILLEGAL => None,
+            value => {
+                let plies = value as u32 - 1;
+                Some(if plies % 2 == 1 { Dtm::Win(plies) } else { Dtm::Loss(plies) })
+            },
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Dtm::Draw => DRAW,
+            Dtm::Win(plies) | Dtm::Loss(plies) => (plies + 1) as u8,
+        }
+    }
+
+    /// The same outcome one ply earlier, for the side that has just moved
+    pub fn parent(self) -> Dtm {
+        match self {
+            Dtm::Draw => Dtm::Draw,
+            Dtm::Win(plies) => Dtm::Loss(plies + 1),
+            Dtm::Loss(plies) => Dtm::Win(plies + 1),
+        }
+    }
+
+    /// Search score of the outcome `ply` plies from the root, on the search's mate scale
+    pub fn score(self, ply: usize) -> i32 {
+        match self {
+            Dtm::Draw => 0,
+            Dtm::Win(plies) => MATE_SCORE - (ply as i32 + plies as i32),
+            Dtm::Loss(plies) => -MATE_SCORE + ply as i32 + plies as i32,
+        }
+    }
+}
+
+fn file_of(square: usize) -> usize {
+    square % 8
+}
+
+fn rank_of(square: usize) -> usize {
+    square / 8
+}
+
+/// Applies a board symmetry: bit 1 mirrors the files, bit 2 the ranks, bit 4 swaps them (a1-h8 diagonal)
+fn transform(square: usize, symmetry: u8) -> usize {
+    let (mut file, mut rank) = (file_of(square), rank_of(square));
+    if symmetry & 1 != 0 {
+        file = 7 - file;
+    }
+    if symmetry & 2 != 0 {
+        rank = 7 - rank;
+    }
+    if symmetry & 4 != 0 {
+        std::mem::swap(&mut file, &mut rank);
+    }
+    rank * 8 + file
+}
+
+/// Legal placements of the two kings after symmetry reduction
+struct KingPairs {
+    index: Vec<u16>,               // [white king * 64 + black king], u16::MAX = not a reduced placement
+    pairs: Vec<(usize, usize)>,    // (white king, black king) by index
+}
+
+impl KingPairs {
+    /// Without pawns the white king is kept in the a1-d1-d4 triangle (and the black king on or
+    /// below the diagonal when the white king is on it); with pawns only on files a-d
+    fn new(pawns: bool) -> KingPairs {
+        let mut index = vec![u16::MAX; 64 * 64];
+        let mut pairs = Vec::new();
+        for white_king in 0..64 {
+            for black_king in 0..64 {
+                let (file, rank) = (file_of(white_king), rank_of(white_king));
+                let reduced = if pawns {
+                    file <= 3
+                } else {
+                    file <= 3 && rank <= file && !(rank == file && rank_of(black_king) > file_of(black_king))
+                };
+                if reduced && distance(white_king, black_king) > 1 {
+                    index[white_king * 64 + black_king] = pairs.len() as u16;
+                    pairs.push((white_king, black_king));
+                }
+            }
+        }
+        KingPairs { index, pairs }
+    }
+}
+
+fn king_pairs(pawns: bool) -> &'static KingPairs {
+    static PAWNLESS: OnceLock<KingPairs> = OnceLock::new();
+    static WITH_PAWNS: OnceLock<KingPairs> = OnceLock::new();
+    if pawns { WITH_PAWNS.get_or_init(|| KingPairs::new(true)) } else { PAWNLESS.get_or_init(|| KingPairs::new(false)) }
+}
+
+fn type_of_letter(letter: char) -> Option<usize> {
+    LETTERS.iter().position(|&l| l == letter)
+}
+
+/// Letters of one side in "KQRBNP" order
+fn sorted_side(letters: impl Iterator<Item = char>) -> String {
+    let mut letters: Vec<char> = letters.collect();
+    letters.sort_by_key(|&letter| ORDER.iter().position(|&t| LETTERS[t] == letter));
+    letters.into_iter().collect()
+}
+
+/// Material value of one side, used to put the stronger side first
+fn side_value(side: &str) -> i32 {
+    side.chars().filter_map(type_of_letter).map(|t| PIECE_VALUES[t]).sum()
+}
+
+/// Material of a table, e.g. White "KQ" against Black "KR"
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Material {
+    pub white: String,
+    pub black: String,
+}
+
+impl Material {
+    /// Parses names such as "KQvKR" (case insensitive)
+    pub fn parse(name: &str) -> Result<Material, String> {
+        let upper = name.trim().to_uppercase();
+        let (white, black) = upper.split_once('V').ok_or_else(|| format!("Materiale non valido '{}': usare ad esempio KQvKR", name))?;
+        for side in [white, black] {
+            if side.chars().filter(|&c| c == 'K').count() != 1 || side.chars().any(|c| type_of_letter(c).is_none()) {
+                return Err(format!("Materiale non valido '{}': ogni lato deve avere un re e pezzi tra QRBNP", name));
+            }
+        }
+        let material = Material { white: sorted_side(white.chars()), black: sorted_side(black.chars()) };
+        if material.piece_count() > MAX_PIECES {
+            return Err(format!("Al massimo {} pezzi, re compresi", MAX_PIECES));
+        }
+        Ok(material)
+    }
+
+    /// Material on `board`
+    pub fn of(board: &Board) -> Material {
+        let side = |white: bool| {
+            sorted_side(board.squares.iter().flatten().filter(|piece| !piece.is_empty() && piece.is_color(white)).map(|piece| LETTERS[piece.type_index()]))
+        };
+        Material { white: side(true), black: side(false) }
+    }
+
+    pub fn name(&self) -> String {
+        format!("{}v{}", self.white, self.black)
+    }
+
+    /// The same material with the colours reversed
+    pub fn mirrored(&self) -> Material {
+        Material { white: self.black.clone(), black: self.white.clone() }
+    }
+
+    /// Stronger side first: the orientation in which tables are generated and stored
+    pub fn normalized(&self) -> Material {
+        if (side_value(&self.black), &self.black) > (side_value(&self.white), &self.white) {
+            self.mirrored()
+        } else {
+            self.clone()
+        }
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.white.len() + self.black.len()
+    }
+
+    pub fn has_pawns(&self) -> bool {
+        self.white.contains('P') || self.black.contains('P')
+    }
+
+    /// Pieces in table order: white king, black king, the other white pieces, the other black pieces
+    fn pieces(&self) -> Vec<Piece> {
+        let mut pieces = vec![Piece::KingWhite, Piece::KingBlack];
+        for (side, white) in [(&self.white, true), (&self.black, false)] {
+            pieces.extend(side.chars().skip(1).filter_map(type_of_letter).map(|t| Piece::from_type_index(t, white)));
+        }
+        pieces
+    }
+
+    /// Normalised materials reachable with one capture and/or promotion
+    pub fn children(&self) -> Vec<Material> {
+        let remove = |side: &str, at: usize| side.chars().enumerate().filter(|&(i, _)| i != at).map(|(_, c)| c).collect::<String>();
+        let mut children = Vec::new();
+        for (own, other, white) in [(&self.white, &self.black, true), (&self.black, &self.white, false)] {
+            let mut results: Vec<(String, String)> = Vec::new();
+            // Captures of any piece but the king
+            for at in 1..other.len() {
+                results.push((own.clone(), remove(other, at)));
+            }
+            // Promotions, with or without a capture
+            if let Some(pawn) = own.find('P') {
+                for letter in ['Q', 'R', 'B', 'N'] {
+                    let promoted = sorted_side(remove(own, pawn).chars().chain(std::iter::once(letter)));
+                    results.push((promoted.clone(), other.clone()));
+                    for at in 1..other.len() {
+                        results.push((promoted.clone(), remove(other, at)));
+                    }
+                }
+            }
+            for (own, other) in results {
+                let (white_side, black_side) = if white { (own, other) } else { (other, own) };
+                let child = Material { white: sorted_side(white_side.chars()), black: sorted_side(black_side.chars()) }.normalized();
+                if !children.contains(&child) {
+                    children.push(child);
+                }
+            }
+        }
+        children
+    }
+}
+
+/// One table: a byte per position for each side to move
+pub struct Table {
+    pub material: Material,
+    pieces: Vec<Piece>,
+    pub values: [Vec<u8>; 2],  // White to move, Black to move
+}
+
+impl Table {
+    /// Empty table with every position marked illegal
+    pub fn new(material: Material) -> Table {
+        let pieces = material.pieces();
+        let size = king_pairs(material.has_pawns()).pairs.len() * 64usize.pow(pieces.len() as u32 - 2);
+        Table { material, pieces, values: [vec![ILLEGAL; size], vec![ILLEGAL; size]] }
+    }
+
+    /// Positions per side to move
+    pub fn size(&self) -> usize {
+        self.values[0].len()
+    }
+
+    /// Index of the position with the pieces on `squares` (in table order), after symmetry reduction;
+    /// None if the kings touch
+    pub fn index(&self, squares: &[usize]) -> Option<usize> {
+        let (white_king, black_king) = (squares[0], squares[1]);
+        let mut symmetry = if file_of(white_king) > 3 { 1 } else { 0 };
+        let mut candidates = [symmetry, symmetry];
+        if !self.material.has_pawns() {
+            if rank_of(white_king) > 3 {
+                symmetry |= 2;
+            }
+            let king = transform(white_king, symmetry);
+            if rank_of(king) > file_of(king) {
+                symmetry |= 4;
+            }
+            candidates = [symmetry, symmetry];
+            // On the diagonal the black king decides; with both kings on it the smaller index wins
+            if rank_of(king) == file_of(king) {
+                let other = transform(black_king, symmetry);
+                if rank_of(other) > file_of(other) {
+                    candidates = [symmetry | 4, symmetry | 4];
+                } else if rank_of(other) == file_of(other) {
+                    candidates = [symmetry, symmetry | 4];
+                }
+            }
+        }
+        candidates.iter().filter_map(|&symmetry| self.index_with(squares, symmetry)).min()
+    }
+
+    fn index_with(&self, squares: &[usize], symmetry: u8) -> Option<usize> {
+        let mut mapped: Vec<usize> = squares.iter().map(|&square| transform(square, symmetry)).collect();
+        // Identical pieces are interchangeable: keep their squares sorted
+        let mut start = 2;
+        while start < mapped.len() {
+            let end = (start..mapped.len()).find(|&i| self.pieces[i] != self.pieces[start]).unwrap_or(mapped.len());
+            mapped[start..end].sort_unstable();
+            start = end;
+        }
+        let kings = king_pairs(self.material.has_pawns()).index[mapped[0] * 64 + mapped[1]];
+        if kings == u16::MAX {
+            return None;
+        }
+        Some(mapped[2..].iter().fold(kings as usize, |index, &square| index * 64 + square))
+    }
+
+    /// Piece squares (in table order) of a position index
+    pub fn squares(&self, mut index: usize) -> Vec<usize> {
+        let mut squares = vec![0; self.pieces.len()];
+        for square in squares[2..].iter_mut().rev() {
+            *square = index % 64;
+            index /= 64;
+        }
+        (squares[0], squares[1]) = king_pairs(self.material.has_pawns()).pairs[index];
+        squares
+    }
+
+    /// Piece squares of `board` in table order; `swapped` reads Black's pieces as White's
+    /// (ranks mirrored). None if the material does not match
+    pub fn board_squares(&self, board: &Board, swapped: bool) -> Option<Vec<usize>> {
+        let mut squares = vec![usize::MAX; self.pieces.len()];
+        for row in 0..8 {
+            for col in 0..8 {
+                let piece = board.squares[row][col];
+                if piece.is_empty() {
+                    continue;
+                }
+                let mut square = (7 - row) * 8 + col;
+                let mut piece_here = piece;
+                if swapped {
+                    square ^= 56;
+                    piece_here = Piece::from_type_index(piece.type_index(), !piece.is_white());
+                }
+                let slot = (0..self.pieces.len()).find(|&i| self.pieces[i] == piece_here && squares[i] == usize::MAX)?;
+                squares[slot] = square;
+            }
+        }
+        squares.iter().all(|&square| square != usize::MAX).then_some(squares)
+    }
+
+    /// Builds the position with the pieces on `squares`; None if two pieces share a square or a
+    /// pawn stands on the first or last rank
+    pub fn board(&self, squares: &[usize], white_to_move: bool) -> Option<Board> {
+        let mut board = Board {
+            squares: [[Piece::Empty; 8]; 8],
+            white_to_move,
+            game_state: GameState { white_king_moved: true, black_king_moved: true, ..Default::default() },
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            history: Vec::new(),
+        };
+        for (&piece, &square) in self.pieces.iter().zip(squares) {
+            let (row, col) = (7 - rank_of(square), file_of(square));
+            if !board.squares[row][col].is_empty() || (piece.type_index() == 0 && (row == 0 || row == 7)) {
+                return None;
+            }
+            board.squares[row][col] = piece;
+        }
+        board.hash = board.compute_hash();
+        Some(board)
+    }
+
+    /// Outcome of `board`, whose material must match the table (`swapped`: with the colours reversed)
+    pub fn probe(&self, board: &Board, swapped: bool) -> Option<Dtm> {
+        let index = self.index(&self.board_squares(board, swapped)?)?;
+        let side = usize::from(board.white_to_move == swapped);
+        Dtm::from_byte(self.values[side][index])
+    }
+
+    /// Writes the table; each side is run-length encoded as (byte, LEB128 run length) pairs
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let name = self.material.name();
+        let mut data = Vec::with_capacity(self.size() / 4);
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.push(name.len() as u8);
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&(self.size() as u32).to_le_bytes());
+        for values in &self.values {
+            for run in values.chunk_by(|a, b| a == b) {
+                data.push(run[0]);
+                let mut length = run.len();
+                while length >= 0x80 {
+                    data.push((length as u8 & 0x7f) | 0x80);
+                    length >>= 7;
+                }
+                data.push(length as u8);
+            }
+        }
+        fs::write(path, data).map_err(|err| format!("Impossibile scrivere {}: {}", path.display(), err))
+    }
+
+    /// Reads a table written by `save`
+    pub fn load(path: &Path) -> Result<Table, String> {
+        let data = fs::read(path).map_err(|err| format!("Impossibile leggere {}: {}", path.display(), err))?;
+        let invalid = || format!("{} non è una tablebase DTM valida", path.display());
+        if data.len() < 6 || &data[..4] != MAGIC || data[4] != VERSION {
+            return Err(invalid());
+        }
+        let name_end = 6 + data[5] as usize;
+        let name = data.get(6..name_end).and_then(|name| std::str::from_utf8(name).ok()).ok_or_else(invalid)?;
+        let mut table = Table::new(Material::parse(name)?);
+        let size = data.get(name_end..name_end + 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize);
+        if size != Some(table.size()) {
+            return Err(invalid());
+        }
+
+        let mut bytes = data[name_end + 4..].iter().copied();
+        for values in table.values.iter_mut() {
+            let mut filled = 0;
+            while filled < values.len() {
+                let value = bytes.next().ok_or_else(invalid)?;
+                let mut length = 0usize;
+                for shift in (0..).step_by(7) {
+                    let byte = bytes.next().ok_or_else(invalid)?;
+                    length |= ((byte & 0x7f) as usize) << shift;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+                let run = values.get_mut(filled..filled + length).ok_or_else(invalid)?;
+                run.fill(value);
+                filled += length;
+            }
+        }
+        Ok(table)
+    }
+}
+
+/// True if the side to move can capture en passant, which the tables do not know about
+fn can_capture_en_passant(board: &Board) -> bool {
+    let Some(target) = board.game_state.en_passant_target else { return false };
+    board.legal_moves().iter().any(|mv| (mv.to_row, mv.to_col) == target && board.squares[mv.from_row][mv.from_col].type_index() == 0)
+}
+
+/// Every loaded table, looked up by material
+#[derive(Default)]
+pub struct DtmTables {
+    tables: HashMap<String, Table>,
+    max_pieces: usize,
+}
+
+impl DtmTables {
+    /// Loads every table in `directory`; fails if there is none
+    pub fn open(directory: &str) -> Result<DtmTables, String> {
+        let entries = fs::read_dir(directory).map_err(|err| format!("Impossibile leggere le tablebase DTM in {}: {}", directory, err))?;
+        let mut tables = DtmTables::default();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == EXTENSION) {
+                tables.insert(Table::load(&path)?);
+            }
+        }
+        if tables.len() == 0 {
+            return Err(format!("Nessuna tablebase DTM trovata in {}", directory));
+        }
+        Ok(tables)
+    }
+
+    pub fn insert(&mut self, table: Table) {
+        self.max_pieces = self.max_pieces.max(table.material.piece_count());
+        self.tables.insert(table.material.name(), table);
+    }
+
+    pub fn get(&self, material: &Material) -> Option<&Table> {
+        self.tables.get(&material.name())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Exact outcome of `board`, if a table covers it
+    pub fn probe(&self, board: &Board) -> Option<Dtm> {
+        if board.piece_count() > self.max_pieces || board.game_state.castling_rights() != 0 || can_capture_en_passant(board) {
+            return None;
+        }
+        let material = Material::of(board);
+        match self.get(&material) {
+            Some(table) => table.probe(board, false),
+            None => self.get(&material.mirrored())?.probe(board, true),
+        }
+    }
+
+    /// The move that mates fastest (or resists longest, or keeps the draw) at the root
+    pub fn probe_root(&self, board: &Board) -> Option<RootProbe> {
+        self.probe(board)?;
+        let mut best: Option<RootProbe> = None;
+        for mv in board.legal_moves() {
+            let mut child = board.clone();
+            child.apply_move(mv);
+            let score = self.probe(&child)?.parent().score(0);
+            if best.is_none_or(|best| score > best.score) {
+                best = Some(RootProbe { best_move: mv, score });
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_is_the_same_for_every_symmetric_copy() {
+        let table = Table::new(Material::parse("KQvK").unwrap());
+        let mut both_on_diagonal = 0;
+        for white_king in 0..64 {
+            for black_king in (0..64).filter(|&square| distance(white_king, square) > 1) {
+                for queen in (0..64).filter(|&square| square != white_king && square != black_king) {
+                    let squares = [white_king, black_king, queen];
+                    let index = table.index(&squares);
+                    assert!(index.is_some(), "nessun indice per {:?}", squares);
+                    for symmetry in 1..8 {
+                        let copy: Vec<usize> = squares.iter().map(|&square| transform(square, symmetry)).collect();
+                        assert_eq!(table.index(&copy), index, "{:?} e la copia {:?} (simmetria {})", squares, copy, symmetry);
+                    }
+                    let diagonal = |square: usize| file_of(square) == rank_of(square) || file_of(square) + rank_of(square) == 7;
+                    if diagonal(white_king) && diagonal(black_king) {
+                        both_on_diagonal += 1;
+                    }
+                }
+            }
+        }
+        assert!(both_on_diagonal > 0);
+    }
+
+    #[test]
+    fn index_of_a_pawn_position_only_mirrors_the_files() {
+        let table = Table::new(Material::parse("KPvK").unwrap());
+        // Kings on e1 and e8, pawn on e2: the files may be mirrored, the ranks may not
+        assert_eq!(table.index(&[4, 60, 12]), table.index(&[3, 59, 11]));
+        assert_ne!(table.index(&[4, 60, 12]), table.index(&[60, 4, 52]));
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut table = Table::new(Material::parse("KRvKN").unwrap());
+        // Long runs, short runs and single bytes, so that run lengths of every size get written
+        for (index, value) in table.values[0].iter_mut().enumerate() {
+            *value = if index % 1000 < 700 { ILLEGAL } else { (index % 7) as u8 };
+        }
+        for (index, value) in table.values[1].iter_mut().enumerate() {
+            *value = (index / 300 % 200) as u8;
+        }
+        let path = std::env::temp_dir().join(format!("gk_dtm_test_{}.{}", std::process::id(), EXTENSION));
+        table.save(&path).unwrap();
+        let loaded = Table::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.material.name(), "KRvKN");
+        assert!(loaded.values == table.values, "valori diversi dopo save/load");
+    }
+}
@@ -0,0 +1,236 @@
+//! Specialised evaluation of known endgames, chosen by material signature (e.g. "KBN" vs "K")
+
+use crate::eval::PIECE_VALUES;
+use crate::kpk::{self, distance};
+use crate::Board;
+
+/// Score of an endgame that is won with correct technique (well below mate scores)
+pub const KNOWN_WIN: i32 = 10_000;
+
+/// Pieces of one side, with squares numbered from White's side (a1 = 0) like the KPK bitbase
+struct Side {
+    signature: String,   // "K" followed by the other pieces, strongest first: "KRP", "KBN"
+    king: usize,
+    pawns: Vec<usize>,
+    bishops: Vec<usize>,
+    material: i32,
+}
+
+impl Side {
+    fn collect(board: &Board, white: bool) -> Side {
+        let mut counts = [0; 6];
+        let mut side = Side { signature: String::new(), king: 0, pawns: Vec::new(), bishops: Vec::new(), material: 0 };
+        for row in 0..8 {
+            for col in 0..8 {
+                let piece = board.squares[row][col];
+                if piece.is_empty() || !piece.is_color(white) {
+                    continue;
+                }
+                let square = (7 - row) * 8 + col;
+                let type_index = piece.type_index();
+                counts[type_index] += 1;
+                side.material += PIECE_VALUES[type_index];
+                match type_index {
+                    0 => side.pawns.push(square),
+                    2 => side.bishops.push(square),
+                    5 => side.king = square,
+                    _ => {},
+                }
+            }
+        }
+        // Stockfish-style order: K, Q, R, B, N, P
+        for (type_index, letter) in [(5, 'K'), (4, 'Q'), (3, 'R'), (2, 'B'), (1, 'N'), (0, 'P')] {
+            side.signature.extend(std::iter::repeat_n(letter, counts[type_index]));
+        }
+        side
+    }
+}
+
+fn is_light_square(square: usize) -> bool {
+    (square % 8 + square / 8) % 2 == 1
+}
+
+/// Bonus for driving a king towards the edge of the board (0 in the centre, 90 in a corner)
+fn push_to_edge(square: usize) -> i32 {
+    let (file, rank) = ((square % 8) as i32, (square / 8) as i32);
+    15 * ((3 - file).max(file - 4) + (3 - rank).max(rank - 4))
+}
+
+/// Bonus for bringing the attacking king close to the defending one
+fn push_close(a: usize, b: usize) -> i32 {
+    140 - 20 * distance(a, b) as i32
+}
+
+/// Evaluates known endgames from the side to move's point of view; None hands over to the
+/// generic evaluation
+pub fn evaluate(board: &Board) -> Option<i32> {
+    // Cheap filter first: every handled endgame has a bare king or at most four pieces
+    let pieces = board.squares.iter().flatten().filter(|piece| !piece.is_empty());
+    let white_count = pieces.clone().filter(|piece| piece.is_white()).count();
+    let black_count = pieces.count() - white_count;
+    if white_count > 1 && black_count > 1 && white_count + black_count > 4 {
+        return None;
+    }
+
+    let white = Side::collect(board, true);
+    let black = Side::collect(board, false);
+    let white_is_strong = white.material >= black.material;
+    let (strong, weak) = if white_is_strong { (&white, &black) } else { (&black, &white) };
+    // Squares as seen from the strong side, so that its pawns always advance "up"
+    let flip = |square: usize| if white_is_strong { square } else { square ^ 56 };
+    let strong_to_move = board.white_to_move == white_is_strong;
+
+    let score = match (strong.signature.as_str(), weak.signature.as_str()) {
+        ("K", "K") | ("KN", "K") | ("KB", "K") | ("KNN", "K") => 0,
+        ("KB", "KB") | ("KB", "KN") | ("KN", "KN") => 0,
+        ("KP", "K") => kpk(strong, weak, strong_to_move, flip),
+        ("KBN", "K") => kbnk(strong, weak),
+        // Usually drawn: only a small edge for pushing the defending king
+        ("KR", "KB") | ("KR", "KN") => push_to_edge(weak.king) / 4,
+        (_, "K") if is_wrong_bishop_draw(strong, weak, flip) => 0,
+        // Bishops that all stand on one colour can never cover the corner's neighbours
+        ("KBB", "K") | ("KBBB", "K") if !has_mating_material(strong) => 0,
+        (_, "K") if has_mating_material(strong) => kxk(strong, weak),
+        _ => return None,
+    };
+    Some(if strong_to_move { score } else { -score })
+}
+
+/// Exact outcome of a king and pawn against king, from the side to move's point of view (1 = win,
+/// 0 = draw, -1 = loss); None for any other material
+pub fn kpk_outcome(board: &Board) -> Option<i32> {
+    let white = Side::collect(board, true);
+    let black = Side::collect(board, false);
+    let white_is_strong = match (white.signature.as_str(), black.signature.as_str()) {
+        ("KP", "K") => true,
+        ("K", "KP") => false,
+        _ => return None,
+    };
+    let (strong, weak) = if white_is_strong { (&white, &black) } else { (&black, &white) };
+    let flip = |square: usize| if white_is_strong { square } else { square ^ 56 };
+    let strong_to_move = board.white_to_move == white_is_strong;
+    let wins = kpk(strong, weak, strong_to_move, flip) != 0;
+    Some(match (wins, strong_to_move) {
+        (false, _) => 0,
+        (true, true) => 1,
+        (true, false) => -1,
+    })
+}
+
+/// Queen, rook, bishops of both colours or bishop and knight force mate against a bare king
+/// (exactly bishop and knight has its own evaluator)
+fn has_mating_material(side: &Side) -> bool {
+    let signature = &side.signature;
+    let bishop_colours = |light: bool| side.bishops.iter().any(|&square| is_light_square(square) == light);
+    signature.contains('Q')
+        || signature.contains('R')
+        || (bishop_colours(true) && bishop_colours(false))
+        || (signature.contains('B') && signature.contains('N'))
+}
+
+/// Lone king: drive it to the edge and bring our king closer
+fn kxk(strong: &Side, weak: &Side) -> i32 {
+    KNOWN_WIN + strong.material + push_to_edge(weak.king) + push_close(strong.king, weak.king)
+}
+
+/// Bishop and knight: mate is only possible in a corner of the bishop's colour
+fn kbnk(strong: &Side, weak: &Side) -> i32 {
+    let corners: [usize; 2] = if is_light_square(strong.bishops[0]) { [7, 56] } else { [0, 63] };
+    let corner_distance = corners
+        .iter()
+        .map(|&corner| (corner % 8).abs_diff(weak.king % 8) + (corner / 8).abs_diff(weak.king / 8))
+        .min()
+        .unwrap_or(0) as i32;
+    KNOWN_WIN + strong.material + 20 * (14 - corner_distance) + push_close(strong.king, weak.king)
+}
+
+/// King and pawn against king: exact win/draw from the bitbase
+fn kpk(strong: &Side, weak: &Side, strong_to_move: bool, flip: impl Fn(usize) -> usize) -> i32 {
+    let pawn = flip(strong.pawns[0]);
+    if kpk::probe(strong_to_move, flip(strong.king), pawn, flip(weak.king)) {
+        // Further advanced pawns are closer to queening
+        KNOWN_WIN + PIECE_VALUES[0] + 10 * (pawn / 8) as i32
+    } else {
+        0
+    }
+}
+
+/// Bishop and rook pawns whose queening square the bishop does not control: a draw when the
+/// defending king reaches the corner
+fn is_wrong_bishop_draw(strong: &Side, weak: &Side, flip: impl Fn(usize) -> usize) -> bool {
+    if strong.signature.trim_end_matches('P') != "KB" || strong.pawns.is_empty() {
+        return false;
+    }
+    let file = strong.pawns[0] % 8;
+    if (file != 0 && file != 7) || strong.pawns.iter().any(|&pawn| pawn % 8 != file) {
+        return false;
+    }
+    let queening_square = 56 + file;
+    let bishop_square = flip(strong.bishops[0]);
+    is_light_square(queening_square) != is_light_square(bishop_square) && distance(flip(weak.king), queening_square) <= 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(fen: &str) -> Option<i32> {
+        evaluate(&Board::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn kpk_uses_the_bitbase() {
+        // Rook pawn with the defending king in the corner
+        assert_eq!(score("7k/8/5K2/7P/8/8/8/8 w - - 0 1"), Some(0));
+        // Opposition: White to move draws, Black to move loses
+        assert_eq!(score("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), Some(0));
+        assert!(score("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1").unwrap() < -KNOWN_WIN);
+        // The same with colours reversed
+        assert!(score("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1").unwrap() < -KNOWN_WIN);
+    }
+
+    #[test]
+    fn wrong_coloured_bishop_draws_with_a_rook_pawn() {
+        // The queening square a8 is light; a dark-squared bishop cannot drive the king away
+        assert_eq!(score("k7/8/8/8/P7/8/8/2B1K3 w - - 0 1"), Some(0));
+        assert_ne!(score("k7/8/8/8/P7/8/8/3BK3 w - - 0 1"), Some(0));
+        // Black pawn queening on the dark a1
+        assert_eq!(score("2b1k3/8/8/8/8/p7/8/K7 w - - 0 1"), Some(0));
+        assert_ne!(score("3bk3/8/8/8/8/p7/8/K7 w - - 0 1"), Some(0));
+        // Far from the corner the defender does not hold it yet
+        assert_ne!(score("8/8/8/8/P5k1/8/8/2B1K3 w - - 0 1"), Some(0));
+    }
+
+    #[test]
+    fn kbnk_drives_the_king_to_a_corner_of_the_bishop_colour() {
+        // Dark-squared bishop: a1 is the mating corner, a8 is not
+        let right = score("8/8/8/8/3KN3/8/8/k1B5 w - - 0 1").unwrap();
+        let wrong = score("k7/8/8/8/3KN3/8/8/2B5 w - - 0 1").unwrap();
+        let centre = score("8/8/8/4k3/8/2K1N3/8/2B5 w - - 0 1").unwrap();
+        assert!(right > wrong && wrong > KNOWN_WIN, "{} {}", right, wrong);
+        assert!(right > centre);
+        assert_eq!(score("8/8/8/8/3KN3/8/8/k1B5 b - - 0 1"), Some(-right));
+    }
+
+    #[test]
+    fn kxk_pushes_the_lone_king_to_the_edge() {
+        let corner = score("7k/8/8/8/8/8/8/K2Q4 w - - 0 1").unwrap();
+        let centre = score("8/8/8/4k3/8/8/8/K2Q4 w - - 0 1").unwrap();
+        assert!(corner > centre && centre > KNOWN_WIN, "{} {}", corner, centre);
+        // A rook is worth less than a queen, and Black's material counts the same way
+        let rook = score("7k/8/8/8/8/8/8/K2R4 w - - 0 1").unwrap();
+        assert!(KNOWN_WIN < rook && rook < corner);
+        assert_eq!(score("k2q4/8/8/8/8/8/8/7K b - - 0 1"), Some(corner));
+        // Bare minor pieces cannot mate
+        assert_eq!(score("7k/8/8/8/8/8/8/K1N5 w - - 0 1"), Some(0));
+    }
+
+    #[test]
+    fn two_bishops_mate_only_on_both_colours() {
+        // c1 and f1 are different colours, c1 and d2 the same
+        assert!(score("7k/8/8/8/8/8/8/K1B2B2 w - - 0 1").unwrap() > KNOWN_WIN);
+        assert_eq!(score("7k/8/8/8/8/8/3B4/K1B5 w - - 0 1"), Some(0));
+        // The same for Black, with c8 and d7 on one colour
+        assert_eq!(score("k1b5/3b4/8/8/8/8/8/7K w - - 0 1"), Some(0));
+    }
+}
@@ -0,0 +1,137 @@
+//! Runs searches on a background thread so callers (UCI loop, GUI) stay responsive
+
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::dtm::DtmTables;
+use crate::nnue::Network;
+use crate::search::{self, EndgameTables, SearchControl, SearchInfo, SearchLimits, SearchResult};
+use crate::syzygy::Tablebases;
+use crate::tt::{TranspositionTable, DEFAULT_HASH_MB};
+use crate::Board;
+
+/// Messages sent by the search thread
+pub enum SearchEvent {
+    Info(SearchInfo),        // A new iteration was completed
+    Finished(SearchResult),  // The search is over; sent exactly once per search
+}
+
+/// Owns the transposition table, the endgame tablebases, the evaluation network and at most one running search
+pub struct Engine {
+    tt: Arc<Mutex<TranspositionTable>>,
+    tablebases: Option<Arc<Tablebases>>,
+    dtm_tables: Option<Arc<DtmTables>>,
+    network: Option<Arc<Network>>,  // Replaces the handcrafted evaluation when set
+    control: Arc<SearchControl>,    // Flags of the latest search; each search gets its own
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new(DEFAULT_HASH_MB)
+    }
+}
+
+impl Engine {
+    pub fn new(hash_mb: usize) -> Self {
+        Engine {
+            tt: Arc::new(Mutex::new(TranspositionTable::new(hash_mb))),
+            tablebases: None,
+            dtm_tables: None,
+            network: None,
+            control: Arc::new(SearchControl::default()),
+            worker: None,
+        }
+    }
+
+    /// Reallocates the transposition table (stops any running search first)
+    pub fn set_hash_size(&mut self, hash_mb: usize) {
+        self.stop();
+        self.tt = Arc::new(Mutex::new(TranspositionTable::new(hash_mb)));
+    }
+
+    /// Replaces the Syzygy tablebases used by the following searches (None = no tables)
+    pub fn set_tablebases(&mut self, tablebases: Option<Tablebases>) {
+        self.stop();
+        self.tablebases = tablebases.map(Arc::new);
+    }
+
+    /// Replaces the distance-to-mate tables made by `gentb` (shared with the GUI, which shows them)
+    pub fn set_dtm_tables(&mut self, tables: Option<Arc<DtmTables>>) {
+        self.stop();
+        self.dtm_tables = tables;
+    }
+
+    pub fn dtm_tables(&self) -> Option<&DtmTables> {
+        self.dtm_tables.as_deref()
+    }
+
+    /// Switches the evaluation to an NNUE network, or back to the handcrafted one with None
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.stop();
+        self.network = network;
+    }
+
+    pub fn network(&self) -> Option<&Network> {
+        self.network.as_deref()
+    }
+
+    /// Forgets everything learned in the previous game
+    pub fn new_game(&mut self) {
+        self.stop();
+        self.tt.lock().unwrap().clear();
+    }
+
+    /// Starts searching `board` in the background; any previous search is stopped first
+    /// `on_event` is called from the search thread
+    pub fn start(&mut self, board: Board, limits: SearchLimits, mut on_event: impl FnMut(SearchEvent) + Send + 'static) {
+        self.stop();
+        // A fresh control, so a search that was only asked to stop can never be restarted by this one
+        self.control = Arc::new(SearchControl::default());
+        self.control.reset(limits.ponder);
+        let tt = Arc::clone(&self.tt);
+        let tablebases = self.tablebases.clone();
+        let dtm_tables = self.dtm_tables.clone();
+        let network = self.network.clone();
+        let control = Arc::clone(&self.control);
+        self.worker = Some(thread::spawn(move || {
+            let mut tt = tt.lock().unwrap();
+            let tables = EndgameTables { syzygy: tablebases.as_deref(), dtm: dtm_tables.as_deref() };
+            let result = search::search(&board, &limits, &mut tt, tables, network.as_deref(), &control, &mut |info| {
+                on_event(SearchEvent::Info(info.clone()))
+            });
+            on_event(SearchEvent::Finished(result));
+        }));
+    }
+
+    /// Converts the running ponder search into a normal search with the clock running
+    pub fn ponderhit(&self) {
+        self.control.ponderhit();
+    }
+
+    /// Stops the running search (if any) and waits for it to report its result
+    pub fn stop(&mut self) {
+        self.control.stop();
+        self.wait();
+    }
+
+    /// Asks the running search to stop without waiting for it: its result still arrives through
+    /// `on_event` and the next search starts as soon as it released the transposition table
+    pub fn request_stop(&mut self) {
+        self.control.stop();
+        self.worker = None;
+    }
+
+    /// Waits for the running search to finish on its own
+    pub fn wait(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,324 @@
+//! Lets the engine play one or both sides in the GUI, searching on a background thread
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+use eframe::egui;
+
+use crate::dtm::DtmTables;
+use crate::engine::{Engine, SearchEvent};
+use crate::movegen::Move;
+use crate::nnue::Network;
+use crate::rng::Rng;
+use crate::search::{SearchInfo, SearchLimits, SearchResult, MATE_BOUND, MATE_SCORE};
+use crate::skill::{self, Skill};
+use crate::syzygy::Tablebases;
+use crate::Board;
+
+/// Thinking time per move when playing at a limited Elo (the skill caps usually stop it earlier)
+const ELO_MODE_MOVE_TIME_MS: u64 = 1000;
+
+/// Who controls one side of the board
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PlayerKind {
+    Human,
+    Engine,
+}
+
+/// How the engine's thinking is limited
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StrengthMode {
+    Depth,     // Fixed search depth
+    MoveTime,  // Fixed time per move
+    Elo,       // Weakened play at an estimated Elo
+}
+
+/// Options chosen in the "game setup" dialog
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameSetup {
+    pub white: PlayerKind,
+    pub black: PlayerKind,
+    pub mode: StrengthMode,
+    pub depth: u32,
+    pub move_time_secs: f32,
+    pub elo: i32,
+    pub ponder: bool,          // Think on the human's time
+    pub syzygy_path: String,   // Directories with Syzygy tablebases, empty = none
+    pub dtm_path: String,      // Directory with the tables made by `gentb`, empty = none
+    pub use_nnue: bool,        // Evaluate with the network instead of the handcrafted terms
+    pub nnue_path: String,     // Network file (.gknn)
+    pub take_backs: bool,      // The user may take moves back; off for serious games
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        GameSetup {
+            white: PlayerKind::Human,
+            black: PlayerKind::Human,
+            mode: StrengthMode::MoveTime,
+            depth: 6,
+            move_time_secs: 2.0,
+            elo: 1200,
+            ponder: false,
+            syzygy_path: String::new(),
+            dtm_path: String::new(),
+            use_nnue: false,
+            nnue_path: String::new(),
+            take_backs: true,
+        }
+    }
+}
+
+impl GameSetup {
+    /// Who plays the given side
+    pub fn player(&self, white: bool) -> PlayerKind {
+        if white { self.white } else { self.black }
+    }
+
+    /// Pondering only makes sense when the engine plays against a human
+    pub fn ponders(&self) -> bool {
+        self.ponder && self.white != self.black
+    }
+
+    /// Search limits for one engine move
+    pub fn limits(&self) -> SearchLimits {
+        match self.mode {
+            StrengthMode::Depth => SearchLimits { depth: Some(self.depth), ..Default::default() },
+            StrengthMode::MoveTime => SearchLimits {
+                movetime: Some((self.move_time_secs * 1000.0) as u64),
+                ..Default::default()
+            },
+            StrengthMode::Elo => SearchLimits {
+                movetime: Some(ELO_MODE_MOVE_TIME_MS),
+                skill: Some(Skill::from_elo(self.elo, Rng::time_seed())),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Draws the setup form
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        for (label, player) in [("Bianco:", &mut self.white), ("Nero:", &mut self.black)] {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                ui.radio_value(player, PlayerKind::Human, "Umano");
+                ui.radio_value(player, PlayerKind::Engine, "Motore");
+            });
+        }
+        ui.checkbox(&mut self.take_backs, "Consenti di ritirare le mosse (Ctrl+Z / Ctrl+Y)");
+
+        ui.separator();
+        ui.label("Forza del motore:");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.mode, StrengthMode::MoveTime, "Tempo per mossa");
+            ui.radio_value(&mut self.mode, StrengthMode::Depth, "Profondità");
+            ui.radio_value(&mut self.mode, StrengthMode::Elo, "Livello Elo");
+        });
+        match self.mode {
+            StrengthMode::MoveTime => {
+                ui.add(egui::Slider::new(&mut self.move_time_secs, 0.1..=30.0).logarithmic(true).suffix(" s"));
+            },
+            StrengthMode::Depth => {
+                ui.add(egui::Slider::new(&mut self.depth, 1..=20).suffix(" semimosse"));
+            },
+            StrengthMode::Elo => {
+                let (min_elo, max_elo) = skill::elo_range();
+                ui.add(egui::Slider::new(&mut self.elo, min_elo..=max_elo).step_by(50.0).suffix(" Elo"));
+            },
+        }
+        ui.checkbox(&mut self.ponder, "Pondering (il motore pensa durante il turno dell'avversario)");
+        ui.horizontal(|ui| {
+            ui.label("Tablebase Syzygy (cartella):");
+            ui.text_edit_singleline(&mut self.syzygy_path);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Tablebase DTM (cartella):");
+            ui.text_edit_singleline(&mut self.dtm_path);
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.use_nnue, "Valutazione NNUE, rete:");
+            ui.add_enabled(self.use_nnue, egui::TextEdit::singleline(&mut self.nnue_path));
+        });
+    }
+}
+
+/// What the background search is currently doing
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum EngineState {
+    Idle,
+    Thinking,        // Searching the move to play in the current position
+    Pondering(Move), // Searching the position after the expected human reply
+}
+
+/// Drives the engine for the GUI: starts searches, converts ponder searches and collects results
+pub struct EnginePlayer {
+    engine: Engine,
+    sender: Sender<(u64, SearchEvent)>,
+    receiver: Receiver<(u64, SearchEvent)>,
+    search_id: u64,            // Results of older (cancelled) searches are ignored
+    state: EngineState,
+    info: Option<SearchInfo>,  // Latest progress report of the current search
+}
+
+impl Default for EnginePlayer {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        EnginePlayer {
+            engine: Engine::default(),
+            sender,
+            receiver,
+            search_id: 0,
+            state: EngineState::Idle,
+            info: None,
+        }
+    }
+}
+
+impl EnginePlayer {
+    /// Starts a search in the background; events wake up the GUI
+    fn start(&mut self, board: Board, limits: SearchLimits, ctx: &egui::Context) {
+        self.cancel();
+        self.search_id += 1;
+        let id = self.search_id;
+        let sender = self.sender.clone();
+        let ctx = ctx.clone();
+        self.engine.start(board, limits, move |event| {
+            let _ = sender.send((id, event));
+            ctx.request_repaint();
+        });
+    }
+
+    /// Opens the Syzygy tables in `path` (empty = none); returns the largest piece count covered
+    pub fn load_tablebases(&mut self, path: &str) -> Result<usize, String> {
+        self.cancel();
+        if path.trim().is_empty() {
+            self.engine.set_tablebases(None);
+            return Ok(0);
+        }
+        let result = Tablebases::open(path, 1, true);
+        let max_pieces = result.as_ref().map(Tablebases::max_pieces).map_err(String::clone);
+        self.engine.set_tablebases(result.ok());
+        max_pieces
+    }
+
+    /// Opens the tables made by `gentb` in `path` (empty = none); returns how many were loaded
+    pub fn load_dtm_tables(&mut self, path: &str) -> Result<usize, String> {
+        self.cancel();
+        if path.trim().is_empty() {
+            self.engine.set_dtm_tables(None);
+            return Ok(0);
+        }
+        let result = DtmTables::open(path.trim()).map(Arc::new);
+        let count = result.as_ref().map(|tables| tables.len()).map_err(String::clone);
+        self.engine.set_dtm_tables(result.ok());
+        count
+    }
+
+    /// Switches to the network in `path`, or back to the handcrafted evaluation when `use_nnue` is
+    /// off; returns the hidden layer size of the network in use
+    pub fn load_network(&mut self, use_nnue: bool, path: &str) -> Result<Option<usize>, String> {
+        self.cancel();
+        if !use_nnue {
+            self.engine.set_network(None);
+            return Ok(None);
+        }
+        let result = Network::load(path.trim());
+        let hidden = result.as_ref().map(|network| Some(network.hidden_size())).map_err(String::clone);
+        self.engine.set_network(result.ok().map(Arc::new));
+        hidden
+    }
+
+    /// Distance-to-mate tables shared with the engine, for showing the exact outcome
+    pub fn dtm_tables(&self) -> Option<&DtmTables> {
+        self.engine.dtm_tables()
+    }
+
+    /// Searches the move to play in `board`
+    pub fn think(&mut self, board: &Board, limits: SearchLimits, ctx: &egui::Context) {
+        self.start(board.clone(), limits, ctx);
+        self.state = EngineState::Thinking;
+    }
+
+    /// Searches the position after the expected reply while the human is thinking
+    pub fn ponder(&mut self, board: &Board, expected: Move, limits: SearchLimits, ctx: &egui::Context) {
+        let mut ponder_board = board.clone();
+        ponder_board.apply_move(expected);
+        self.start(ponder_board, SearchLimits { ponder: true, ..limits }, ctx);
+        self.state = EngineState::Pondering(expected);
+    }
+
+    /// Tells the engine which move the human played: a ponder hit keeps the search going
+    pub fn opponent_moved(&mut self, mv: Move) {
+        if let EngineState::Pondering(expected) = self.state {
+            if mv == expected {
+                self.engine.ponderhit();
+                self.state = EngineState::Thinking;
+            } else {
+                self.cancel();
+            }
+        }
+    }
+
+    /// Makes the engine play the best move found so far
+    pub fn move_now(&mut self) {
+        if self.state == EngineState::Thinking {
+            self.engine.request_stop();
+        }
+    }
+
+    /// Stops any search and discards its result; the GUI does not wait for the search thread, `poll`
+    /// drops its late events by `search_id`
+    pub fn cancel(&mut self) {
+        self.engine.request_stop();
+        self.search_id += 1;
+        self.state = EngineState::Idle;
+        self.info = None;
+    }
+
+    /// Collects pending events; returns the result once a (non-ponder) search is finished
+    pub fn poll(&mut self) -> Option<SearchResult> {
+        while let Ok((id, event)) = self.receiver.try_recv() {
+            if id != self.search_id {
+                continue;
+            }
+            match event {
+                SearchEvent::Info(info) if info.multipv == 1 => self.info = Some(info),
+                SearchEvent::Info(_) => {},
+                SearchEvent::Finished(result) => {
+                    if self.state == EngineState::Thinking {
+                        self.state = EngineState::Idle;
+                        return Some(result);
+                    }
+                },
+            }
+        }
+        None
+    }
+
+    pub fn is_thinking(&self) -> bool {
+        self.state == EngineState::Thinking
+    }
+
+    /// The reply the engine is pondering on, if any
+    pub fn pondering_on(&self) -> Option<Move> {
+        match self.state {
+            EngineState::Pondering(mv) => Some(mv),
+            _ => None,
+        }
+    }
+
+    pub fn info(&self) -> Option<&SearchInfo> {
+        self.info.as_ref()
+    }
+}
+
+/// Formats a score for display: "+0.35" or "#3" / "#-2" for mates
+pub fn format_score_pawns(score: i32) -> String {
+    if score >= MATE_BOUND {
+        format!("#{}", (MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("#-{}", (MATE_SCORE + score) / 2)
+    } else {
+        format!("{:+.2}", score as f32 / 100.0)
+    }
+}
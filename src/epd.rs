@@ -0,0 +1,188 @@
+//! Test suites in EPD format (WAC, ECM, STS...): `gk_chess_engine epd`
+
+use std::fs;
+use std::time::Instant;
+
+use crate::movegen::Move;
+use crate::search::{self, EndgameTables, SearchControl, SearchLimits};
+use crate::tt::TranspositionTable;
+use crate::Board;
+
+/// Hash size of every search, cleared between positions
+const EPD_HASH_MB: usize = 64;
+
+/// One position of a suite with the opcodes the runner understands
+pub struct EpdEntry {
+    pub board: Board,
+    pub id: String,
+    pub best_moves: Vec<Move>,   // `bm`: any of these solves the position
+    pub avoid_moves: Vec<Move>,  // `am`: none of these may be played
+}
+
+impl EpdEntry {
+    /// Whether playing `mv` solves the position
+    pub fn is_solution(&self, mv: Move) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&mv)) && !self.avoid_moves.contains(&mv)
+    }
+}
+
+/// Outcome of searching one position
+pub struct EpdOutcome {
+    pub best_move: Option<Move>,
+    pub solved: bool,
+    pub solved_at: Option<(u32, u64)>,  // Depth and time (ms) from which the move stayed correct
+    pub nodes: u64,
+}
+
+/// Parses an EPD record: four FEN fields, then `opcode operands;` operations. Blank lines and
+/// comments give None
+pub fn parse_line(line: &str, number: usize) -> Result<Option<EpdEntry>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    // The four FEN fields, separated by any amount of whitespace; the operations are the rest
+    let mut fields = Vec::with_capacity(4);
+    let mut operations = line;
+    while fields.len() < 4 {
+        let (field, rest) = operations.split_once(char::is_whitespace).unwrap_or((operations, ""));
+        if field.is_empty() {
+            return Err(format!("Riga {}: EPD incompleto", number));
+        }
+        fields.push(field);
+        operations = rest.trim_start();
+    }
+    let fen = fields.join(" ");
+    let board = Board::from_fen(&fen).map_err(|err| format!("Riga {}: {}", number, err))?;
+    let mut entry = EpdEntry { board, id: format!("#{}", number), best_moves: Vec::new(), avoid_moves: Vec::new() };
+
+    for operation in operations.split(';') {
+        let operation = operation.trim();
+        let (opcode, operands) = operation.split_once(char::is_whitespace).unwrap_or((operation, ""));
+        let moves = || -> Result<Vec<Move>, String> {
+            operands
+                .split_whitespace()
+                .map(|san| entry.board.parse_san(san).ok_or_else(|| format!("Riga {}: mossa non valida '{}'", number, san)))
+                .collect()
+        };
+        match opcode {
+            "bm" => entry.best_moves = moves()?,
+            "am" => entry.avoid_moves = moves()?,
+            "id" => entry.id = operands.trim().trim_matches('"').to_string(),
+            _ => {},
+        }
+    }
+    if entry.best_moves.is_empty() && entry.avoid_moves.is_empty() {
+        return Err(format!("Riga {}: nessuna operazione bm o am", number));
+    }
+    Ok(Some(entry))
+}
+
+/// Reads every position of a suite
+pub fn parse_suite(text: &str) -> Result<Vec<EpdEntry>, String> {
+    text.lines().enumerate().filter_map(|(index, line)| parse_line(line, index + 1).transpose()).collect()
+}
+
+/// Searches one position, following the iterations to find when the solution was first kept
+pub fn solve(entry: &EpdEntry, limits: &SearchLimits, tt: &mut TranspositionTable) -> EpdOutcome {
+    let control = SearchControl::default();
+    let mut solved_at = None;
+    let result = search::search(&entry.board, limits, tt, EndgameTables::default(), None, &control, &mut |info| {
+        if info.multipv != 1 {
+            return;
+        }
+        match info.pv.first() {
+            Some(&mv) if entry.is_solution(mv) => {
+                solved_at.get_or_insert((info.depth, info.time_ms));
+            },
+            _ => solved_at = None,
+        }
+    });
+    let solved = result.best_move.is_some_and(|mv| entry.is_solution(mv));
+    EpdOutcome { best_move: result.best_move, solved, solved_at: if solved { solved_at } else { None }, nodes: result.nodes }
+}
+
+/// Expected moves of an entry as written in the report: "bm Qg6" or "am Bxh7"
+fn expected(entry: &EpdEntry) -> String {
+    let mut parts = Vec::new();
+    for (opcode, moves) in [("bm", &entry.best_moves), ("am", &entry.avoid_moves)] {
+        if !moves.is_empty() {
+            let sans: Vec<String> = moves.iter().map(|&mv| entry.board.to_san(mv)).collect();
+            parts.push(format!("{} {}", opcode, sans.join(" ")));
+        }
+    }
+    parts.join(", ")
+}
+
+/// `epd <suite.epd> [--movetime ms | --depth N]`: searches every position and prints the score
+pub fn run(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "Uso: gk_chess_engine epd <suite.epd> [--movetime ms | --depth N]";
+    let (limits, paths) = search::parse_search_limits(args, 1000)?;
+    let [path] = paths[..] else { return Err(USAGE.to_string()) };
+    let text = fs::read_to_string(path).map_err(|err| format!("Impossibile leggere {}: {}", path, err))?;
+    let entries = parse_suite(&text)?;
+
+    let started = Instant::now();
+    let mut tt = TranspositionTable::new(EPD_HASH_MB);
+    let mut failed = Vec::new();
+    let mut total_nodes = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        tt.clear();
+        let outcome = solve(entry, &limits, &mut tt);
+        total_nodes += outcome.nodes;
+        let played = outcome.best_move.map_or("-".to_string(), |mv| entry.board.to_san(mv));
+        let status = match outcome.solved_at {
+            Some((depth, time_ms)) => format!("OK  prof. {:2}, {:6} ms", depth, time_ms),
+            None if outcome.solved => "OK".to_string(),
+            None => "KO".to_string(),
+        };
+        println!("{:4} {:<12} {:<8} ({}) {}", index + 1, entry.id, played, expected(entry), status);
+        if !outcome.solved {
+            failed.push(entry.id.as_str());
+        }
+    }
+
+    println!("===========================");
+    println!("Risolte: {}/{}, fallite: {}", entries.len() - failed.len(), entries.len(), failed.len());
+    println!("Tempo: {} ms, nodi: {}", started.elapsed().as_millis(), total_nodes);
+    if !failed.is_empty() {
+        println!("Fallite: {}", failed.join(" "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tactical positions (Win At Chess) the engine must keep solving at a small fixed depth
+    const REGRESSION_SUITE: &str = include_str!("../suites/wac_regression.epd");
+    const REGRESSION_DEPTH: u32 = 6;
+
+    #[test]
+    fn fields_may_be_separated_by_several_spaces() {
+        let entry = parse_line("6k1/5ppp/8/8/8/8/5PPP/R5K1  w   -  -   bm Ra8#; id \"mate\";", 1).unwrap().unwrap();
+        assert_eq!(entry.board.to_fen(), "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        assert_eq!(entry.best_moves, entry.board.parse_uci_move("a1a8").into_iter().collect::<Vec<_>>());
+        assert_eq!(entry.id, "mate");
+        assert!(parse_line("6k1/5ppp/8/8/8/8/5PPP/R5K1 w  -", 2).is_err());
+        assert!(parse_line("  # commento", 3).unwrap().is_none());
+    }
+
+    #[test]
+    fn wac_regression_suite() {
+        let entries = parse_suite(REGRESSION_SUITE).unwrap();
+        assert!(!entries.is_empty());
+        let limits = SearchLimits { depth: Some(REGRESSION_DEPTH), ..Default::default() };
+        let mut tt = TranspositionTable::new(16);
+        let failed: Vec<&str> = entries
+            .iter()
+            .filter(|entry| {
+                tt.clear();
+                !solve(entry, &limits, &mut tt).solved
+            })
+            .map(|entry| entry.id.as_str())
+            .collect();
+        assert!(failed.is_empty(), "Posizioni non risolte: {:?}", failed);
+    }
+}
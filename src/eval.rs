@@ -0,0 +1,348 @@
+//! Static evaluation: material, piece-square tables, mobility, pawn structure and king safety, tapered
+//! between middlegame and endgame
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+
+use crate::params::PARAMS;
+use crate::pawns::{self, PawnEntry, PawnTable};
+use crate::{endgame, king_safety};
+use crate::{Board, Piece};
+
+/// Nominal material values in centipawns (pawn, knight, bishop, rook, queen, king), for move ordering
+/// and material signatures; the evaluation uses the tuned values in `params::PARAMS`
+pub const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+/// Game phase weight of each piece type; 24 = all pieces on the board
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+/// A middlegame and an endgame value, blended by the game phase
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const fn new(mg: i32, eg: i32) -> Score {
+        Score { mg, eg }
+    }
+
+    /// Interpolates between the endgame (phase 0) and middlegame (MAX_PHASE) values
+    pub fn taper(self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, factor: i32) -> Score {
+        Score::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl Div<i32> for Score {
+    type Output = Score;
+    fn div(self, divisor: i32) -> Score {
+        Score::new(self.mg / divisor, self.eg / divisor)
+    }
+}
+
+// Layout of the parameter vector `params::PARAMS`, which holds every tunable weight
+const MATERIAL: usize = 0;                   // Pawn to queen
+const PST: usize = MATERIAL + 5;             // 64 squares per piece type, from White's side, rank 8 first
+const MOBILITY: usize = PST + 6 * 64;        // Knight to queen
+const DOUBLED: usize = MOBILITY + 4;
+const ISOLATED: usize = DOUBLED + 1;
+const BACKWARD: usize = ISOLATED + 1;
+const ISLAND: usize = BACKWARD + 1;
+const PASSED: usize = ISLAND + 1;            // By relative rank
+const PASSED_BLOCKED: usize = PASSED + 8;    // By relative rank, square in front occupied
+const SHELTER: usize = PASSED_BLOCKED + 8;   // See `king_safety::KingSafety`
+const STORM: usize = SHELTER + 4;
+const KING_ATTACK: usize = STORM + 5;        // Knight to queen
+pub const PARAM_COUNT: usize = KING_ATTACK + 4;
+
+/// Sections of the parameter vector with their first index, as written by the tuner
+pub const PARAM_SECTIONS: [(&str, usize); 17] = [
+    ("Material, pawn to queen", MATERIAL),
+    ("Piece-square table: pawn", PST),
+    ("Piece-square table: knight", PST + 64),
+    ("Piece-square table: bishop", PST + 2 * 64),
+    ("Piece-square table: rook", PST + 3 * 64),
+    ("Piece-square table: queen", PST + 4 * 64),
+    ("Piece-square table: king", PST + 5 * 64),
+    ("Mobility per square beyond the normal count, knight to queen", MOBILITY),
+    ("Doubled pawn", DOUBLED),
+    ("Isolated pawn", ISOLATED),
+    ("Backward pawn", BACKWARD),
+    ("Pawn island beyond the first", ISLAND),
+    ("Passed pawn by relative rank", PASSED),
+    ("Blocked passed pawn by relative rank", PASSED_BLOCKED),
+    ("King shelter by distance of the own pawn (0 = none)", SHELTER),
+    ("Pawn storm by distance of the enemy pawn (0 = none near)", STORM),
+    ("King zone attack per square, knight to queen", KING_ATTACK),
+];
+
+/// Game phase of the position, from MAX_PHASE (opening) down to 0 (bare kings and pawns)
+pub fn game_phase(board: &Board) -> i32 {
+    let mut phase = 0;
+    for row in 0..8 {
+        for col in 0..8 {
+            let piece = board.squares[row][col];
+            if !piece.is_empty() {
+                phase += PHASE_WEIGHTS[piece.type_index()];
+            }
+        }
+    }
+    phase.min(MAX_PHASE)
+}
+
+/// Number of evaluation terms (see `TERM_NAMES`)
+pub const TERM_COUNT: usize = 11;
+
+/// Names of the evaluation terms, in the order used by `Terms`
+pub const TERM_NAMES: [&str; TERM_COUNT] = [
+    "Materiale",
+    "Pezzo-casa",
+    "Mobilità",
+    "Pedoni doppiati",
+    "Pedoni isolati",
+    "Pedoni arretrati",
+    "Isole di pedoni",
+    "Pedoni passati",
+    "Scudo del re",
+    "Assalto di pedoni",
+    "Attacchi al re",
+];
+
+/// Every term for White and Black, each from its own side's point of view: `[term][colour]`
+pub type Terms = [[Score; 2]; TERM_COUNT];
+
+/// Reachable squares that count as normal mobility, knight to queen
+const MOBILITY_NORMAL: [i32; 4] = [4, 6, 7, 13];
+
+/// Receives the evaluation as a sum of parameters times counts, term by term
+pub trait EvalSink {
+    /// Adds `count` times the parameter `param` to `term` of `side` (0 = White, 1 = Black)
+    fn add(&mut self, term: usize, side: usize, param: usize, count: i32);
+    /// Scales what `side` has collected so far in `term`, in percent
+    fn scale(&mut self, term: usize, side: usize, percent: i32);
+}
+
+/// The engine's own evaluation: the terms valued with `params::PARAMS`
+impl EvalSink for Terms {
+    fn add(&mut self, term: usize, side: usize, param: usize, count: i32) {
+        self[term][side] += PARAMS[param] * count;
+    }
+
+    fn scale(&mut self, term: usize, side: usize, percent: i32) {
+        self[term][side] = self[term][side] * percent / 100;
+    }
+}
+
+/// True if a pawn of the given colour attacks (row, col)
+fn pawn_attacks(board: &Board, row: usize, col: usize, by_white: bool) -> bool {
+    let pawn = if by_white { Piece::PawnWhite } else { Piece::PawnBlack };
+    let Some(pawn_row) = (if by_white { row.checked_add(1).filter(|&r| r < 8) } else { row.checked_sub(1) }) else { return false };
+    (col > 0 && board.squares[pawn_row][col - 1] == pawn) || (col < 7 && board.squares[pawn_row][col + 1] == pawn)
+}
+
+/// Squares reachable by the piece on (row, col), not counting own pieces and squares held by enemy pawns
+fn mobility(board: &Board, row: usize, col: usize) -> i32 {
+    let white = board.squares[row][col].is_white();
+    let mut squares = 0;
+    board.for_each_attack(row, col, |r, c| {
+        if !board.squares[r][c].is_color(white) && !pawn_attacks(board, r, c, !white) {
+            squares += 1;
+        }
+    });
+    squares
+}
+
+/// Feeds every term of `board` to `sink`; `pawn_entry` holds the pawn analysis of `board`
+fn collect_terms(board: &Board, pawn_entry: &PawnEntry, sink: &mut impl EvalSink) {
+    for row in 0..8 {
+        for col in 0..8 {
+            let piece = board.squares[row][col];
+            if piece.is_empty() {
+                continue;
+            }
+            let type_index = piece.type_index();
+            // Black reads the tables upside down
+            let table_row = if piece.is_white() { row } else { 7 - row };
+            let side = usize::from(!piece.is_white());
+            if type_index < 5 {
+                sink.add(0, side, MATERIAL + type_index, 1);
+            }
+            sink.add(1, side, PST + type_index * 64 + table_row * 8 + col, 1);
+            if (1..5).contains(&type_index) {
+                sink.add(2, side, MOBILITY + type_index - 1, mobility(board, row, col) - MOBILITY_NORMAL[type_index - 1]);
+            }
+        }
+    }
+
+    for (side, white) in [(0, true), (1, false)] {
+        let counts = &pawn_entry.counts[side];
+        sink.add(3, side, DOUBLED, counts.doubled);
+        sink.add(4, side, ISOLATED, counts.isolated);
+        sink.add(5, side, BACKWARD, counts.backward);
+        sink.add(6, side, ISLAND, counts.islands);
+        for (rank, blocked) in pawns::passed_pawns(board, pawn_entry.passed[side], white) {
+            sink.add(7, side, if blocked { PASSED_BLOCKED } else { PASSED } + rank, 1);
+        }
+        let Some(safety) = king_safety::evaluate(board, white) else { continue };
+        for distance in safety.shelter {
+            sink.add(8, side, SHELTER + distance, 1);
+        }
+        for distance in safety.storm {
+            sink.add(9, side, STORM + distance, 1);
+        }
+        for (piece, squares) in safety.attacks.into_iter().enumerate() {
+            sink.add(10, side, KING_ATTACK + piece, squares);
+        }
+        sink.scale(10, side, safety.attack_scale);
+    }
+}
+
+/// Computes every term; `pawn_entry` holds the pawn analysis of `board`
+fn compute_terms(board: &Board, pawn_entry: &PawnEntry) -> Terms {
+    let mut terms = [[Score::default(); 2]; TERM_COUNT];
+    collect_terms(board, pawn_entry, &mut terms);
+    terms
+}
+
+/// Feeds the terms of `board` to `sink` without the specialised endgame evaluators (used by the tuner)
+pub fn collect(board: &Board, sink: &mut impl EvalSink) {
+    collect_terms(board, &pawns::analyze(board), sink);
+}
+
+/// Sum of the terms from White's point of view
+fn balance(terms: &Terms) -> Score {
+    terms.iter().fold(Score::default(), |total, [white, black]| total + *white - *black)
+}
+
+/// Evaluates the position in centipawns from the point of view of the side to move;
+/// `pawns` caches the pawn structure terms
+pub fn evaluate(board: &Board, pawns: &mut PawnTable) -> i32 {
+    if let Some(score) = endgame::evaluate(board) {
+        return score;
+    }
+    let terms = compute_terms(board, &pawns.probe(board));
+    let score = balance(&terms).taper(game_phase(board));
+    if board.white_to_move { score } else { -score }
+}
+
+/// Breakdown of the evaluation of a position, term by term
+pub struct EvalTrace {
+    pub terms: Terms,
+    pub phase: i32,            // Game phase used for tapering, 0..=MAX_PHASE
+    pub endgame: Option<i32>,  // Score of a specialised endgame evaluator, which replaces the terms
+    pub score: i32,            // Final evaluation from White's point of view
+}
+
+impl EvalTrace {
+    /// One term from White's point of view (White's value minus Black's)
+    pub fn balance(&self, term: usize) -> Score {
+        self.terms[term][0] - self.terms[term][1]
+    }
+}
+
+/// Evaluates `board` like `evaluate`, keeping every term (scores from White's point of view)
+pub fn evaluate_trace(board: &Board) -> EvalTrace {
+    let terms = compute_terms(board, &pawns::analyze(board));
+    let phase = game_phase(board);
+    let from_white = |score: i32| if board.white_to_move { score } else { -score };
+    let endgame = endgame::evaluate(board).map(from_white);
+    let score = endgame.unwrap_or_else(|| balance(&terms).taper(phase));
+    EvalTrace { terms, phase, endgame, score }
+}
+
+/// Prints the trace as a table (used by the `eval` commands)
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<20} | {:>13} | {:>13} | {:>13}", "Termine", "Bianco", "Nero", "Totale")?;
+        writeln!(f, "{:<20} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}", "", "MG", "EG", "MG", "EG", "MG", "EG")?;
+        writeln!(f, "{}", "-".repeat(68))?;
+        for (term, name) in TERM_NAMES.iter().enumerate() {
+            let [white, black] = self.terms[term];
+            let total = self.balance(term);
+            writeln!(
+                f,
+                "{:<20} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+                name, white.mg, white.eg, black.mg, black.eg, total.mg, total.eg
+            )?;
+        }
+        writeln!(f, "{}", "-".repeat(68))?;
+        let total = balance(&self.terms);
+        writeln!(f, "{:<20} | {:>13} | {:>13} | {:>6} {:>6}", "Somma", "", "", total.mg, total.eg)?;
+        writeln!(f, "Fase di gioco: {}/{} (mediogioco {}%)", self.phase, MAX_PHASE, self.phase * 100 / MAX_PHASE)?;
+        if let Some(score) = self.endgame {
+            writeln!(f, "Finale riconosciuto: la valutazione specifica ({:+} cp) sostituisce i termini", score)?;
+        }
+        write!(f, "Valutazione: {:+} cp dal punto di vista del Bianco", self.score)
+    }
+}
+
+/// True if the given side has any piece other than pawns and king (null-move safety)
+pub fn has_non_pawn_material(board: &Board, white: bool) -> bool {
+    board.squares.iter().flatten().any(|piece| {
+        piece.is_color(white) && !matches!(piece, Piece::PawnWhite | Piece::PawnBlack | Piece::KingWhite | Piece::KingBlack)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_adds_up_to_the_evaluation() {
+        let fens = [
+            crate::fen::START_FEN,
+            "r1bqkb1r/pp3ppp/2n1pn2/3p4/2PP4/2N2N2/PP3PPP/R1BQKB1R b KQkq - 0 6",
+            "5rk1/8/8/8/6p1/4n2P/5PP1/6K1 w - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+            "4k3/p7/8/4P3/8/2P5/P1P5/4K3 w - - 0 1",
+            "8/8/8/8/3KN3/8/8/k1B5 b - - 0 1",
+        ];
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+            let trace = evaluate_trace(&board);
+            let from_side_to_move = evaluate(&board, &mut PawnTable::default());
+            assert_eq!(if board.white_to_move { trace.score } else { -trace.score }, from_side_to_move, "{}", fen);
+            if trace.endgame.is_none() {
+                let sum = (0..TERM_COUNT).fold(Score::default(), |total, term| total + trace.balance(term));
+                assert_eq!(sum.taper(trace.phase), trace.score, "{}", fen);
+                assert_eq!(trace.phase, game_phase(&board));
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! "Valutazione" panel of the GUI: the static evaluation of the current position, term by term
+
+use eframe::egui;
+
+use crate::engine_player::format_score_pawns;
+use crate::eval::{evaluate_trace, MAX_PHASE, TERM_NAMES};
+use crate::Board;
+
+/// Draws the evaluation breakdown of `board` (middlegame / endgame values in centipawns)
+pub fn ui(ui: &mut egui::Ui, board: &Board) {
+    let trace = evaluate_trace(board);
+    egui::Grid::new("eval_trace").striped(true).num_columns(4).show(ui, |ui| {
+        for header in ["Termine", "Bianco", "Nero", "Totale"] {
+            ui.strong(header);
+        }
+        ui.end_row();
+        for (term, name) in TERM_NAMES.iter().enumerate() {
+            let [white, black] = trace.terms[term];
+            let total = trace.balance(term);
+            ui.label(*name);
+            for score in [white, black, total] {
+                ui.monospace(format!("{:>5} {:>5}", score.mg, score.eg));
+            }
+            ui.end_row();
+        }
+    });
+    ui.label(format!("Fase di gioco: {}/{} (valori mediogioco / finale)", trace.phase, MAX_PHASE));
+    if let Some(score) = trace.endgame {
+        ui.label(format!("Finale riconosciuto: valutazione specifica {}", format_score_pawns(score)));
+    }
+    ui.strong(format!("Valutazione (Bianco): {}", format_score_pawns(trace.score)));
+}
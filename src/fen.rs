@@ -0,0 +1,232 @@
+//! Forsyth-Edwards Notation (FEN) import and export for `Board`
+
+use crate::movegen::{parse_square, square_name};
+use crate::{Board, GameState, Piece};
+
+/// FEN of the standard starting position
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Maps a FEN letter to a piece
+fn piece_from_char(c: char) -> Option<Piece> {
+    Some(match c {
+        'P' => Piece::PawnWhite,
+        'N' => Piece::KnightWhite,
+        'B' => Piece::BishopWhite,
+        'R' => Piece::RookWhite,
+        'Q' => Piece::QueenWhite,
+        'K' => Piece::KingWhite,
+        'p' => Piece::PawnBlack,
+        'n' => Piece::KnightBlack,
+        'b' => Piece::BishopBlack,
+        'r' => Piece::RookBlack,
+        'q' => Piece::QueenBlack,
+        'k' => Piece::KingBlack,
+        _ => return None,
+    })
+}
+
+/// Maps a piece to its FEN letter (uppercase for White)
+fn piece_to_char(piece: Piece) -> char {
+    let letter = ['p', 'n', 'b', 'r', 'q', 'k', '.'][piece.type_index()];
+    if piece.is_white() { letter.to_ascii_uppercase() } else { letter }
+}
+
+impl Board {
+    /// FEN letter of the piece on every square, None for empty squares (for drawing the board)
+    pub fn piece_letters(&self) -> [[Option<char>; 8]; 8] {
+        self.squares.map(|row| row.map(|piece| (!piece.is_empty()).then(|| piece_to_char(piece))))
+    }
+
+    /// Parses a FEN string; the move counters may be omitted (as in EPD records)
+    pub fn from_fen(fen: &str) -> Result<Board, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(format!("FEN incompleto: '{}'", fen));
+        }
+
+        // Piece placement, rank 8 first
+        let mut squares = [[Piece::Empty; 8]; 8];
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("FEN deve avere 8 traverse: '{}'", fields[0]));
+        }
+        for (row, rank) in ranks.iter().enumerate() {
+            let mut col = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    col += empty as usize;
+                } else {
+                    let piece = piece_from_char(c).ok_or_else(|| format!("Pezzo sconosciuto '{}' nel FEN", c))?;
+                    if col >= 8 {
+                        return Err(format!("Traversa troppo lunga: '{}'", rank));
+                    }
+                    if matches!(piece, Piece::PawnWhite | Piece::PawnBlack) && (row == 0 || row == 7) {
+                        return Err(format!("Pedone sulla prima o sull'ottava traversa: '{}'", rank));
+                    }
+                    squares[row][col] = piece;
+                    col += 1;
+                }
+            }
+            if col != 8 {
+                return Err(format!("Traversa di lunghezza errata: '{}'", rank));
+            }
+        }
+
+        let white_to_move = match fields[1] {
+            "w" => true,
+            "b" => false,
+            other => return Err(format!("Colore al tratto non valido: '{}'", other)),
+        };
+
+        // Castling rights are stored as "has moved" flags
+        let castling = fields[2];
+        let game_state = GameState {
+            white_king_moved: !castling.contains('K') && !castling.contains('Q'),
+            black_king_moved: !castling.contains('k') && !castling.contains('q'),
+            white_rook_queenside_moved: !castling.contains('Q'),
+            white_rook_kingside_moved: !castling.contains('K'),
+            black_rook_queenside_moved: !castling.contains('q'),
+            black_rook_kingside_moved: !castling.contains('k'),
+            en_passant_target: if fields[3] == "-" {
+                None
+            } else {
+                Some(parse_square(fields[3]).ok_or_else(|| format!("Casella en passant non valida: '{}'", fields[3]))?)
+            },
+        };
+
+        let halfmove_clock = fields.get(4).and_then(|f| f.parse().ok()).unwrap_or(0);
+        let fullmove_number = fields.get(5).and_then(|f| f.parse().ok()).unwrap_or(1);
+
+        let mut board = Board {
+            squares,
+            white_to_move,
+            game_state,
+            halfmove_clock,
+            fullmove_number,
+            hash: 0,
+            history: Vec::new(),
+        };
+        if board.find_king(true).is_none() || board.find_king(false).is_none() {
+            return Err("Il FEN deve contenere entrambi i re".to_string());
+        }
+        board.hash = board.compute_hash();
+        Ok(board)
+    }
+
+    /// Serialises the position as a FEN string
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for row in 0..8 {
+            let mut empty = 0;
+            for col in 0..8 {
+                let piece = self.squares[row][col];
+                if piece.is_empty() {
+                    empty += 1;
+                } else {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    placement.push(piece_to_char(piece));
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if row < 7 {
+                placement.push('/');
+            }
+        }
+
+        let rights = self.game_state.castling_rights();
+        let mut castling: String = [(1, 'K'), (2, 'Q'), (4, 'k'), (8, 'q')]
+            .iter()
+            .filter(|(bit, _)| rights & bit != 0)
+            .map(|&(_, c)| c)
+            .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = match self.game_state.en_passant_target {
+            Some((row, col)) => square_name(row, col),
+            None => "-".to_string(),
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            if self.white_to_move { "w" } else { "b" },
+            castling,
+            en_passant,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+}
+
+/// The same position with colours swapped and the board turned upside down, for symmetry tests
+#[cfg(test)]
+pub fn mirror_fen(fen: &str) -> String {
+    let swap_case = |text: &str| text.chars().map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect::<String>();
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let placement = fields[0].split('/').rev().map(swap_case).collect::<Vec<_>>().join("/");
+    let side = if fields[1] == "w" { "b" } else { "w" };
+    let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+    castling.sort_by_key(|c| (c.is_ascii_lowercase(), *c != 'K' && *c != 'k'));
+    let en_passant = match fields[3].as_bytes() {
+        [file, rank] => format!("{}{}", *file as char, (b'1' + b'8' - rank) as char),
+        _ => "-".to_string(),
+    };
+    format!("{} {} {} {} {}", placement, side, castling.into_iter().collect::<String>(), en_passant, fields[4..].join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mirror_fen;
+    use crate::Board;
+
+    #[test]
+    fn fen_round_trip() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 7 42",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        for fen in fens {
+            assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn pawns_on_back_ranks_are_rejected() {
+        assert!(Board::from_fen("4k2P/8/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/p3K3 b - - 0 1").is_err());
+        assert!(Board::from_fen("4k3/P7/8/8/8/8/7p/4K3 w - - 0 1").is_ok());
+    }
+
+    #[test]
+    fn mirrored_fen() {
+        let fen = "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 2";
+        assert_eq!(mirror_fen(fen), "r3k2r/8/8/8/3Pp3/8/8/R3K2R b Qk d3 0 2");
+        assert_eq!(mirror_fen(&mirror_fen(fen)), fen);
+        assert_eq!(mirror_fen(super::START_FEN), super::START_FEN.replace(" w ", " b "));
+    }
+
+    #[test]
+    fn fen_matches_played_moves() {
+        // 1.e4 c5 2.e5 d5: the FEN keeps the en passant square, counters and castling rights
+        let mut board = Board::new();
+        for text in ["e2e4", "c7c5", "e4e5", "d7d5"] {
+            let mv = board.parse_uci_move(text).unwrap();
+            board.apply_move(mv);
+        }
+        let fen = board.to_fen();
+        assert_eq!(fen, "rnbqkbnr/pp2pppp/8/2ppP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3");
+        let parsed = Board::from_fen(&fen).unwrap();
+        assert_eq!(parsed.hash, board.hash);
+        assert_eq!(parsed.to_fen(), fen);
+    }
+}
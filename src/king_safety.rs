@@ -0,0 +1,127 @@
+//! King safety: pawn shelter, pawn storms and piece attacks on the squares around the king
+
+use crate::pawns::relative_rank;
+use crate::Board;
+
+/// Percentage of the attack that counts, by number of attacking pieces (one alone is harmless)
+const ATTACKER_SCALE: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 100];
+
+/// King safety features of one side, valued by the `eval` parameters (middlegame weights)
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct KingSafety {
+    pub shelter: [usize; 3],  // Per file around the king: ranks from the king to the own pawn in front (0 = none within 3)
+    pub storm: [usize; 3],    // Per file: ranks from the king to the closest enemy pawn (0 = none within 4)
+    pub attacks: [i32; 4],    // King zone squares attacked by enemy knights, bishops, rooks and queens
+    pub attack_scale: i32,    // Percentage of the attacks that counts, from the number of attackers
+}
+
+/// King safety of `white`'s king
+pub fn evaluate(board: &Board, white: bool) -> Option<KingSafety> {
+    let mut safety = KingSafety::default();
+    let (king_row, king_col) = board.find_king(white)?;
+    let king_rank = relative_rank(king_row, white);
+
+    // Shelter and storm on the king's file and its neighbours (kept off the edge)
+    let centre = king_col.clamp(1, 6);
+    for (file, col) in (centre - 1..=centre + 1).enumerate() {
+        let mut own = 0;
+        let mut enemy = 0;
+        for row in 0..8 {
+            let piece = board.squares[row][col];
+            let rank = relative_rank(row, white);
+            if piece.type_index() != 0 || rank <= king_rank {
+                continue;
+            }
+            let distance = rank - king_rank;
+            if piece.is_color(white) {
+                own = if own == 0 { distance } else { own.min(distance) };
+            } else {
+                enemy = if enemy == 0 { distance } else { enemy.min(distance) };
+            }
+        }
+        safety.shelter[file] = if own < 4 { own } else { 0 };
+        safety.storm[file] = if enemy < 5 { enemy } else { 0 };
+    }
+
+    // Enemy knights, bishops, rooks and queens (type indices 1 to 4) hitting the squares around the king
+    let mut attackers = 0;
+    for row in 0..8 {
+        for col in 0..8 {
+            let piece = board.squares[row][col];
+            let kind = piece.type_index();
+            if piece.is_empty() || piece.is_color(white) || !(1..=4).contains(&kind) {
+                continue;
+            }
+            let mut squares = 0;
+            board.for_each_attack(row, col, |r, c| {
+                if r.abs_diff(king_row) <= 1 && c.abs_diff(king_col) <= 1 {
+                    squares += 1;
+                }
+            });
+            if squares > 0 {
+                attackers += 1;
+                safety.attacks[kind - 1] += squares;
+            }
+        }
+    }
+    safety.attack_scale = ATTACKER_SCALE[attackers.min(7)];
+    Some(safety)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::evaluate_trace;
+    use crate::fen::mirror_fen;
+
+    fn safety(fen: &str, white: bool) -> KingSafety {
+        evaluate(&Board::from_fen(fen).unwrap(), white).unwrap()
+    }
+
+    #[test]
+    fn shelter_and_storm() {
+        // g1 king behind f2, g2 and h3; a black pawn on g4 three ranks away
+        let white = safety("6k1/8/8/8/6p1/7P/5PP1/6K1 w - - 0 1", true);
+        assert_eq!(white.shelter, [1, 1, 2]);
+        assert_eq!(white.storm, [0, 3, 0]);
+        // A king on the edge uses the files next to it
+        assert_eq!(safety("6k1/8/8/8/8/8/PP6/K7 w - - 0 1", true).shelter, [1, 1, 0]);
+    }
+
+    #[test]
+    fn attacks_on_the_king_zone() {
+        // The e3 knight hits f1 and g2, the f8 rook f2 and f1: two attackers count half
+        let white = safety("5rk1/8/8/8/8/4n3/6PP/6K1 w - - 0 1", true);
+        assert_eq!(white.attacks, [2, 0, 2, 0]);
+        assert_eq!(white.attack_scale, 50);
+        // A single attacker is ignored by the scoring
+        let lone = safety("6k1/8/8/8/8/4n3/6PP/6K1 w - - 0 1", true);
+        assert_eq!((lone.attacks, lone.attack_scale), ([2, 0, 0, 0], 0));
+        assert_eq!(evaluate_trace(&Board::from_fen("6k1/8/8/8/8/4n3/6PP/6K1 w - - 0 1").unwrap()).terms[10][0].mg, 0);
+    }
+
+    #[test]
+    fn intact_shelter_scores_better() {
+        let shelter = |fen: &str| evaluate_trace(&Board::from_fen(fen).unwrap()).terms[8][0].mg;
+        let intact = shelter("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let broken = shelter("6k1/8/8/8/8/8/5P1P/6K1 w - - 0 1");
+        assert!(intact > broken, "{} {}", intact, broken);
+    }
+
+    #[test]
+    fn mirrored_positions_give_mirrored_safety() {
+        let fens = [
+            "5rk1/8/8/8/6p1/4n2P/5PP1/6K1 w - - 0 1",
+            "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP2BPPP/R2QKB1R w KQ - 0 8",
+        ];
+        for fen in fens {
+            let mirrored = mirror_fen(fen);
+            assert_eq!(safety(fen, true), safety(&mirrored, false), "{}", fen);
+            assert_eq!(safety(fen, false), safety(&mirrored, true), "{}", fen);
+            let (trace, mirrored_trace) = (evaluate_trace(&Board::from_fen(fen).unwrap()), evaluate_trace(&Board::from_fen(&mirrored).unwrap()));
+            for term in 8..=10 {
+                assert_eq!(trace.terms[term], [mirrored_trace.terms[term][1], mirrored_trace.terms[term][0]], "{}", fen);
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! King and pawn vs king bitbase, generated by retrograde analysis the first time it is needed
+//!
+//! Squares here are numbered from White's side, `rank * 8 + file` with a1 = 0, and positions are
+//! normalised so that the pawn is White's and stands on files a-d.
+
+use std::sync::OnceLock;
+
+/// Positions: side to move (2) x black king (64) x white king (64) x pawn on a2-d7 (24)
+const POSITION_COUNT: usize = 2 * 64 * 64 * 24;
+
+/// Classification of one position during generation
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Outcome {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+/// One bit per position: set = White wins
+static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+
+fn file_of(square: usize) -> usize {
+    square % 8
+}
+
+fn rank_of(square: usize) -> usize {
+    square / 8
+}
+
+/// Chebyshev distance: number of king moves between two squares
+pub fn distance(a: usize, b: usize) -> usize {
+    file_of(a).abs_diff(file_of(b)).max(rank_of(a).abs_diff(rank_of(b)))
+}
+
+fn index(white_to_move: bool, black_king: usize, white_king: usize, pawn: usize) -> usize {
+    let pawn_index = (rank_of(pawn) - 1) * 4 + file_of(pawn);
+    ((usize::from(white_to_move) * 64 + black_king) * 64 + white_king) * 24 + pawn_index
+}
+
+/// Squares a king on `square` can step to
+fn king_steps(square: usize) -> impl Iterator<Item = usize> {
+    let (file, rank) = (file_of(square) as i32, rank_of(square) as i32);
+    (-1..=1)
+        .flat_map(move |df| (-1..=1).map(move |dr| (file + df, rank + dr)))
+        .filter(move |&(f, r)| (f, r) != (file, rank) && (0..8).contains(&f) && (0..8).contains(&r))
+        .map(|(f, r)| (r * 8 + f) as usize)
+}
+
+/// True if a white pawn on `pawn` attacks `square`
+fn pawn_attacks(pawn: usize, square: usize) -> bool {
+    rank_of(square) == rank_of(pawn) + 1 && file_of(square).abs_diff(file_of(pawn)) == 1
+}
+
+/// Outcome that is known without looking at successors
+fn initial_outcome(white_to_move: bool, black_king: usize, white_king: usize, pawn: usize) -> Outcome {
+    if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        || (white_to_move && pawn_attacks(pawn, black_king))
+    {
+        return Outcome::Invalid;
+    }
+
+    // The pawn promotes safely: the new queen is defended or out of the black king's reach
+    let promotion = pawn + 8;
+    if white_to_move
+        && rank_of(pawn) == 6
+        && promotion != white_king
+        && promotion != black_king
+        && (distance(black_king, promotion) > 1 || distance(white_king, promotion) == 1)
+    {
+        return Outcome::Win;
+    }
+
+    if !white_to_move {
+        let escapes = king_steps(black_king)
+            .filter(|&to| distance(to, white_king) > 1 && !pawn_attacks(pawn, to))
+            .count();
+        let in_check = pawn_attacks(pawn, black_king);
+        // Black takes the undefended pawn, or has no move at all
+        if distance(black_king, pawn) == 1 && distance(white_king, pawn) > 1 {
+            return Outcome::Draw;
+        }
+        if escapes == 0 {
+            return if in_check { Outcome::Win } else { Outcome::Draw };
+        }
+    }
+    Outcome::Unknown
+}
+
+/// Classifies an unknown position from its successors
+fn classify(db: &[Outcome], white_to_move: bool, black_king: usize, white_king: usize, pawn: usize) -> Outcome {
+    let mut successors = Vec::with_capacity(10);
+    if white_to_move {
+        successors.extend(king_steps(white_king).map(|to| db[index(false, black_king, to, pawn)]));
+        // Pawn pushes; a push to the last rank is already classified as a win or not at all
+        let single = pawn + 8;
+        if rank_of(pawn) < 6 && single != white_king && single != black_king {
+            successors.push(db[index(false, black_king, white_king, single)]);
+            let double = single + 8;
+            if rank_of(pawn) == 1 && double != white_king && double != black_king {
+                successors.push(db[index(false, black_king, white_king, double)]);
+            }
+        }
+    } else {
+        successors.extend(king_steps(black_king).map(|to| db[index(true, to, white_king, pawn)]));
+    }
+
+    // Each side looks for its good outcome; with none reachable and nothing unknown it gets the bad one
+    let (good, bad) = if white_to_move { (Outcome::Win, Outcome::Draw) } else { (Outcome::Draw, Outcome::Win) };
+    if successors.contains(&good) {
+        good
+    } else if successors.contains(&Outcome::Unknown) {
+        Outcome::Unknown
+    } else {
+        bad
+    }
+}
+
+/// Runs the retrograde analysis until no position changes, then packs the wins into bits
+fn generate() -> Vec<u64> {
+    let mut db = vec![Outcome::Invalid; POSITION_COUNT];
+    let mut positions = Vec::with_capacity(POSITION_COUNT);
+    for white_to_move in [false, true] {
+        for black_king in 0..64 {
+            for white_king in 0..64 {
+                for pawn_index in 0..24 {
+                    let pawn = (pawn_index / 4 + 1) * 8 + pawn_index % 4;
+                    let position = (white_to_move, black_king, white_king, pawn);
+                    db[index(white_to_move, black_king, white_king, pawn)] = initial_outcome(white_to_move, black_king, white_king, pawn);
+                    positions.push(position);
+                }
+            }
+        }
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(white_to_move, black_king, white_king, pawn) in &positions {
+            let i = index(white_to_move, black_king, white_king, pawn);
+            if db[i] == Outcome::Unknown {
+                let outcome = classify(&db, white_to_move, black_king, white_king, pawn);
+                if outcome != Outcome::Unknown {
+                    db[i] = outcome;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut bits = vec![0u64; POSITION_COUNT.div_ceil(64)];
+    for (i, &outcome) in db.iter().enumerate() {
+        if outcome == Outcome::Win {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+    bits
+}
+
+/// True if the side with the pawn wins; squares as in this module, pawn side = White,
+/// any pawn file (positions with the pawn on files e-h are mirrored)
+pub fn probe(white_to_move: bool, white_king: usize, pawn: usize, black_king: usize) -> bool {
+    let mirror = |square: usize| if file_of(pawn) > 3 { square ^ 7 } else { square };
+    let i = index(white_to_move, mirror(black_king), mirror(white_king), mirror(pawn));
+    let bits = BITBASE.get_or_init(generate);
+    bits[i / 64] & (1 << (i % 64)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Square number of a name like "e4"
+    fn square(name: &str) -> usize {
+        let bytes = name.as_bytes();
+        (bytes[1] - b'1') as usize * 8 + (bytes[0] - b'a') as usize
+    }
+
+    /// Probes with White to move and with Black to move
+    fn outcomes(white_king: &str, pawn: &str, black_king: &str) -> (bool, bool) {
+        let (white_king, pawn, black_king) = (square(white_king), square(pawn), square(black_king));
+        (probe(true, white_king, pawn, black_king), probe(false, white_king, pawn, black_king))
+    }
+
+    #[test]
+    fn rook_pawn_is_drawn_with_the_king_in_front() {
+        assert_eq!(outcomes("c3", "a4", "a8"), (false, false));
+        assert_eq!(outcomes("f6", "h5", "h8"), (false, false));
+        // With the defending king cut off the pawn queens
+        assert_eq!(outcomes("b7", "a5", "d7"), (true, true));
+    }
+
+    #[test]
+    fn opposition_decides() {
+        // Whoever has to move loses the opposition
+        assert_eq!(outcomes("e5", "e4", "e7"), (false, true));
+        assert_eq!(outcomes("d5", "d4", "d7"), (false, true));
+        // A king on the sixth rank in front of the pawn wins whoever moves
+        assert_eq!(outcomes("e6", "e5", "e8"), (true, true));
+        // Pawn in front of its king with the defender in opposition: drawn either way
+        assert_eq!(outcomes("e3", "e4", "e5"), (false, false));
+    }
+
+    #[test]
+    fn pawn_outside_the_square_outruns_the_king() {
+        // The black king reaches the square of the pawn only if it moves first
+        assert_eq!(outcomes("a1", "h5", "d4"), (true, false));
+        assert_eq!(outcomes("a1", "h5", "c4"), (true, true));
+        // Mirrored files
+        assert_eq!(outcomes("h1", "a5", "e4"), (true, false));
+    }
+}
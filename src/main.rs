@@ -1,9 +1,63 @@
-use eframe::{egui, App, Frame, NativeOptions, egui::ViewportBuilder};
+use eframe::{egui, App, Frame, NativeOptions};
 use egui::Vec2;
+use std::process::ExitCode;
+
+mod analysis_panel;
+mod annotate;
+mod bench;
+mod board_widget;
+mod book;
+mod book_builder;
+mod book_panel;
+mod datagen;
+mod dtm;
+mod endgame;
+mod engine;
+mod engine_player;
+mod epd;
+mod eval;
+mod eval_panel;
+mod fen;
+mod king_safety;
+mod kpk;
+mod mate;
+mod move_list;
+mod movegen;
+mod nnue;
+mod params;
+mod pawns;
+mod pgn;
+mod puzzle;
+mod puzzle_trainer;
+mod rng;
+mod san;
+mod search;
+mod selfplay;
+mod skill;
+mod sprt;
+mod syzygy;
+mod tablegen;
+mod take_back;
+mod tournament;
+mod tt;
+mod tuner;
+mod uci;
+mod uci_client;
+mod zobrist;
+
+use analysis_panel::AnalysisPanel;
+use board_widget::{BoardWidget, Overlay};
+use book_panel::BookPanel;
+use dtm::Dtm;
+use engine_player::{format_score_pawns, EnginePlayer, GameSetup, PlayerKind};
+use move_list::MoveList;
+use movegen::{Move, UndoInfo};
+use puzzle_trainer::{PuzzleAction, PuzzleTrainer};
+use take_back::TakeBacks;
 
 /// Enum representing all possible chess pieces and empty squares
 /// Each piece has a color variant (White/Black)
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum Piece {
     Empty,
     PawnWhite,
@@ -23,20 +77,14 @@ enum Piece {
 impl Piece {
     /// Returns true if the piece is white
     fn is_white(&self) -> bool {
-        match self {
-            Piece::PawnWhite | Piece::RookWhite | Piece::KnightWhite | 
-            Piece::BishopWhite | Piece::QueenWhite | Piece::KingWhite => true,
-            _ => false,
-        }
+        matches!(self, Piece::PawnWhite | Piece::RookWhite | Piece::KnightWhite |
+                       Piece::BishopWhite | Piece::QueenWhite | Piece::KingWhite)
     }
 
     /// Returns true if the piece is black
     fn is_black(&self) -> bool {
-        match self {
-            Piece::PawnBlack | Piece::RookBlack | Piece::KnightBlack | 
-            Piece::BishopBlack | Piece::QueenBlack | Piece::KingBlack => true,
-            _ => false,
-        }
+        matches!(self, Piece::PawnBlack | Piece::RookBlack | Piece::KnightBlack |
+                       Piece::BishopBlack | Piece::QueenBlack | Piece::KingBlack)
     }
 
     /// Returns true if the square is empty
@@ -48,11 +96,45 @@ impl Piece {
     fn is_same_color(&self, other: &Piece) -> bool {
         (self.is_white() && other.is_white()) || (self.is_black() && other.is_black())
     }
+
+    /// Returns true if the piece belongs to the given side
+    fn is_color(&self, white: bool) -> bool {
+        if white { self.is_white() } else { self.is_black() }
+    }
+
+    /// Returns a 0..12 index (white pieces first) used by hashing and evaluation tables
+    /// Only defined for real pieces: callers index by the piece standing on a square or being moved
+    fn index(&self) -> usize {
+        debug_assert!(*self != Piece::Empty, "an empty square has no piece index");
+        *self as usize - 1
+    }
+
+    /// Returns the piece type as 0..6 (pawn, knight, bishop, rook, queen, king)
+    fn type_index(&self) -> usize {
+        match self {
+            Piece::PawnWhite | Piece::PawnBlack => 0,
+            Piece::KnightWhite | Piece::KnightBlack => 1,
+            Piece::BishopWhite | Piece::BishopBlack => 2,
+            Piece::RookWhite | Piece::RookBlack => 3,
+            Piece::QueenWhite | Piece::QueenBlack => 4,
+            Piece::KingWhite | Piece::KingBlack => 5,
+            Piece::Empty => 6,
+        }
+    }
+
+    /// Builds a piece from its type index (see `type_index`) and color
+    fn from_type_index(type_index: usize, white: bool) -> Piece {
+        const WHITE: [Piece; 6] = [Piece::PawnWhite, Piece::KnightWhite, Piece::BishopWhite,
+                                   Piece::RookWhite, Piece::QueenWhite, Piece::KingWhite];
+        const BLACK: [Piece; 6] = [Piece::PawnBlack, Piece::KnightBlack, Piece::BishopBlack,
+                                   Piece::RookBlack, Piece::QueenBlack, Piece::KingBlack];
+        if white { WHITE[type_index] } else { BLACK[type_index] }
+    }
 }
 
 /// Struct to track game state for special moves (castling, en passant)
 /// This is necessary to enforce chess rules properly
-#[derive(Clone)]
+#[derive(Copy, Clone, Debug, Default)]
 struct GameState {
     // Castling rights - track if kings and rooks have moved
     white_king_moved: bool,
@@ -66,18 +148,15 @@ struct GameState {
     en_passant_target: Option<(usize, usize)>,
 }
 
-impl Default for GameState {
-    fn default() -> Self {
-        // Initial game state - no pieces have moved yet
-        GameState {
-            white_king_moved: false,
-            black_king_moved: false,
-            white_rook_queenside_moved: false,
-            white_rook_kingside_moved: false,
-            black_rook_queenside_moved: false,
-            black_rook_kingside_moved: false,
-            en_passant_target: None,
-        }
+impl GameState {
+    /// Packs the remaining castling rights into bits: 1 = K, 2 = Q, 4 = k, 8 = q
+    fn castling_rights(&self) -> u8 {
+        let mut rights = 0;
+        if !self.white_king_moved && !self.white_rook_kingside_moved { rights |= 1; }
+        if !self.white_king_moved && !self.white_rook_queenside_moved { rights |= 2; }
+        if !self.black_king_moved && !self.black_rook_kingside_moved { rights |= 4; }
+        if !self.black_king_moved && !self.black_rook_queenside_moved { rights |= 8; }
+        rights
     }
 }
 
@@ -86,6 +165,10 @@ struct Board {
     squares: [[Piece; 8]; 8],  // 8x8 chess board
     white_to_move: bool,       // Whose turn it is
     game_state: GameState,     // Special move tracking
+    halfmove_clock: u32,       // Moves since the last capture or pawn move (fifty-move rule)
+    fullmove_number: u32,      // Starts at 1, incremented after Black's move
+    hash: u64,                 // Zobrist key of the position, updated incrementally
+    history: Vec<UndoInfo>,    // Moves played so far, used by unmake_move and repetition detection
 }
 
 impl Board {
@@ -104,31 +187,17 @@ impl Board {
             [PawnWhite; 8],     // White pawns on rank 2
             [RookWhite, KnightWhite, BishopWhite, QueenWhite, KingWhite, BishopWhite, KnightWhite, RookWhite],
         ];
-        Board { 
-            squares, 
+        let mut board = Board {
+            squares,
             white_to_move: true,  // White moves first
             game_state: GameState::default(),
-        }
-    }
-
-    /// Returns the Unicode symbol for each piece type
-    /// Uses Unicode chess symbols for visual representation
-    fn piece_symbol(p: Piece) -> &'static str {
-        match p {
-            Piece::Empty => "·",
-            Piece::PawnWhite => "♙",
-            Piece::PawnBlack => "♟",
-            Piece::RookWhite => "♖",
-            Piece::RookBlack => "♜",
-            Piece::KnightWhite => "♘",
-            Piece::KnightBlack => "♞",
-            Piece::BishopWhite => "♗",
-            Piece::BishopBlack => "♝",
-            Piece::QueenWhite => "♕",
-            Piece::QueenBlack => "♛",
-            Piece::KingWhite => "♔",
-            Piece::KingBlack => "♚",
-        }
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            history: Vec::new(),
+        };
+        board.hash = board.compute_hash();
+        board
     }
 
     /// Main move validation function - checks if a move is legal
@@ -332,7 +401,7 @@ impl Board {
 
         // King cannot castle through check - verify king's path is safe
         // This includes the king's current square, transit square, and destination
-        for col in 4..=to_col.max(4).min(6) {
+        for col in 4..=to_col.clamp(4, 6) {
             if self.is_square_under_attack(expected_row, col, !is_white) {
                 return false;
             }
@@ -412,10 +481,8 @@ impl Board {
                 }
                 
                 // Check if this piece belongs to the attacking color and can attack the target square
-                if (by_white && piece.is_white()) || (!by_white && piece.is_black()) {
-                    if self.can_piece_attack(piece, r, c, row, col) {
-                        return true;
-                    }
+                if piece.is_color(by_white) && self.can_piece_attack(piece, r, c, row, col) {
+                    return true;
                 }
             }
         }
@@ -467,74 +534,28 @@ impl Board {
     }
 
     /// Executes a validated move and handles all special cases
-    /// This is the main move execution function
-    fn make_move(&mut self, from_row: usize, from_col: usize, to_row: usize, to_col: usize) -> bool {
+    /// This is the main move execution function; a pawn reaching the last rank becomes `promotion`,
+    /// a queen when None
+    fn make_move(&mut self, from_row: usize, from_col: usize, to_row: usize, to_col: usize, promotion: Option<Piece>) -> bool {
         // Validate the move first
         if !self.is_valid_move(from_row, from_col, to_row, to_col) {
             return false;
         }
 
         let piece = self.squares[from_row][from_col];
-        
-        // Handle en passant captures
-        if matches!(piece, Piece::PawnWhite | Piece::PawnBlack) {
-            if let Some((ep_row, ep_col)) = self.game_state.en_passant_target {
-                if to_row == ep_row && to_col == ep_col {
-                    // Remove the captured pawn (not on the destination square)
-                    let captured_pawn_row = if piece.is_white() { ep_row + 1 } else { ep_row - 1 };
-                    self.squares[captured_pawn_row][ep_col] = Piece::Empty;
-                }
-            }
-            
-            // Set en passant target for next turn if pawn moves two squares
-            let row_diff = (to_row as i32 - from_row as i32).abs();
-            if row_diff == 2 {
-                let ep_row = if piece.is_white() { from_row - 1 } else { from_row + 1 };
-                self.game_state.en_passant_target = Some((ep_row, from_col));
-            } else {
-                self.game_state.en_passant_target = None;
-            }
-        } else {
-            // Clear en passant if it's not a pawn move
-            self.game_state.en_passant_target = None;
-        }
-
-        // Handle castling - move the rook as well
-        if matches!(piece, Piece::KingWhite | Piece::KingBlack) {
-            let col_diff = (to_col as i32 - from_col as i32).abs();
-            if col_diff == 2 {
-                // This is a castling move
-                let is_kingside = to_col == 6;
-                let rook_from_col = if is_kingside { 7 } else { 0 };
-                let rook_to_col = if is_kingside { 5 } else { 3 };
-                
-                // Move the rook to its new position
-                let rook_piece = self.squares[from_row][rook_from_col];
-                self.squares[from_row][rook_to_col] = rook_piece;
-                self.squares[from_row][rook_from_col] = Piece::Empty;
-            }
-        }
-
-        // Update game state to track piece movements (for castling rights)
-        self.update_game_state_after_move(piece, from_row, from_col);
-
-        // Execute the main move
-        self.squares[to_row][to_col] = piece;
-        self.squares[from_row][from_col] = Piece::Empty;
-
-        // Handle pawn promotion
-        if matches!(piece, Piece::PawnWhite | Piece::PawnBlack) {
-            if (piece.is_white() && to_row == 0) || (piece.is_black() && to_row == 7) {
-                // Automatically promote to queen (simplification)
-                self.squares[to_row][to_col] = if piece.is_white() { Piece::QueenWhite } else { Piece::QueenBlack };
-            }
+        let mut mv = Move::new(from_row, from_col, to_row, to_col);
+        if self.is_promotion(from_row, from_col, to_row) {
+            mv.promotion = Some(promotion.unwrap_or(if piece.is_white() { Piece::QueenWhite } else { Piece::QueenBlack }));
         }
-
-        // Switch turns
-        self.white_to_move = !self.white_to_move;
+        self.apply_move(mv);
         true
     }
 
+    /// Whether the piece on (from_row, from_col) is a pawn that promotes on `to_row`
+    fn is_promotion(&self, from_row: usize, from_col: usize, to_row: usize) -> bool {
+        matches!(self.squares[from_row][from_col], Piece::PawnWhite | Piece::PawnBlack) && (to_row == 0 || to_row == 7)
+    }
+
     /// Updates game state flags after a move (for castling rights tracking)
     fn update_game_state_after_move(&mut self, piece: Piece, from_row: usize, from_col: usize) {
         match piece {
@@ -572,67 +593,13 @@ impl Board {
     /// Determines if the current player is in checkmate
     /// Checkmate = in check AND no legal moves available
     fn is_checkmate(&self) -> bool {
-        // Must be in check to be checkmate
-        if !self.is_in_check(self.white_to_move) {
-            return false;
-        }
-
-        // Try all possible moves to see if any can escape check
-        for from_row in 0..8 {
-            for from_col in 0..8 {
-                let piece = self.squares[from_row][from_col];
-                if piece.is_empty() {
-                    continue;
-                }
-                
-                // Only consider pieces belonging to the current player
-                if (self.white_to_move && piece.is_white()) || (!self.white_to_move && piece.is_black()) {
-                    // Try all possible destinations
-                    for to_row in 0..8 {
-                        for to_col in 0..8 {
-                            if self.is_valid_move(from_row, from_col, to_row, to_col) {
-                                return false;  // Found a legal move, not checkmate
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        
-        true  // No legal moves found while in check = checkmate
+        self.is_in_check(self.white_to_move) && self.legal_moves().is_empty()
     }
 
     /// Determines if the game is in stalemate
     /// Stalemate = NOT in check but no legal moves available
     fn is_stalemate(&self) -> bool {
-        // Cannot be stalemate if in check
-        if self.is_in_check(self.white_to_move) {
-            return false;
-        }
-
-        // Check if any legal moves are available
-        for from_row in 0..8 {
-            for from_col in 0..8 {
-                let piece = self.squares[from_row][from_col];
-                if piece.is_empty() {
-                    continue;
-                }
-                
-                // Only consider pieces belonging to the current player
-                if (self.white_to_move && piece.is_white()) || (!self.white_to_move && piece.is_black()) {
-                    // Try all possible destinations
-                    for to_row in 0..8 {
-                        for to_col in 0..8 {
-                            if self.is_valid_move(from_row, from_col, to_row, to_col) {
-                                return false;  // Found a legal move, not stalemate
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        
-        true  // No legal moves available while not in check = stalemate
+        !self.is_in_check(self.white_to_move) && self.legal_moves().is_empty()
     }
 }
 
@@ -642,18 +609,43 @@ impl Clone for Board {
         Board {
             squares: self.squares,
             white_to_move: self.white_to_move,
-            game_state: self.game_state.clone(),
+            game_state: self.game_state,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            hash: self.hash,
+            history: self.history.clone(),
         }
     }
 }
 
+/// Board overlay colours (premultiplied alpha)
+const LAST_MOVE_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(64, 82, 0, 105);
+const SELECTED_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(10, 43, 15, 128);
+const HINT_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(125, 100, 0, 160);
+const TARGET_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(9, 37, 13, 110);
+const CHECK_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(157, 0, 0, 200);
+const ENGINE_ARROW_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(0, 28, 80, 150);
+
 /// Main application struct for the GUI chess game
 struct ChessApp {
     board: Board,                              // The chess board state
     selected: Option<(usize, usize)>,          // Currently selected square (row, col)
     game_over: bool,                           // Whether the game has ended
     status_message: String,                    // Status/error messages to display
-    square_rects: [[egui::Rect; 8]; 8],       // GUI rectangles for each board square (unused in current implementation)
+    engine_status: String,                     // Notes about the engine's last move (e.g. tablebase use)
+    dragging: Option<(usize, usize)>,          // Square of the piece being dragged
+    drop_target: Option<(usize, usize)>,       // Square under the dragged piece
+    promotion: Option<((usize, usize), (usize, usize))>,  // Pawn move waiting for the user to choose the new piece
+    flipped: bool,                             // Black at the bottom of the board
+    show_coordinates: bool,                    // Rank and file labels on the board
+    setup: GameSetup,                          // Human/engine players and engine strength
+    setup_dialog: Option<GameSetup>,           // Settings being edited while the setup dialog is open
+    engine_player: EnginePlayer,               // Background search for engine-controlled sides
+    book_panel: BookPanel,                     // Opening book shown beside the board, also used by the engine
+    analysis_panel: AnalysisPanel,             // Annotation of the game played so far
+    puzzle_trainer: PuzzleTrainer,             // Puzzle mode: the board shows puzzles instead of a game
+    move_list: MoveList,                       // Moves of the game, with variations, for going through it
+    take_backs: TakeBacks,                     // Moves taken back that can be replayed
 }
 
 impl Default for ChessApp {
@@ -664,7 +656,20 @@ impl Default for ChessApp {
             selected: None,                     // No square selected initially
             game_over: false,                   // Game is active
             status_message: String::new(),      // No status message
-            square_rects: [[egui::Rect::NOTHING; 8]; 8],  // Initialize empty rectangles
+            engine_status: String::new(),
+            dragging: None,                     // No piece picked up
+            drop_target: None,
+            promotion: None,
+            flipped: false,
+            show_coordinates: true,
+            setup: GameSetup::default(),        // Two human players until configured
+            setup_dialog: None,
+            engine_player: EnginePlayer::default(),
+            book_panel: BookPanel::default(),
+            analysis_panel: AnalysisPanel::default(),
+            puzzle_trainer: PuzzleTrainer::default(),
+            move_list: MoveList::default(),
+            take_backs: TakeBacks::default(),
         }
     }
 }
@@ -673,6 +678,77 @@ impl Default for ChessApp {
 impl App for ChessApp {
     /// Main update function called every frame by the GUI framework
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        // Take-back shortcuts, unless a text field is being edited (it has its own undo)
+        if ctx.memory(|memory| memory.focus().is_none()) {
+            if ctx.input_mut(|input| input.consume_key(egui::Modifiers::COMMAND, egui::Key::Z)) {
+                self.take_back();
+            }
+            if ctx.input_mut(|input| input.consume_key(egui::Modifiers::COMMAND, egui::Key::Y)) {
+                self.redo_move();
+            }
+        }
+        self.move_list.sync(&self.board);
+        self.puzzle_trainer.update(&mut self.board, ctx);
+        self.update_engine(ctx);
+        self.show_setup_dialog(ctx);
+        self.show_promotion_dialog(ctx);
+
+        egui::SidePanel::left("move_list").show(ctx, |ui| {
+            ui.heading("Mosse");
+            // A puzzle keeps its own position on the board
+            let can_browse = !self.puzzle_trainer.is_active() && self.dragging.is_none();
+            if let Some(board) = self.move_list.ui(ui, can_browse) {
+                self.show_position(board);
+            }
+        });
+
+        egui::SidePanel::right("book_panel").show(ctx, |ui| {
+            let human_to_move = self.setup.player(self.board.white_to_move) == PlayerKind::Human;
+            let can_play = !self.game_over && human_to_move && !self.puzzle_trainer.is_active() && self.may_branch() && self.promotion.is_none();
+            if let Some(mv) = self.book_panel.ui(ui, &self.board, can_play) {
+                self.play_move(mv);
+            }
+            ui.separator();
+            egui::CollapsingHeader::new("Valutazione").show(ui, |ui| eval_panel::ui(ui, &self.board));
+            egui::CollapsingHeader::new("Analisi").show(ui, |ui| self.analysis_panel.ui(ui, &self.board));
+            let puzzle = egui::CollapsingHeader::new("Puzzle").show(ui, |ui| self.puzzle_trainer.ui(ui));
+            match puzzle.body_returned.flatten() {
+                Some(PuzzleAction::Show(board)) => self.show_puzzle(board),
+                Some(PuzzleAction::Leave) => self.new_game(),
+                None => {},
+            }
+        });
+
+        // Buttons and instructions below the board
+        egui::TopBottomPanel::bottom("controls").show(ctx, |ui| {
+            // New Game and game setup buttons
+            ui.horizontal(|ui| {
+                if ui.button("Nuova Partita").clicked() {
+                    self.new_game();
+                }
+                if ui.button("Impostazioni partita").clicked() {
+                    self.setup_dialog = Some(self.setup.clone());
+                }
+                let can_take_back = self.can_take_back();
+                if ui.add_enabled(can_take_back && !self.board.history.is_empty(), egui::Button::new("Ritira (Ctrl+Z)")).clicked() {
+                    self.take_back();
+                }
+                if ui.add_enabled(can_take_back && self.take_backs.can_redo(&self.board), egui::Button::new("Rigioca (Ctrl+Y)")).clicked() {
+                    self.redo_move();
+                }
+                ui.checkbox(&mut self.flipped, "Capovolgi scacchiera");
+                ui.checkbox(&mut self.show_coordinates, "Coordinate");
+            });
+            
+            // Display instructions for the user
+            ui.separator();
+            ui.label("Istruzioni:");
+            ui.label("• Click per selezionare un pezzo, poi click sulla casella di destinazione");
+            ui.label("• Oppure trascina il pezzo sulla casella di destinazione");
+            ui.label("• Tasti freccia o click sulle mosse a sinistra per rivedere la partita");
+            ui.label("• Ctrl+Z ritira l'ultima mossa (contro il motore anche la sua risposta), Ctrl+Y la rigioca");
+        });
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("GK Chess Engine");
             
@@ -685,6 +761,32 @@ impl App for ChessApp {
                     ui.label(format!("Turno: {}", current_player));
                 }
             }
+
+            // Engine progress: thinking indicator and "move now" button
+            if self.engine_player.is_thinking() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Il motore sta pensando…");
+                    if let Some(info) = self.engine_player.info() {
+                        let best = info.pv.first().map(|mv| mv.to_uci()).unwrap_or_default();
+                        ui.label(format!("profondità {}, valutazione {}, mossa {}", info.depth, format_score_pawns(info.score), best));
+                        if info.tbhits > 0 {
+                            ui.label(format!("tablebase: {} accessi", info.tbhits));
+                        }
+                    }
+                    if ui.button("Muovi ora").clicked() {
+                        self.engine_player.move_now();
+                    }
+                });
+            } else if let Some(expected) = self.engine_player.pondering_on() {
+                ui.label(format!("Il motore medita sulla risposta {}…", expected.to_uci()));
+            }
+            if !self.engine_status.is_empty() {
+                ui.label(&self.engine_status);
+            }
+            if let Some(dtm) = self.engine_player.dtm_tables().and_then(|tables| tables.probe(&self.board)) {
+                ui.label(format!("Tablebase DTM: {}", describe_dtm(dtm, self.board.white_to_move)));
+            }
             
             // Display any status or error messages
             if !self.status_message.is_empty() {
@@ -693,79 +795,325 @@ impl App for ChessApp {
 
             ui.separator();
 
-            // Variable to track potential drop targets (for future drag-and-drop implementation)
-            let mut drop_target: Option<(usize, usize)> = None;
-
-            // Main chess board GUI using a grid layout
-            let grid_response = egui::Grid::new("chess_board").spacing([2.0, 2.0]).show(ui, |ui| {
-                // Create 8x8 grid of buttons representing the chess board
-                for row in 0..8 {
-                    for col in 0..8 {
-                        let piece = self.board.squares[row][col];
-                        let is_light_square = (row + col) % 2 == 0;  // Checkerboard pattern
-                        
-                        // Create button text with chess piece symbol
-                        let mut piece_text = egui::RichText::new(Board::piece_symbol(piece))
-                            .size(50.0)
-                            .strong();
-                        
-                        let mut button = egui::Button::new(piece_text)
-                            .min_size(egui::Vec2::splat(65.0));
-
-                        // Set base square colors (light and dark squares)
-                        let base_color = if is_light_square {
-                            egui::Color32::from_rgb(240, 217, 181)  // Light beige
-                        } else {
-                            egui::Color32::from_rgb(181, 136, 99)   // Dark brown
-                        };
-
-                        button = button.fill(base_color);
-
-                        // Highlight the currently selected square
-                        if let Some((sr, sc)) = self.selected {
-                            if sr == row && sc == col {
-                                button = button.fill(egui::Color32::LIGHT_BLUE);
-                            }
-                        }
-
-                        // Highlight valid move destinations in green
-                        if let Some((sel_row, sel_col)) = self.selected {
-                            if self.board.is_valid_move(sel_row, sel_col, row, col) {
-                                button = button.fill(egui::Color32::LIGHT_GREEN);
-                            }
-                        }
-
-                        let response = ui.add(button);
-                        
-                        // Store the rectangle position for potential future use (drag-and-drop)
-                        self.square_rects[row][col] = response.rect;
-                        
-                        // Handle square clicks if game is not over
-                        if !self.game_over {
-                            if response.clicked() {
-                                self.handle_square_click(row, col);
-                            }
-                        }
+            // Squares, pieces and overlays: last move, puzzle hint, selection with its legal targets,
+            // king in check, drop target and the engine's current best move
+            let mut overlays = Vec::new();
+            if let Some(undo) = self.board.history.last() {
+                overlays.push(Overlay::Highlight((undo.mv.from_row, undo.mv.from_col), LAST_MOVE_COLOR));
+                overlays.push(Overlay::Highlight((undo.mv.to_row, undo.mv.to_col), LAST_MOVE_COLOR));
+            }
+            if let Some(square) = self.puzzle_trainer.hint_square() {
+                overlays.push(Overlay::Highlight(square, HINT_COLOR));
+            }
+            if let Some((sel_row, sel_col)) = self.selected {
+                overlays.push(Overlay::Highlight((sel_row, sel_col), SELECTED_COLOR));
+                for (row, col) in (0..8).flat_map(|row| (0..8).map(move |col| (row, col))) {
+                    if self.board.is_valid_move(sel_row, sel_col, row, col) {
+                        overlays.push(Overlay::Dot((row, col), TARGET_COLOR));
                     }
-                    ui.end_row();  // End this row of the grid
                 }
-            });
+            }
+            if self.board.is_in_check(self.board.white_to_move) {
+                if let Some(king) = self.board.find_king(self.board.white_to_move) {
+                    overlays.push(Overlay::Circle(king, CHECK_COLOR));
+                }
+            }
+            if let (Some((from_row, from_col)), Some((row, col))) = (self.dragging, self.drop_target) {
+                if self.board.is_valid_move(from_row, from_col, row, col) {
+                    overlays.push(Overlay::Circle((row, col), TARGET_COLOR));
+                }
+            }
+            if let Some(mv) = self.engine_player.info().filter(|_| self.engine_player.is_thinking()).and_then(|info| info.pv.first()) {
+                overlays.push(Overlay::Arrow((mv.from_row, mv.from_col), (mv.to_row, mv.to_col), ENGINE_ARROW_COLOR));
+            }
 
-            // New Game button
-            ui.separator();
-            if ui.button("Nuova Partita").clicked() {
-                *self = ChessApp::default();  // Reset to initial state
+            // The board takes the rest of the panel
+            let widget = BoardWidget::new(self.board.piece_letters())
+                .overlays(&overlays)
+                .flipped(self.flipped)
+                .coordinates(self.show_coordinates)
+                .dragging(self.dragging);
+            let board = widget.show(ui);
+            self.drop_target = board.hovered.filter(|_| self.dragging.is_some());
+
+            // Handle square clicks if game is not over and a human is to move (in puzzle mode, when
+            // the puzzle waits for the user's move)
+            let human_to_move = self.setup.player(self.board.white_to_move) == PlayerKind::Human;
+            let can_move = self.promotion.is_none() && if self.puzzle_trainer.is_active() {
+                self.puzzle_trainer.accepts_moves()
+            } else {
+                !self.game_over && human_to_move && self.may_branch()
+            };
+            if let Some((row, col)) = board.clicked.filter(|_| can_move) {
+                self.handle_square_click(row, col);
+            }
+
+            // Drag-and-drop: pick up a piece of the side to move, play it where it is dropped
+            if let Some((row, col)) = board.drag_started.filter(|_| can_move) {
+                if self.board.squares[row][col].is_color(self.board.white_to_move) {
+                    self.dragging = Some((row, col));
+                    self.selected = Some((row, col));
+                    self.status_message.clear();
+                }
+            }
+            if board.drag_released {
+                if let Some(from) = self.dragging.take() {
+                    self.drop_piece(from, board.hovered);
+                }
             }
-            
-            // Display instructions for the user
-            ui.separator();
-            ui.label("Istruzioni:");
-            ui.label("• Click per selezionare un pezzo, poi click sulla casella di destinazione");
         });
     }
 }
 
 impl ChessApp {
+    /// Resets the board for a new game, keeping the current players and settings (and leaves puzzle mode)
+    fn new_game(&mut self) {
+        self.show_puzzle(Board::new());
+        self.puzzle_trainer.leave();
+    }
+
+    /// Sets up the position of a puzzle; the engine stays silent until normal play resumes
+    fn show_puzzle(&mut self, board: Board) {
+        self.move_list.reset(&board);
+        self.show_position(board);
+    }
+
+    /// Puts a position on the board, e.g. one picked from the move list; its moves are kept as history
+    fn show_position(&mut self, board: Board) {
+        self.engine_player.cancel();
+        self.board = board;
+        self.selected = None;
+        self.dragging = None;
+        self.drop_target = None;
+        self.promotion = None;
+        self.take_backs.clear();
+        self.game_over = false;
+        self.engine_status.clear();
+        self.check_game_over();
+    }
+
+    /// Take-backs are allowed by the setup, outside puzzles and with a human playing (two engines would
+    /// replay the moves at once)
+    fn can_take_back(&self) -> bool {
+        let human_plays = self.setup.white == PlayerKind::Human || self.setup.black == PlayerKind::Human;
+        self.setup.take_backs && human_plays && !self.puzzle_trainer.is_active() && self.dragging.is_none()
+    }
+
+    /// Without take-backs no move can be played from an earlier position of the move list
+    fn may_branch(&self) -> bool {
+        self.move_list.may_branch(self.setup.take_backs)
+    }
+
+    /// Takes moves back until a human is to move
+    fn take_back(&mut self) {
+        if self.can_take_back() && self.take_backs.take_back(&mut self.board, &self.setup) {
+            self.after_take_back();
+        }
+    }
+
+    /// Replays the moves taken back, up to the next human turn
+    fn redo_move(&mut self) {
+        if self.can_take_back() && self.take_backs.redo(&mut self.board, &self.setup) {
+            self.after_take_back();
+        }
+    }
+
+    /// The engine forgets its search: the position it was about changed
+    fn after_take_back(&mut self) {
+        self.engine_player.cancel();
+        self.selected = None;
+        self.promotion = None;
+        self.game_over = false;
+        self.engine_status.clear();
+        self.check_game_over();
+    }
+
+    /// Shows the game setup dialog while it is open; starting a game applies the settings
+    fn show_setup_dialog(&mut self, ctx: &egui::Context) {
+        let Some(setup) = self.setup_dialog.as_mut() else { return };
+        let mut start = false;
+        let mut cancel = false;
+        egui::Window::new("Impostazioni partita")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                setup.ui(ui);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    start = ui.button("Inizia partita").clicked();
+                    cancel = ui.button("Annulla").clicked();
+                });
+            });
+        if start {
+            let setup = self.setup_dialog.take().unwrap_or_default();
+            let tablebases_changed = setup.syzygy_path != self.setup.syzygy_path;
+            let dtm_changed = setup.dtm_path != self.setup.dtm_path;
+            let network_changed = (setup.use_nnue, &setup.nnue_path) != (self.setup.use_nnue, &self.setup.nnue_path);
+            self.setup = setup;
+            self.new_game();
+            if tablebases_changed {
+                self.engine_status = match self.engine_player.load_tablebases(&self.setup.syzygy_path) {
+                    Ok(0) => String::new(),
+                    Ok(max_pieces) => format!("Tablebase Syzygy caricate (fino a {} pezzi)", max_pieces),
+                    Err(err) => err,
+                };
+            }
+            if dtm_changed {
+                self.engine_status = match self.engine_player.load_dtm_tables(&self.setup.dtm_path) {
+                    Ok(0) => String::new(),
+                    Ok(count) => format!("Tablebase DTM caricate: {}", count),
+                    Err(err) => err,
+                };
+            }
+            if network_changed {
+                self.engine_status = match self.engine_player.load_network(self.setup.use_nnue, &self.setup.nnue_path) {
+                    Ok(None) => "Valutazione classica".to_string(),
+                    Ok(Some(hidden)) => format!("Valutazione NNUE ({} neuroni nascosti)", hidden),
+                    Err(err) => format!("{}: resta la valutazione classica", err),
+                };
+            }
+        } else if cancel {
+            self.setup_dialog = None;
+        }
+    }
+
+    /// Plays finished engine moves and starts the engine when it is its turn
+    fn update_engine(&mut self, ctx: &egui::Context) {
+        // While an earlier position is viewed the engine waits for the user
+        if self.puzzle_trainer.is_active() || self.move_list.is_browsing() {
+            return;
+        }
+        if let Some(result) = self.engine_player.poll() {
+            if let Some(mv) = result.best_move.filter(|_| !self.game_over) {
+                self.engine_status = match result.tbhits {
+                    0 => String::new(),
+                    hits if result.nodes == 0 => format!("Mossa {} presa dalle tablebase ({} accessi)", mv.to_uci(), hits),
+                    hits => format!("Mossa {}: {} accessi alle tablebase durante la ricerca", mv.to_uci(), hits),
+                };
+                self.board.apply_move(mv);
+                self.selected = None;
+                self.check_game_over();
+
+                // Think on the human's time about the reply we expect
+                if !self.game_over && self.setup.ponders() {
+                    if let Some(expected) = result.ponder_move {
+                        self.engine_player.ponder(&self.board, expected, self.setup.limits(), ctx);
+                    }
+                }
+            }
+        }
+
+        let engine_to_move = self.setup.player(self.board.white_to_move) == PlayerKind::Engine;
+        if !self.game_over && engine_to_move && !self.engine_player.is_thinking() {
+            // In book the engine moves instantly
+            match self.book_panel.engine_move(&self.board) {
+                Some(mv) => self.play_move(mv),
+                None => self.engine_player.think(&self.board, self.setup.limits(), ctx),
+            }
+        }
+    }
+
+    /// Plays a legal move chosen outside the board (e.g. from the opening book)
+    fn play_move(&mut self, mv: Move) {
+        self.engine_player.opponent_moved(mv);
+        self.board.apply_move(mv);
+        self.selected = None;
+        self.check_game_over();
+    }
+
+    /// Checks for game ending conditions after a move and updates the status message
+    fn check_game_over(&mut self) {
+        if self.board.is_checkmate() {
+            let winner = if self.board.white_to_move { "Nero" } else { "Bianco" };
+            self.status_message = format!("SCACCO MATTO! {} vince!", winner);
+            self.game_over = true;
+        } else if self.board.is_stalemate() {
+            self.status_message = "STALLO! La partita è patta!".to_string();
+            self.game_over = true;
+        } else if self.board.repetition_count() >= 2 {
+            self.status_message = "PATTA per triplice ripetizione!".to_string();
+            self.game_over = true;
+        } else if self.board.halfmove_clock >= 100 {
+            self.status_message = "PATTA per la regola delle 50 mosse!".to_string();
+            self.game_over = true;
+        } else if self.board.has_insufficient_material() {
+            self.status_message = "PATTA per materiale insufficiente!".to_string();
+            self.game_over = true;
+        } else {
+            self.status_message.clear();  // Clear any previous messages
+        }
+        if self.game_over {
+            self.engine_player.cancel();
+        }
+    }
+
+    /// Ends a drag: a legal drop plays the move, anything else puts the piece back
+    fn drop_piece(&mut self, from: (usize, usize), to: Option<(usize, usize)>) {
+        match to {
+            // Dropped where it was picked up: it stays selected for a click on the target
+            Some(to) if to == from => {},
+            Some(to) if self.board.is_valid_move(from.0, from.1, to.0, to.1) => {
+                self.selected = Some(from);
+                self.handle_square_click(to.0, to.1);
+            },
+            _ => self.selected = None,
+        }
+    }
+
+    /// Follows up a move the user played on the board
+    fn after_user_move(&mut self) {
+        self.selected = None;
+
+        // A puzzle checks the move itself
+        if self.puzzle_trainer.is_active() {
+            self.puzzle_trainer.user_moved(&mut self.board);
+            return;
+        }
+
+        // Let a pondering engine know which move was played
+        if let Some(undo) = self.board.history.last() {
+            self.engine_player.opponent_moved(undo.mv);
+        }
+
+        // Check for game ending conditions
+        self.check_game_over();
+    }
+
+    /// Asks which piece a pawn reaching the last rank becomes, then plays the move
+    fn show_promotion_dialog(&mut self, ctx: &egui::Context) {
+        let Some(((from_row, from_col), (to_row, to_col))) = self.promotion else { return };
+        let white = self.board.white_to_move;
+        let choices = [
+            ("♕ Donna", Piece::QueenWhite, Piece::QueenBlack),
+            ("♖ Torre", Piece::RookWhite, Piece::RookBlack),
+            ("♗ Alfiere", Piece::BishopWhite, Piece::BishopBlack),
+            ("♘ Cavallo", Piece::KnightWhite, Piece::KnightBlack),
+        ];
+        let mut choice = None;
+        let mut cancel = false;
+        egui::Window::new("Promozione")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for (name, white_piece, black_piece) in choices {
+                        if ui.button(name).clicked() {
+                            choice = Some(if white { white_piece } else { black_piece });
+                        }
+                    }
+                });
+                cancel = ui.button("Annulla").clicked();
+            });
+        if let Some(piece) = choice {
+            self.promotion = None;
+            if self.board.make_move(from_row, from_col, to_row, to_col, Some(piece)) {
+                self.after_user_move();
+            }
+        } else if cancel {
+            self.promotion = None;
+        }
+    }
+
     /// Handles user clicks on board squares
     /// Implements the two-click interface: first click selects, second click moves
     fn handle_square_click(&mut self, row: usize, col: usize) {
@@ -776,21 +1124,12 @@ impl ChessApp {
                 self.selected = None;
             } else {
                 // Clicked on a different square - attempt to make a move
-                if self.board.make_move(from_row, from_col, row, col) {
-                    // Move was successful
+                if self.board.is_valid_move(from_row, from_col, row, col) && self.board.is_promotion(from_row, from_col, row) {
+                    // The promotion dialog plays the move once the user picks the new piece
+                    self.promotion = Some(((from_row, from_col), (row, col)));
                     self.selected = None;
-                    
-                    // Check for game ending conditions
-                    if self.board.is_checkmate() {
-                        let winner = if self.board.white_to_move { "Nero" } else { "Bianco" };
-                        self.status_message = format!("SCACCO MATTO! {} vince!", winner);
-                        self.game_over = true;
-                    } else if self.board.is_stalemate() {
-                        self.status_message = "STALLO! La partita è patta!".to_string();
-                        self.game_over = true;
-                    } else {
-                        self.status_message.clear();  // Clear any previous messages
-                    }
+                } else if self.board.make_move(from_row, from_col, row, col, None) {
+                    self.after_user_move();
                 } else {
                     // Move was invalid - try to select the new square instead
                     let piece = self.board.squares[row][col];
@@ -825,12 +1164,93 @@ impl ChessApp {
     }
 }
 
-fn main() {
+/// Exact outcome from the DTM tables, e.g. "il Bianco dà matto in 12 mosse"
+fn describe_dtm(dtm: Dtm, white_to_move: bool) -> String {
+    let name = |white: bool| if white { "il Bianco" } else { "il Nero" };
+    match dtm {
+        Dtm::Draw => "patta".to_string(),
+        Dtm::Loss(0) => format!("{} ha subito scacco matto", name(white_to_move)),
+        Dtm::Win(plies) => format!("{} dà matto in {} mosse", name(white_to_move), plies.div_ceil(2)),
+        Dtm::Loss(plies) => format!("{} dà matto in {} mosse", name(!white_to_move), plies / 2),
+    }
+}
+
+fn main() -> ExitCode {
+    // Subcommands run the engine tools, otherwise the GUI starts
+    let args: Vec<String> = std::env::args().collect();
+    let tool_result = match args.get(1).map(String::as_str) {
+        Some("uci") => {
+            uci::run();
+            Ok(())
+        },
+        Some("calibrate") => {
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            skill::run_calibration(&args[2..], threads)
+        },
+        Some("elo") => {
+            skill::print_elo_table();
+            Ok(())
+        },
+        Some("annotate") => annotate::run(&args[2..]),
+        Some("bench") => bench::run(&args[2..]),
+        Some("epd") => epd::run(&args[2..]),
+        Some("makebook") => book_builder::run(&args[2..]),
+        Some("eval") => {
+            // `eval [FEN]`: term-by-term evaluation, of the starting position by default
+            let fen = if args.len() > 2 { args[2..].join(" ") } else { fen::START_FEN.to_string() };
+            Board::from_fen(&fen).map(|board| println!("{}", eval::evaluate_trace(&board)))
+        },
+        Some("datagen") => datagen::run(&args[2..]),
+        Some("tune") => tuner::run(&args[2..]),
+        Some("tournament") => tournament::run(&args[2..]),
+        Some("mate") => mate::run(&args[2..]),
+        Some("puzzles") => puzzle::run(&args[2..]),
+        Some("gentb") => tablegen::run(&args[2..]),
+        _ => return run_gui(),
+    };
+    // A failed tool exits with an error status, so that scripts can tell
+    match tool_result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn run_gui() -> ExitCode {
     let native_options = NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size(Vec2::new(750.0, 900.0))
+            .with_inner_size(Vec2::new(1000.0, 900.0))
             .with_title("GK Chess Engine"),
         ..Default::default()
     };
-    eframe::run_native("GK Chess", native_options, Box::new(|_cc| Box::new(ChessApp::default())));
-}
\ No newline at end of file
+    match eframe::run_native("GK Chess", native_options, Box::new(|_cc| Box::new(ChessApp::default()))) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Errore avvio GUI: {}", err);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_moves_promote_to_the_chosen_piece() {
+        // A puzzle solution with an under-promotion compares the stored move with the one played
+        let start = Board::from_fen("8/P5k1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut board = start.clone();
+        assert!(board.is_promotion(1, 0, 0));
+        assert!(board.make_move(1, 0, 0, 0, Some(Piece::KnightWhite)));
+        assert_eq!(board.history.last().map(|undo| undo.mv), start.parse_uci_move("a7a8n"));
+        assert_eq!(board.squares[0][0], Piece::KnightWhite);
+
+        let mut board = start.clone();
+        assert!(board.make_move(1, 0, 0, 0, None));
+        assert_eq!(board.history.last().map(|undo| undo.mv), start.parse_uci_move("a7a8q"));
+        assert!(!board.is_promotion(7, 4, 6), "il re non viene promosso");
+    }
+}
@@ -0,0 +1,421 @@
+//! Mate solver for problems: direct mates, helpmates and selfmates in N moves, with a depth-first search
+//! for short problems and a proof-number search for longer direct mates: `gk_chess_engine mate`
+
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use crate::movegen::Move;
+use crate::pgn::format_line;
+use crate::Board;
+
+/// Direct mates longer than this are solved with the proof-number search
+const MAX_DEPTH_FIRST_MOVES: u32 = 3;
+/// Default node budget of the proof-number search, for each move of the problem position
+const DEFAULT_PNS_NODES: usize = 2_000_000;
+/// Helpmate solutions listed at most
+const MAX_HELPMATE_SOLUTIONS: usize = 50;
+/// Proof and disproof number of a node that can no longer be proved (or disproved)
+const INFINITE: u32 = u32::MAX;
+
+/// Kind of problem: who moves and who gets mated
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ProblemType {
+    Direct,    // The side to move mates in N against any defence
+    Helpmate,  // The side to move helps the opponent to mate it in N
+    Selfmate,  // The side to move forces the opponent to mate it in N
+}
+
+/// Exhaustive solver with a table of positions already settled
+#[derive(Default)]
+struct Solver {
+    solved: HashMap<(u64, u32), bool>,  // (position, moves) -> the side to move succeeds (one problem type per solver)
+    dead_ends: HashSet<(u64, u32)>,     // Helpmate positions without solutions, by plies left
+    nodes: u64,
+}
+
+impl Solver {
+    /// The side to move mates in at most `moves` moves
+    fn mates_in(&mut self, board: &mut Board, moves: u32) -> bool {
+        if let Some(&result) = self.solved.get(&(board.hash, moves)) {
+            return result;
+        }
+        let result = board.legal_moves().into_iter().any(|mv| self.forces_mate(board, mv, moves));
+        self.solved.insert((board.hash, moves), result);
+        result
+    }
+
+    /// After `mv` every defence is mated within `moves` moves of the attacker (`mv` included)
+    fn forces_mate(&mut self, board: &mut Board, mv: Move, moves: u32) -> bool {
+        self.nodes += 1;
+        board.apply_move(mv);
+        // Only a check can mate on the last move
+        let result = if moves == 1 && !board.in_check() {
+            false
+        } else {
+            let replies = board.legal_moves();
+            if replies.is_empty() {
+                board.in_check()
+            } else {
+                moves > 1
+                    && replies.into_iter().all(|reply| {
+                        board.apply_move(reply);
+                        let mated = self.mates_in(board, moves - 1);
+                        board.unmake_move();
+                        mated
+                    })
+            }
+        };
+        board.unmake_move();
+        result
+    }
+
+    /// The side to move forces the opponent to mate it in at most `moves` moves
+    fn selfmates_in(&mut self, board: &mut Board, moves: u32) -> bool {
+        if let Some(&result) = self.solved.get(&(board.hash, moves)) {
+            return result;
+        }
+        let result = board.legal_moves().into_iter().any(|mv| self.forces_selfmate(board, mv, moves));
+        self.solved.insert((board.hash, moves), result);
+        result
+    }
+
+    /// After `mv` every reply either mates the mover or leads to a selfmate in `moves - 1`
+    fn forces_selfmate(&mut self, board: &mut Board, mv: Move, moves: u32) -> bool {
+        self.nodes += 1;
+        board.apply_move(mv);
+        let replies = board.legal_moves();
+        // Mating or stalemating the opponent ends the game the wrong way
+        let result = !replies.is_empty()
+            && replies.into_iter().all(|reply| {
+                board.apply_move(reply);
+                let done = board.is_checkmate() || (moves > 1 && self.selfmates_in(board, moves - 1));
+                board.unmake_move();
+                done
+            });
+        board.unmake_move();
+        result
+    }
+
+    /// Collects in `solutions` every line of `plies` plies ending with the side to move at the start
+    /// checkmated
+    fn helpmates(&mut self, board: &mut Board, plies: u32, line: &mut Vec<Move>, solutions: &mut Vec<Vec<Move>>) {
+        if plies == 0 {
+            if board.is_checkmate() {
+                solutions.push(line.clone());
+            }
+            return;
+        }
+        if solutions.len() >= MAX_HELPMATE_SOLUTIONS || self.dead_ends.contains(&(board.hash, plies)) {
+            return;
+        }
+        let found = solutions.len();
+        for mv in board.legal_moves() {
+            self.nodes += 1;
+            board.apply_move(mv);
+            // Only a check can mate on the last ply
+            if plies > 1 || board.in_check() {
+                line.push(mv);
+                self.helpmates(board, plies - 1, line, solutions);
+                line.pop();
+            }
+            board.unmake_move();
+        }
+        if solutions.len() == found {
+            self.dead_ends.insert((board.hash, plies));
+        }
+    }
+
+    /// Shortest forced win (mate or selfmate) starting with `mv`, up to `moves` moves
+    fn shortest(&mut self, board: &mut Board, mv: Move, moves: u32, problem: ProblemType) -> Option<u32> {
+        (1..=moves).find(|&n| match problem {
+            ProblemType::Selfmate => self.forces_selfmate(board, mv, n),
+            _ => self.forces_mate(board, mv, n),
+        })
+    }
+
+    /// Main line of a direct mate in exactly `moves` starting with `key`: the defender holds out as
+    /// long as possible
+    fn main_line(&mut self, board: &Board, key: Move, moves: u32) -> Vec<Move> {
+        let mut board = board.clone();
+        let mut line = vec![key];
+        board.apply_move(key);
+        for left in (1..moves).rev() {
+            let replies = board.legal_moves();
+            let defence = replies.iter().copied().find(|&reply| {
+                board.apply_move(reply);
+                let holds = left == 1 || !self.mates_in(&mut board, left - 1);
+                board.unmake_move();
+                holds
+            });
+            let Some(defence) = defence.or(replies.first().copied()) else { break };
+            board.apply_move(defence);
+            let Some(attack) = board.legal_moves().into_iter().find(|&mv| self.forces_mate(&mut board, mv, left)) else { break };
+            board.apply_move(attack);
+            line.extend([defence, attack]);
+        }
+        line
+    }
+}
+
+/// Outcome of a proof-number search
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Proof {
+    Proven,
+    Disproven,
+    Unknown,  // The node budget ran out
+}
+
+/// A node of the proof-number search tree
+struct PnsNode {
+    mv: Option<Move>,      // Move leading here (None at the root)
+    parent: usize,
+    children: Vec<usize>,
+    proof: u32,            // Leaves still to prove for a mate
+    disproof: u32,         // Leaves still to prove for an escape
+    attacker: bool,        // The attacker is to move (OR node)
+    plies: u32,            // Plies left to the mate
+}
+
+/// Proof and disproof numbers of a new node; `attacker` tells who is to move
+fn initial_numbers(board: &Board, attacker: bool, plies: u32) -> (u32, u32) {
+    let moves = board.legal_moves().len() as u32;
+    if moves == 0 {
+        // A mated defender is the proof; a mated attacker or a stalemate refutes
+        return if !attacker && board.in_check() { (0, INFINITE) } else { (INFINITE, 0) };
+    }
+    if plies == 0 {
+        return (INFINITE, 0);
+    }
+    // Few defences are easier to prove, few attacking moves easier to refute
+    if attacker { (1, moves) } else { (moves, 1) }
+}
+
+/// Whether the attacker, not to move in `start`, mates within `plies` plies (defender moves included)
+fn proof_number_search(start: &Board, plies: u32, max_nodes: usize, nodes: &mut u64) -> Proof {
+    let (proof, disproof) = initial_numbers(start, false, plies);
+    let mut tree = vec![PnsNode { mv: None, parent: 0, children: Vec::new(), proof, disproof, attacker: false, plies }];
+    while tree[0].proof != 0 && tree[0].disproof != 0 && tree.len() < max_nodes {
+        // Most-proving node: the easiest child to prove under the attacker, to refute under the defender
+        let mut board = start.clone();
+        let mut index = 0;
+        while !tree[index].children.is_empty() {
+            let node = &tree[index];
+            index = *node
+                .children
+                .iter()
+                .min_by_key(|&&child| if node.attacker { tree[child].proof } else { tree[child].disproof })
+                .unwrap();
+            board.apply_move(tree[index].mv.unwrap());
+        }
+
+        let (attacker, plies) = (!tree[index].attacker, tree[index].plies - 1);
+        for mv in board.legal_moves() {
+            *nodes += 1;
+            board.apply_move(mv);
+            let (proof, disproof) = initial_numbers(&board, attacker, plies);
+            board.unmake_move();
+            tree.push(PnsNode { mv: Some(mv), parent: index, children: Vec::new(), proof, disproof, attacker, plies });
+            let child = tree.len() - 1;
+            tree[index].children.push(child);
+        }
+
+        // Back up the new numbers to the root
+        loop {
+            let node = &tree[index];
+            let proofs = node.children.iter().map(|&child| tree[child].proof);
+            let disproofs = node.children.iter().map(|&child| tree[child].disproof);
+            let (proof, disproof) = if node.attacker {
+                (proofs.min().unwrap(), disproofs.fold(0, u32::saturating_add))
+            } else {
+                (proofs.fold(0, u32::saturating_add), disproofs.min().unwrap())
+            };
+            tree[index].proof = proof;
+            tree[index].disproof = disproof;
+            if index == 0 {
+                break;
+            }
+            index = tree[index].parent;
+        }
+    }
+    match (tree[0].proof, tree[0].disproof) {
+        (0, _) => Proof::Proven,
+        (_, 0) => Proof::Disproven,
+        _ => Proof::Unknown,
+    }
+}
+
+/// "1 chiave", "3 chiavi"
+fn count(n: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", n, if n == 1 { singular } else { plural })
+}
+
+/// `mate <N> <FEN> [--helpmate | --selfmate] [--pns] [--nodes N]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "Uso: gk_chess_engine mate <N> <FEN> [--helpmate | --selfmate] [--pns] [--nodes N]";
+    let mut problem = ProblemType::Direct;
+    let mut force_pns = false;
+    let mut max_nodes = DEFAULT_PNS_NODES;
+    let mut positional = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--helpmate" => problem = ProblemType::Helpmate,
+            "--selfmate" => problem = ProblemType::Selfmate,
+            "--pns" => force_pns = true,
+            "--nodes" => {
+                let value = args.get(i + 1).ok_or_else(|| format!("Valore mancante per {}", args[i]))?;
+                max_nodes = value.parse().map_err(|_| format!("Valore non valido per {}", args[i]))?;
+                i += 1;
+            },
+            other => positional.push(other),
+        }
+        i += 1;
+    }
+    let Some((moves, fen)) = positional.split_first() else { return Err(USAGE.to_string()) };
+    let moves: u32 = moves.parse().ok().filter(|&moves| moves > 0).ok_or_else(|| USAGE.to_string())?;
+    let mut board = Board::from_fen(&fen.join(" "))?;
+    if force_pns && problem != ProblemType::Direct {
+        return Err("La ricerca proof-number risolve solo matti diretti".to_string());
+    }
+
+    let started = Instant::now();
+    let mut solver = Solver::default();
+    match problem {
+        ProblemType::Helpmate => {
+            let mut solutions = Vec::new();
+            solver.helpmates(&mut board, 2 * moves, &mut Vec::new(), &mut solutions);
+            println!("Aiutomatto in {}: {}", moves, count(solutions.len(), "soluzione", "soluzioni"));
+            for solution in &solutions {
+                println!("  {}", format_line(&board, solution));
+            }
+            if solutions.len() >= MAX_HELPMATE_SOLUTIONS {
+                println!("  (elencate solo le prime {})", MAX_HELPMATE_SOLUTIONS);
+            }
+        },
+        ProblemType::Direct if force_pns || moves > MAX_DEPTH_FIRST_MOVES => {
+            // Every move of the problem position is proved or refuted on its own, so that all keys are found
+            let mut nodes = 0;
+            let (mut keys, mut unknown) = (Vec::new(), Vec::new());
+            for mv in board.legal_moves() {
+                board.apply_move(mv);
+                let proof = proof_number_search(&board, 2 * moves - 2, max_nodes, &mut nodes);
+                board.unmake_move();
+                match proof {
+                    Proof::Proven => keys.push(board.to_san(mv)),
+                    Proof::Unknown => unknown.push(board.to_san(mv)),
+                    Proof::Disproven => {},
+                }
+            }
+            solver.nodes = nodes;
+            if !keys.is_empty() {
+                println!("Matto in {} dimostrato, chiavi: {}", moves, keys.join(", "));
+            } else if unknown.is_empty() {
+                println!("Nessun matto in {}: ogni mossa è confutata", moves);
+            } else {
+                println!("Nessun matto in {} trovato", moves);
+            }
+            if !unknown.is_empty() {
+                println!("Non risolte entro {} nodi: {}", max_nodes, unknown.join(", "));
+            }
+        },
+        ProblemType::Direct | ProblemType::Selfmate => {
+            let name = if problem == ProblemType::Direct { "Matto" } else { "Automatto" };
+            let mut keys = Vec::new();
+            for mv in board.legal_moves() {
+                if let Some(length) = solver.shortest(&mut board, mv, moves, problem) {
+                    keys.push((mv, length));
+                }
+            }
+            if keys.is_empty() {
+                println!("Nessun {} in {}: ogni mossa è confutata", name.to_lowercase(), moves);
+            } else {
+                println!("{} in {}: {}", name, moves, count(keys.len(), "chiave", "chiavi"));
+            }
+            for (key, length) in keys {
+                if problem == ProblemType::Direct {
+                    let line = solver.main_line(&board, key, length);
+                    println!("  {} (matto in {}): {}", board.to_san(key), length, format_line(&board, &line));
+                } else {
+                    println!("  {} (automatto in {})", board.to_san(key), length);
+                }
+            }
+        },
+    }
+    println!("Nodi: {}, tempo: {:.2} s", solver.nodes, started.elapsed().as_secs_f64());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(fen: &str, moves: u32, problem: ProblemType) -> Vec<String> {
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut solver = Solver::default();
+        board
+            .legal_moves()
+            .into_iter()
+            .filter(|&mv| match problem {
+                ProblemType::Selfmate => solver.forces_selfmate(&mut board, mv, moves),
+                _ => solver.forces_mate(&mut board, mv, moves),
+            })
+            .map(|mv| mv.to_uci())
+            .collect()
+    }
+
+    #[test]
+    fn direct_mates() {
+        // Légal's mate: 1. Nf6+ gxf6 2. Bxf7#, and nothing shorter
+        let legal = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+        assert_eq!(keys(legal, 2, ProblemType::Direct), ["d5f6"]);
+        assert!(keys(legal, 1, ProblemType::Direct).is_empty());
+
+        // Rook against king: 1. Kg6 Kg8 2. Ra8#, no mate in 1
+        let rook = "7k/8/8/6K1/8/8/8/R7 w - - 0 1";
+        assert_eq!(keys(rook, 2, ProblemType::Direct), ["g5g6"]);
+        assert!(keys(rook, 1, ProblemType::Direct).is_empty());
+        let mut solver = Solver::default();
+        assert!(!solver.mates_in(&mut Board::from_fen(rook).unwrap(), 1));
+    }
+
+    #[test]
+    fn helpmate_fools_mate() {
+        // White helps Black to mate it in 2: f3/f4 and g4 in either order, e5 or e6, then Qh4#
+        let mut board = Board::new();
+        let mut solutions = Vec::new();
+        Solver::default().helpmates(&mut board, 4, &mut Vec::new(), &mut solutions);
+        assert_eq!(solutions.len(), 8);
+        assert!(solutions.iter().all(|line| line[3].to_uci() == "d8h4"));
+    }
+
+    #[test]
+    fn selfmate_in_one() {
+        // After 1. b5 the black king has to step off the long diagonal and Ba8 mates the king on h1
+        let fen = "b7/1k6/8/8/1P6/8/7P/6BK w - - 0 1";
+        assert_eq!(keys(fen, 1, ProblemType::Selfmate), ["b4b5"]);
+        let mut solver = Solver::default();
+        assert!(solver.selfmates_in(&mut Board::from_fen(fen).unwrap(), 1));
+    }
+
+    #[test]
+    fn proof_number_search_agrees_with_depth_first() {
+        // Mate in 3 with the rook: keys Kf6, Kg6 (a mate in 2) and Kh6
+        let mut board = Board::from_fen("7k/8/8/6K1/8/8/8/R7 w - - 0 1").unwrap();
+        let mut solver = Solver::default();
+        let mut nodes = 0;
+        let mut keys = Vec::new();
+        for mv in board.legal_moves() {
+            let depth_first = solver.forces_mate(&mut board, mv, 3);
+            board.apply_move(mv);
+            let proof = proof_number_search(&board, 4, DEFAULT_PNS_NODES, &mut nodes);
+            board.unmake_move();
+            assert_ne!(proof, Proof::Unknown, "{}", mv.to_uci());
+            assert_eq!(depth_first, proof == Proof::Proven, "{}", mv.to_uci());
+            if depth_first {
+                keys.push(mv.to_uci());
+            }
+        }
+        keys.sort();
+        assert_eq!(keys, ["g5f6", "g5g6", "g5h6"]);
+    }
+}
@@ -0,0 +1,339 @@
+//! "Mosse" panel of the GUI: the game in SAN with its variations, and navigation between its positions
+
+use eframe::egui;
+
+use crate::movegen::Move;
+use crate::Board;
+
+/// A move of the game tree
+struct Node {
+    mv: Move,
+    san: String,
+    parent: Option<usize>,    // None for the moves of the starting position
+    children: Vec<usize>,     // Main line first, then the variations
+    fullmove_number: u32,
+    white: bool,              // Played by White
+}
+
+pub struct MoveList {
+    start: Board,               // Position before the first move
+    nodes: Vec<Node>,
+    first_moves: Vec<usize>,    // Moves of the starting position
+    current: Option<usize>,     // Last move of the position on the board; None at the start
+    truncate: bool,             // A move from an earlier position replaces the rest of the line
+    browsing: bool,             // The user went to an earlier position and no move was played since
+    scroll_to_current: bool,
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList {
+            start: Board::new(),
+            nodes: Vec::new(),
+            first_moves: Vec::new(),
+            current: None,
+            truncate: false,
+            browsing: false,
+            scroll_to_current: false,
+        }
+    }
+}
+
+impl MoveList {
+    /// Forgets the game: `board` is the new starting position
+    pub fn reset(&mut self, board: &Board) {
+        let mut start = board.clone();
+        while start.unmake_move().is_some() {}
+        self.start = start;
+        self.nodes.clear();
+        self.first_moves.clear();
+        self.current = None;
+        self.sync(board);
+    }
+
+    fn children(&self, node: Option<usize>) -> &[usize] {
+        match node {
+            Some(index) => &self.nodes[index].children,
+            None => &self.first_moves,
+        }
+    }
+
+    /// Moves from the starting position to `node`
+    fn path(&self, mut node: Option<usize>) -> Vec<usize> {
+        let mut path = Vec::new();
+        while let Some(index) = node {
+            path.push(index);
+            node = self.nodes[index].parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// True while the board shows an earlier position chosen in the panel, until a move is played on it
+    pub fn is_browsing(&self) -> bool {
+        self.browsing
+    }
+
+    /// Whether a move may be played on the board: without take-backs not from an earlier position
+    pub fn may_branch(&self, take_backs: bool) -> bool {
+        take_backs || !self.browsing
+    }
+
+    /// Follows the moves played on `board`: moves taken back are kept and new ones join the tree
+    pub fn sync(&mut self, board: &Board) {
+        let start_hash = board.history.first().map_or(board.hash, |undo| undo.hash);
+        if start_hash != self.start.hash {
+            self.reset(board);
+            return;
+        }
+        let path = self.path(self.current);
+        let played: Vec<Move> = board.history.iter().map(|undo| undo.mv).collect();
+        let common = path.iter().zip(&played).take_while(|(index, mv)| self.nodes[**index].mv == **mv).count();
+        if common == path.len() && common == played.len() {
+            return;
+        }
+        self.current = common.checked_sub(1).map(|last| path[last]);
+        self.browsing = false;
+        self.scroll_to_current = true;
+
+        let mut before = board.clone();
+        for _ in common..played.len() {
+            before.unmake_move();
+        }
+        for &mv in &played[common..] {
+            let existing = self.children(self.current).iter().copied().find(|&index| self.nodes[index].mv == mv);
+            let index = existing.unwrap_or_else(|| self.add(&before, mv));
+            self.current = Some(index);
+            before.apply_move(mv);
+        }
+    }
+
+    /// Adds `mv`, played in `before`, after the current move
+    fn add(&mut self, before: &Board, mv: Move) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            mv,
+            san: before.to_san(mv),
+            parent: self.current,
+            children: Vec::new(),
+            fullmove_number: before.fullmove_number,
+            white: before.white_to_move,
+        });
+        // The replaced moves stay in `nodes`, unreachable, until the next reset
+        let children = match self.current {
+            Some(current) => &mut self.nodes[current].children,
+            None => &mut self.first_moves,
+        };
+        if self.truncate {
+            children.clear();
+        }
+        children.push(index);
+        index
+    }
+
+    /// Position after `node`, which becomes the current move
+    fn go_to(&mut self, node: Option<usize>) -> Board {
+        let mut board = self.start.clone();
+        for index in self.path(node) {
+            board.apply_move(self.nodes[index].mv);
+        }
+        self.current = node;
+        self.browsing = !self.children(node).is_empty();
+        self.scroll_to_current = true;
+        board
+    }
+
+    /// Draws the moves from `children` on: the first one and its main line, each move followed by its
+    /// alternatives in parentheses
+    fn line_ui<'a>(&'a self, ui: &mut egui::Ui, mut children: &'a [usize], clicked: &mut Option<usize>) {
+        let mut show_number = true;
+        while let Some((&main, alternatives)) = children.split_first() {
+            self.move_ui(ui, main, show_number, clicked);
+            show_number = false;
+            for &alternative in alternatives {
+                ui.label("(");
+                self.line_ui(ui, &[alternative], clicked);
+                ui.label(")");
+                show_number = true;
+            }
+            children = &self.nodes[main].children;
+        }
+    }
+
+    fn move_ui(&self, ui: &mut egui::Ui, index: usize, show_number: bool, clicked: &mut Option<usize>) {
+        let node = &self.nodes[index];
+        if node.white {
+            ui.label(format!("{}.", node.fullmove_number));
+        } else if show_number {
+            ui.label(format!("{}...", node.fullmove_number));
+        }
+        let response = ui.selectable_label(self.current == Some(index), &node.san);
+        if self.current == Some(index) && self.scroll_to_current {
+            response.scroll_to_me(None);
+        }
+        if response.clicked() {
+            *clicked = Some(index);
+        }
+    }
+
+    /// Draws the panel; returns the position to show when the user moved through the game. With
+    /// `enabled` false the moves are only shown
+    pub fn ui(&mut self, ui: &mut egui::Ui, enabled: bool) -> Option<Board> {
+        let last = {
+            let mut node = self.current;
+            while let Some(&next) = self.children(node).first() {
+                node = Some(next);
+            }
+            node
+        };
+        let back = self.current.and_then(|index| self.nodes[index].parent);
+        let forward = self.children(self.current).first().copied();
+
+        let mut target = None;
+        ui.add_enabled_ui(enabled, |ui| {
+            ui.horizontal(|ui| {
+                let at_start = self.current.is_none();
+                if ui.add_enabled(!at_start, egui::Button::new("⏮")).on_hover_text("Inizio (↑)").clicked() {
+                    target = Some(None);
+                }
+                if ui.add_enabled(!at_start, egui::Button::new("⏴")).on_hover_text("Indietro (←)").clicked() {
+                    target = Some(back);
+                }
+                if ui.add_enabled(forward.is_some(), egui::Button::new("⏵")).on_hover_text("Avanti (→)").clicked() {
+                    target = Some(forward);
+                }
+                if ui.add_enabled(forward.is_some(), egui::Button::new("⏭")).on_hover_text("Fine (↓)").clicked() {
+                    target = Some(last);
+                }
+            });
+            ui.label("Mossa da una posizione passata:");
+            ui.radio_value(&mut self.truncate, false, "crea una variante");
+            ui.radio_value(&mut self.truncate, true, "sostituisce le mosse successive");
+        });
+
+        // Arrow keys, unless a text field is being edited
+        let typing = ui.memory(|memory| memory.focus().is_some());
+        if enabled && !typing {
+            ui.input(|input| {
+                if input.key_pressed(egui::Key::ArrowUp) && self.current.is_some() {
+                    target = Some(None);
+                }
+                if input.key_pressed(egui::Key::ArrowLeft) && self.current.is_some() {
+                    target = Some(back);
+                }
+                if input.key_pressed(egui::Key::ArrowRight) && forward.is_some() {
+                    target = Some(forward);
+                }
+                if input.key_pressed(egui::Key::ArrowDown) && forward.is_some() {
+                    target = Some(last);
+                }
+            });
+        }
+
+        ui.separator();
+        let mut clicked = None;
+        egui::ScrollArea::vertical().id_source("move_list").show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing.x = 4.0;
+                self.line_ui(ui, &self.first_moves, &mut clicked);
+            });
+        });
+        self.scroll_to_current = false;
+        if let Some(index) = clicked.filter(|_| enabled) {
+            target = Some(Some(index));
+        }
+        target.map(|node| self.go_to(node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for uci in moves {
+            let mv = board.parse_uci_move(uci).unwrap();
+            board.apply_move(mv);
+        }
+    }
+
+    #[test]
+    fn replaying_a_known_move_ends_browsing() {
+        let mut list = MoveList::default();
+        let mut board = Board::new();
+        play(&mut board, &["e2e4", "e7e5"]);
+        list.sync(&board);
+        assert!(!list.is_browsing());
+
+        // Back to the start, then e4 again: the node already exists but the game goes on from it
+        board = list.go_to(None);
+        list.sync(&board);
+        assert!(list.is_browsing(), "la posizione iniziale non è la fine della partita");
+        play(&mut board, &["e2e4"]);
+        list.sync(&board);
+        assert!(!list.is_browsing(), "dopo una mossa sulla scacchiera il motore deve poter giocare");
+        assert_eq!(list.first_moves.len(), 1);
+        assert_eq!(list.children(list.current).len(), 1);
+    }
+
+    #[test]
+    fn going_to_the_end_of_a_line_is_not_browsing() {
+        let mut list = MoveList::default();
+        let mut board = Board::new();
+        play(&mut board, &["d2d4", "d7d5", "c2c4"]);
+        list.sync(&board);
+        let last = list.current;
+
+        list.go_to(list.first_moves.first().copied());
+        assert!(list.is_browsing());
+        board = list.go_to(last);
+        list.sync(&board);
+        assert!(!list.is_browsing());
+
+        // A take-back is a move on the board too
+        board.unmake_move();
+        list.sync(&board);
+        assert!(!list.is_browsing());
+        assert_eq!(list.children(list.current).len(), 1);
+    }
+
+    #[test]
+    fn earlier_positions_need_take_backs() {
+        let mut list = MoveList::default();
+        let mut board = Board::new();
+        play(&mut board, &["e2e4", "e7e5"]);
+        list.sync(&board);
+        assert!(list.may_branch(false));
+
+        list.go_to(list.first_moves.first().copied());
+        assert!(!list.may_branch(false), "senza ritiri non si gioca da una posizione passata");
+        assert!(list.may_branch(true));
+    }
+
+    #[test]
+    fn a_new_move_from_an_earlier_position_is_a_variation() {
+        let mut list = MoveList::default();
+        let mut board = Board::new();
+        play(&mut board, &["e2e4", "e7e5", "g1f3"]);
+        list.sync(&board);
+
+        let e4 = list.first_moves[0];
+        board = list.go_to(Some(e4));
+        play(&mut board, &["c7c5"]);
+        list.sync(&board);
+        assert!(!list.is_browsing());
+        let children: Vec<&str> = list.nodes[e4].children.iter().map(|&index| list.nodes[index].san.as_str()).collect();
+        assert_eq!(children, ["e5", "c5"], "la linea principale resta prima");
+        let e5 = list.nodes[e4].children[0];
+        assert_eq!(list.nodes[list.nodes[e5].children[0]].san, "Nf3");
+        assert_eq!(list.current, Some(list.nodes[e4].children[1]));
+
+        // Replacing the rest of the line instead
+        list.truncate = true;
+        board = list.go_to(Some(e4));
+        play(&mut board, &["d7d5"]);
+        list.sync(&board);
+        let children: Vec<&str> = list.nodes[e4].children.iter().map(|&index| list.nodes[index].san.as_str()).collect();
+        assert_eq!(children, ["d5"]);
+    }
+}
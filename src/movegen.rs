@@ -0,0 +1,525 @@
+//! Move representation, pseudo-legal move generation and reversible make/unmake for `Board`
+
+use crate::{zobrist, Board, GameState, Piece};
+
+/// Knight jumps as (row, col) offsets
+pub const KNIGHT_OFFSETS: [(i32, i32); 8] = [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)];
+/// King steps as (row, col) offsets
+pub const KING_OFFSETS: [(i32, i32); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+/// Horizontal and vertical ray directions (rooks and queens)
+pub const ROOK_DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+/// Diagonal ray directions (bishops and queens)
+pub const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+/// A single move in board coordinates (row 0 = rank 8, col 0 = file a)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Move {
+    pub from_row: usize,
+    pub from_col: usize,
+    pub to_row: usize,
+    pub to_col: usize,
+    pub promotion: Option<Piece>,  // Piece a pawn turns into on the last rank
+}
+
+impl Move {
+    /// Creates a move without promotion
+    pub fn new(from_row: usize, from_col: usize, to_row: usize, to_col: usize) -> Self {
+        Move { from_row, from_col, to_row, to_col, promotion: None }
+    }
+
+    /// Formats the move in UCI long algebraic notation (e.g. "e2e4", "e7e8q")
+    pub fn to_uci(self) -> String {
+        let mut text = format!("{}{}", square_name(self.from_row, self.from_col), square_name(self.to_row, self.to_col));
+        if let Some(piece) = self.promotion {
+            text.push(match piece.type_index() {
+                1 => 'n',
+                2 => 'b',
+                3 => 'r',
+                _ => 'q',
+            });
+        }
+        text
+    }
+}
+
+/// Returns the algebraic name of a square, e.g. (7, 4) -> "e1"
+pub fn square_name(row: usize, col: usize) -> String {
+    format!("{}{}", (b'a' + col as u8) as char, 8 - row)
+}
+
+/// Parses an algebraic square name like "e4" into (row, col)
+pub fn parse_square(text: &str) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+        return None;
+    }
+    Some(((b'8' - bytes[1]) as usize, (bytes[0] - b'a') as usize))
+}
+
+/// Everything needed to take back a move with `unmake_move`
+#[derive(Copy, Clone, Debug)]
+pub struct UndoInfo {
+    pub mv: Move,
+    pub moved: Piece,                       // Piece that moved (the pawn, for promotions)
+    pub captured: Piece,                    // Captured piece or Empty
+    pub captured_square: (usize, usize),    // Differs from the destination for en passant
+    pub game_state: GameState,              // Castling / en passant state before the move
+    pub halfmove_clock: u32,
+    pub hash: u64,                          // Zobrist key before the move
+}
+
+impl Board {
+    /// Generates pseudo-legal moves for the side to move (they may leave the king in check)
+    /// With `captures_only` only captures and queen promotions are produced (quiescence search)
+    pub fn generate_pseudo_moves(&self, captures_only: bool, moves: &mut Vec<Move>) {
+        let white = self.white_to_move;
+        for row in 0..8 {
+            for col in 0..8 {
+                let piece = self.squares[row][col];
+                if piece.is_empty() || !piece.is_color(white) {
+                    continue;
+                }
+                match piece {
+                    Piece::PawnWhite | Piece::PawnBlack => self.generate_pawn_moves(piece, row, col, captures_only, moves),
+                    Piece::KnightWhite | Piece::KnightBlack => {
+                        self.generate_step_moves(row, col, &KNIGHT_OFFSETS, captures_only, moves)
+                    },
+                    Piece::BishopWhite | Piece::BishopBlack => {
+                        self.generate_slider_moves(row, col, &BISHOP_DIRECTIONS, captures_only, moves)
+                    },
+                    Piece::RookWhite | Piece::RookBlack => {
+                        self.generate_slider_moves(row, col, &ROOK_DIRECTIONS, captures_only, moves)
+                    },
+                    Piece::QueenWhite | Piece::QueenBlack => {
+                        self.generate_slider_moves(row, col, &ROOK_DIRECTIONS, captures_only, moves);
+                        self.generate_slider_moves(row, col, &BISHOP_DIRECTIONS, captures_only, moves);
+                    },
+                    Piece::KingWhite | Piece::KingBlack => {
+                        self.generate_step_moves(row, col, &KING_OFFSETS, captures_only, moves);
+                        // Castling - can_castle already verifies the king does not pass through check
+                        if !captures_only {
+                            for to_col in [6, 2] {
+                                if self.can_castle(row, col, row, to_col) {
+                                    moves.push(Move::new(row, col, row, to_col));
+                                }
+                            }
+                        }
+                    },
+                    Piece::Empty => {},
+                }
+            }
+        }
+    }
+
+    /// Pawn pushes, double pushes, captures, en passant and promotions
+    fn generate_pawn_moves(&self, piece: Piece, row: usize, col: usize, captures_only: bool, moves: &mut Vec<Move>) {
+        let white = piece.is_white();
+        let direction = if white { -1i32 } else { 1i32 };
+        let start_row = if white { 6 } else { 1 };
+        let promotion_row = if white { 0 } else { 7 };
+        let to_row = (row as i32 + direction) as usize;
+
+        let push = |moves: &mut Vec<Move>, to_col: usize, quiet: bool| {
+            if to_row == promotion_row {
+                // Quiet under-promotions are left out of the quiescence search
+                let types: &[usize] = if captures_only && quiet { &[4] } else { &[4, 3, 2, 1] };
+                for &type_index in types {
+                    let mut mv = Move::new(row, col, to_row, to_col);
+                    mv.promotion = Some(Piece::from_type_index(type_index, white));
+                    moves.push(mv);
+                }
+            } else if !captures_only || !quiet {
+                moves.push(Move::new(row, col, to_row, to_col));
+            }
+        };
+
+        // Forward pushes
+        if self.squares[to_row][col].is_empty() {
+            push(moves, col, true);
+            let double_row = (row as i32 + 2 * direction) as usize;
+            if !captures_only && row == start_row && self.squares[double_row][col].is_empty() {
+                moves.push(Move::new(row, col, double_row, col));
+            }
+        }
+
+        // Diagonal captures, including en passant
+        for to_col in [col as i32 - 1, col as i32 + 1] {
+            if !(0..8).contains(&to_col) {
+                continue;
+            }
+            let to_col = to_col as usize;
+            let target = self.squares[to_row][to_col];
+            let is_en_passant = self.game_state.en_passant_target == Some((to_row, to_col));
+            if (!target.is_empty() && !target.is_same_color(&piece)) || is_en_passant {
+                push(moves, to_col, false);
+            }
+        }
+    }
+
+    /// Single-step moves for knights and kings
+    fn generate_step_moves(&self, row: usize, col: usize, offsets: &[(i32, i32)], captures_only: bool, moves: &mut Vec<Move>) {
+        let piece = self.squares[row][col];
+        for &(dr, dc) in offsets {
+            let (r, c) = (row as i32 + dr, col as i32 + dc);
+            if !(0..8).contains(&r) || !(0..8).contains(&c) {
+                continue;
+            }
+            let target = self.squares[r as usize][c as usize];
+            if target.is_empty() {
+                if !captures_only {
+                    moves.push(Move::new(row, col, r as usize, c as usize));
+                }
+            } else if !target.is_same_color(&piece) {
+                moves.push(Move::new(row, col, r as usize, c as usize));
+            }
+        }
+    }
+
+    /// Sliding moves along the given ray directions
+    fn generate_slider_moves(&self, row: usize, col: usize, directions: &[(i32, i32)], captures_only: bool, moves: &mut Vec<Move>) {
+        let piece = self.squares[row][col];
+        for &(dr, dc) in directions {
+            let (mut r, mut c) = (row as i32 + dr, col as i32 + dc);
+            while (0..8).contains(&r) && (0..8).contains(&c) {
+                let target = self.squares[r as usize][c as usize];
+                if target.is_empty() {
+                    if !captures_only {
+                        moves.push(Move::new(row, col, r as usize, c as usize));
+                    }
+                } else {
+                    if !target.is_same_color(&piece) {
+                        moves.push(Move::new(row, col, r as usize, c as usize));
+                    }
+                    break;
+                }
+                r += dr;
+                c += dc;
+            }
+        }
+    }
+
+    /// Calls `visit` with every square the knight, bishop, rook or queen on (row, col) attacks,
+    /// including the first occupied square of each ray
+    pub fn for_each_attack(&self, row: usize, col: usize, mut visit: impl FnMut(usize, usize)) {
+        let (offsets, sliding): (&[(i32, i32)], bool) = match self.squares[row][col].type_index() {
+            1 => (&KNIGHT_OFFSETS, false),
+            2 => (&BISHOP_DIRECTIONS, true),
+            3 => (&ROOK_DIRECTIONS, true),
+            4 => (&KING_OFFSETS, true),  // Queen: every direction
+            _ => return,
+        };
+        for &(dr, dc) in offsets {
+            let (mut r, mut c) = (row as i32 + dr, col as i32 + dc);
+            while (0..8).contains(&r) && (0..8).contains(&c) {
+                visit(r as usize, c as usize);
+                if !sliding || !self.squares[r as usize][c as usize].is_empty() {
+                    break;
+                }
+                r += dr;
+                c += dc;
+            }
+        }
+    }
+
+    /// Determines if a square is attacked by the given side, scanning outward from the square
+    /// instead of testing every piece on the board (the GUI rules use `is_square_under_attack`)
+    pub fn is_attacked_by(&self, row: usize, col: usize, by_white: bool) -> bool {
+        let (pawn, knight, bishop, rook, queen, king) = if by_white {
+            (Piece::PawnWhite, Piece::KnightWhite, Piece::BishopWhite, Piece::RookWhite, Piece::QueenWhite, Piece::KingWhite)
+        } else {
+            (Piece::PawnBlack, Piece::KnightBlack, Piece::BishopBlack, Piece::RookBlack, Piece::QueenBlack, Piece::KingBlack)
+        };
+        let piece_at = |r: i32, c: i32| -> Option<Piece> {
+            if (0..8).contains(&r) && (0..8).contains(&c) {
+                Some(self.squares[r as usize][c as usize])
+            } else {
+                None
+            }
+        };
+        let (row, col) = (row as i32, col as i32);
+
+        // Pawns attack diagonally: white pawns from the row below, black pawns from the row above
+        let pawn_row = if by_white { row + 1 } else { row - 1 };
+        if piece_at(pawn_row, col - 1) == Some(pawn) || piece_at(pawn_row, col + 1) == Some(pawn) {
+            return true;
+        }
+
+        // Knights and kings attack fixed offsets
+        if KNIGHT_OFFSETS.iter().any(|&(dr, dc)| piece_at(row + dr, col + dc) == Some(knight)) {
+            return true;
+        }
+        if KING_OFFSETS.iter().any(|&(dr, dc)| piece_at(row + dr, col + dc) == Some(king)) {
+            return true;
+        }
+
+        // Sliding pieces: walk each ray until the first occupied square
+        for (directions, slider) in [(ROOK_DIRECTIONS, rook), (BISHOP_DIRECTIONS, bishop)] {
+            for &(dr, dc) in directions.iter() {
+                let (mut r, mut c) = (row + dr, col + dc);
+                while let Some(piece) = piece_at(r, c) {
+                    if !piece.is_empty() {
+                        if piece == slider || piece == queen {
+                            return true;
+                        }
+                        break;
+                    }
+                    r += dr;
+                    c += dc;
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns true if the king of the given side is attacked
+    pub fn king_attacked(&self, white: bool) -> bool {
+        self.find_king(white).is_some_and(|(row, col)| self.is_attacked_by(row, col, !white))
+    }
+
+    /// Returns every legal move for the side to move
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut board = self.clone();
+        let mut moves = Vec::with_capacity(64);
+        board.generate_pseudo_moves(false, &mut moves);
+        moves.retain(|&mv| board.is_pseudo_move_legal(mv));
+        moves
+    }
+
+    /// Plays a pseudo-legal move and reports whether it leaves the mover's king safe
+    pub fn is_pseudo_move_legal(&mut self, mv: Move) -> bool {
+        self.apply_move(mv);
+        let legal = !self.king_attacked(!self.white_to_move);
+        self.unmake_move();
+        legal
+    }
+
+    /// Finds the legal move matching a UCI string such as "e2e4" or "a7a8q"
+    pub fn parse_uci_move(&self, text: &str) -> Option<Move> {
+        self.legal_moves().into_iter().find(|mv| mv.to_uci() == text)
+    }
+
+    /// Returns true if the side to move is in check
+    pub fn in_check(&self) -> bool {
+        self.king_attacked(self.white_to_move)
+    }
+
+    /// Executes a (pseudo-legal) move, handling every special case, and records it in the history
+    /// The Zobrist key is updated incrementally
+    pub fn apply_move(&mut self, mv: Move) {
+        let piece = self.squares[mv.from_row][mv.from_col];
+        let is_pawn = matches!(piece, Piece::PawnWhite | Piece::PawnBlack);
+
+        // En passant captures a pawn that is not on the destination square
+        let mut captured_square = (mv.to_row, mv.to_col);
+        if is_pawn && self.game_state.en_passant_target == Some((mv.to_row, mv.to_col)) && self.squares[mv.to_row][mv.to_col].is_empty() {
+            captured_square = (mv.from_row, mv.to_col);
+        }
+        let captured = self.squares[captured_square.0][captured_square.1];
+
+        self.history.push(UndoInfo {
+            mv,
+            moved: piece,
+            captured,
+            captured_square,
+            game_state: self.game_state,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        });
+
+        // Remove the old castling / en passant contribution from the key
+        self.hash ^= zobrist::castling_key(self.game_state.castling_rights());
+        if let Some((_, ep_col)) = self.game_state.en_passant_target {
+            self.hash ^= zobrist::en_passant_key(ep_col);
+        }
+
+        // Remove the captured piece
+        if !captured.is_empty() {
+            self.hash ^= zobrist::piece_key(captured, captured_square.0, captured_square.1);
+            self.squares[captured_square.0][captured_square.1] = Piece::Empty;
+        }
+
+        // Move the piece (promoting it if needed)
+        let placed = mv.promotion.unwrap_or(piece);
+        self.hash ^= zobrist::piece_key(piece, mv.from_row, mv.from_col);
+        self.hash ^= zobrist::piece_key(placed, mv.to_row, mv.to_col);
+        self.squares[mv.from_row][mv.from_col] = Piece::Empty;
+        self.squares[mv.to_row][mv.to_col] = placed;
+
+        // Castling - move the rook as well
+        if matches!(piece, Piece::KingWhite | Piece::KingBlack) && mv.from_col.abs_diff(mv.to_col) == 2 {
+            let (rook_from_col, rook_to_col) = if mv.to_col == 6 { (7, 5) } else { (0, 3) };
+            let rook = self.squares[mv.from_row][rook_from_col];
+            self.hash ^= zobrist::piece_key(rook, mv.from_row, rook_from_col);
+            self.hash ^= zobrist::piece_key(rook, mv.from_row, rook_to_col);
+            self.squares[mv.from_row][rook_to_col] = rook;
+            self.squares[mv.from_row][rook_from_col] = Piece::Empty;
+        }
+
+        // En passant target for the next turn after a double pawn push
+        self.game_state.en_passant_target = if is_pawn && mv.from_row.abs_diff(mv.to_row) == 2 {
+            Some(((mv.from_row + mv.to_row) / 2, mv.from_col))
+        } else {
+            None
+        };
+
+        // Castling rights: moving king/rook, or a rook captured on its starting square
+        self.update_game_state_after_move(piece, mv.from_row, mv.from_col);
+        if matches!(captured, Piece::RookWhite | Piece::RookBlack) {
+            self.update_game_state_after_move(captured, captured_square.0, captured_square.1);
+        }
+
+        self.hash ^= zobrist::castling_key(self.game_state.castling_rights());
+        if let Some((_, ep_col)) = self.game_state.en_passant_target {
+            self.hash ^= zobrist::en_passant_key(ep_col);
+        }
+
+        // Clocks and turn
+        self.halfmove_clock = if is_pawn || !captured.is_empty() { 0 } else { self.halfmove_clock + 1 };
+        if !self.white_to_move {
+            self.fullmove_number += 1;
+        }
+        self.white_to_move = !self.white_to_move;
+        self.hash ^= zobrist::side_key();
+    }
+
+    /// Takes back the last move played with `apply_move`, returning it
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let undo = self.history.pop()?;
+        let mv = undo.mv;
+
+        self.white_to_move = !self.white_to_move;
+        if !self.white_to_move {
+            self.fullmove_number -= 1;
+        }
+
+        // Put the rook back after castling
+        if matches!(undo.moved, Piece::KingWhite | Piece::KingBlack) && mv.from_col.abs_diff(mv.to_col) == 2 {
+            let (rook_from_col, rook_to_col) = if mv.to_col == 6 { (7, 5) } else { (0, 3) };
+            self.squares[mv.from_row][rook_from_col] = self.squares[mv.from_row][rook_to_col];
+            self.squares[mv.from_row][rook_to_col] = Piece::Empty;
+        }
+
+        self.squares[mv.to_row][mv.to_col] = Piece::Empty;
+        self.squares[mv.from_row][mv.from_col] = undo.moved;
+        self.squares[undo.captured_square.0][undo.captured_square.1] = undo.captured;
+
+        self.game_state = undo.game_state;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+        Some(mv)
+    }
+
+    /// Passes the turn without moving (null-move pruning); undone with `unmake_null_move`
+    pub fn make_null_move(&mut self) -> Option<(usize, usize)> {
+        let en_passant = self.game_state.en_passant_target.take();
+        if let Some((_, ep_col)) = en_passant {
+            self.hash ^= zobrist::en_passant_key(ep_col);
+        }
+        self.white_to_move = !self.white_to_move;
+        self.hash ^= zobrist::side_key();
+        en_passant
+    }
+
+    /// Reverts `make_null_move`, restoring the en passant square it returned
+    pub fn unmake_null_move(&mut self, en_passant: Option<(usize, usize)>) {
+        self.white_to_move = !self.white_to_move;
+        self.hash ^= zobrist::side_key();
+        if let Some((_, ep_col)) = en_passant {
+            self.hash ^= zobrist::en_passant_key(ep_col);
+        }
+        self.game_state.en_passant_target = en_passant;
+    }
+
+    /// True if the current position already occurred since the last irreversible move
+    pub fn is_repetition(&self) -> bool {
+        self.history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|undo| undo.hash == self.hash)
+    }
+
+    /// Counts leaf nodes of the legal move tree (move generator verification)
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut moves = Vec::with_capacity(64);
+        self.generate_pseudo_moves(false, &mut moves);
+        let mut nodes = 0;
+        for mv in moves {
+            self.apply_move(mv);
+            if !self.king_attacked(!self.white_to_move) {
+                nodes += self.perft(depth - 1);
+            }
+            self.unmake_move();
+        }
+        nodes
+    }
+
+    /// How many times the current position occurred before (threefold repetition = 2)
+    pub fn repetition_count(&self) -> usize {
+        self.history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|undo| undo.hash == self.hash)
+            .count()
+    }
+
+    /// Number of pieces on the board, kings and pawns included
+    pub fn piece_count(&self) -> usize {
+        self.squares.iter().flatten().filter(|piece| !piece.is_empty()).count()
+    }
+
+    /// Neither side can possibly mate: bare kings, or a single minor piece left
+    pub fn has_insufficient_material(&self) -> bool {
+        let mut minors = 0;
+        for row in 0..8 {
+            for col in 0..8 {
+                match self.squares[row][col].type_index() {
+                    1 | 2 => minors += 1,
+                    0 | 3 | 4 => return false,
+                    _ => {},
+                }
+            }
+        }
+        minors <= 1
+    }
+
+    /// Draw by the fifty-move rule or by insufficient material
+    pub fn is_draw_by_rule(&self) -> bool {
+        self.halfmove_clock >= 100 || self.has_insufficient_material()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Board;
+
+    /// Leaf counts for the standard perft positions (chessprogramming.org "Perft Results")
+    const PERFT_POSITIONS: [(&str, &str, &[u64]); 5] = [
+        ("startpos", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281]),
+        ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862]),
+        // En passant captures, discovered checks along the rank
+        ("position 3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238]),
+        // Promotions and under-promotions with captures, castling rights of one side
+        ("position 4", "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467]),
+        ("position 5", "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379]),
+    ];
+
+    #[test]
+    fn perft_positions() {
+        for (name, fen, expected) in PERFT_POSITIONS {
+            let mut board = Board::from_fen(fen).unwrap();
+            for (depth, &nodes) in expected.iter().enumerate() {
+                assert_eq!(board.perft(depth as u32 + 1), nodes, "{} a profondità {}", name, depth + 1);
+            }
+            assert_eq!(board.to_fen(), fen, "{}: perft non ha ripristinato la posizione", name);
+        }
+    }
+}
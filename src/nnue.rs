@@ -0,0 +1,470 @@
+//! Efficiently updatable neural network evaluation (768 -> N -> 1), quantised to int16 / int8
+
+use std::fs;
+
+use crate::{Board, Piece};
+
+/// Header of the network files
+const MAGIC: &[u8; 4] = b"GKNN";
+const VERSION: u32 = 1;
+
+/// Inputs per perspective: 2 colours (own, enemy) x 6 piece types x 64 squares
+const FEATURES: usize = 768;
+/// Hidden sizes must be a multiple of this, so that the SIMD path needs no tail loop
+const LANES: usize = 16;
+
+/// Quantisation: activations are clipped to 0..=QA, output weights are scaled by QB
+const QA: i32 = 255;
+const QB: i32 = 64;
+/// Centipawns per unit of the network output
+const SCALE: i32 = 400;
+
+/// Largest evaluation returned, so that the network never claims a known win or a mate
+const MAX_EVAL: i32 = 9_000;
+
+/// A network loaded from a `.gknn` file
+///
+/// Layout (little endian): "GKNN", version u32, hidden size N u32, feature weights i16 [768][N],
+/// feature biases i16 [N], output weights i8 [2N] (side to move first), output bias i32
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,  // Row `feature` holds the N weights of that input
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i8>,
+    output_bias: i32,
+    avx2: bool,                 // SIMD accumulator updates and output layer available on this CPU
+}
+
+/// Hidden layer values of one position from both perspectives (White, Black)
+#[derive(Clone)]
+pub struct Accumulator {
+    values: [Vec<i16>; 2],
+}
+
+/// Reads little-endian values from the file contents
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        if self.bytes.len() < count {
+            return Err("File della rete troncato".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, count: usize) -> Result<Vec<i16>, String> {
+        Ok(self.take(2 * count)?.chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect())
+    }
+
+    fn i8s(&mut self, count: usize) -> Result<Vec<i8>, String> {
+        Ok(self.take(count)?.iter().map(|&byte| byte as i8).collect())
+    }
+}
+
+/// Input index of `piece` on (row, col) seen by one side: its own pieces first, squares from its back rank
+fn feature(white_perspective: bool, piece: Piece, row: usize, col: usize) -> usize {
+    let own = piece.is_white() == white_perspective;
+    let square = if white_perspective { (7 - row) * 8 + col } else { row * 8 + col };
+    usize::from(!own) * 384 + piece.type_index() * 64 + square
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Network, String> {
+        let bytes = fs::read(path).map_err(|err| format!("Impossibile leggere {}: {}", path, err))?;
+        let mut reader = Reader { bytes: &bytes };
+        if reader.take(4)? != MAGIC || reader.u32()? != VERSION {
+            return Err(format!("{} non è una rete GKNN versione {}", path, VERSION));
+        }
+        let hidden = reader.u32()? as usize;
+        if hidden == 0 || !hidden.is_multiple_of(LANES) || hidden > 4096 {
+            return Err(format!("Dimensione dello strato nascosto non valida: {}", hidden));
+        }
+        let network = Network {
+            hidden,
+            feature_weights: reader.i16s(FEATURES * hidden)?,
+            feature_biases: reader.i16s(hidden)?,
+            output_weights: reader.i8s(2 * hidden)?,
+            output_bias: reader.u32()? as i32,
+            avx2: avx2_available(),
+        };
+        if !reader.bytes.is_empty() {
+            return Err(format!("{}: dati in eccesso dopo la rete", path));
+        }
+        Ok(network)
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    /// Computes the accumulator of `board` from scratch
+    pub fn refresh(&self, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator { values: [self.feature_biases.clone(), self.feature_biases.clone()] };
+        for row in 0..8 {
+            for col in 0..8 {
+                let piece = board.squares[row][col];
+                if !piece.is_empty() {
+                    self.update(&mut accumulator, piece, row, col, true);
+                }
+            }
+        }
+        accumulator
+    }
+
+    /// Adds or removes one piece in both perspectives
+    fn update(&self, accumulator: &mut Accumulator, piece: Piece, row: usize, col: usize, add: bool) {
+        for (values, white_perspective) in accumulator.values.iter_mut().zip([true, false]) {
+            let start = feature(white_perspective, piece, row, col) * self.hidden;
+            let weights = &self.feature_weights[start..start + self.hidden];
+            #[cfg(target_arch = "x86_64")]
+            if self.avx2 {
+                // SAFETY: AVX2 support was detected when the network was loaded
+                unsafe { update_avx2(values, weights, add) };
+                continue;
+            }
+            update_scalar(values, weights, add);
+        }
+    }
+
+    /// Updates `accumulator` for the last move played on `board`
+    fn apply_last_move(&self, accumulator: &mut Accumulator, board: &Board) {
+        let Some(undo) = board.history.last() else { return };
+        let mv = undo.mv;
+        self.update(accumulator, undo.moved, mv.from_row, mv.from_col, false);
+        self.update(accumulator, mv.promotion.unwrap_or(undo.moved), mv.to_row, mv.to_col, true);
+        if !undo.captured.is_empty() {
+            self.update(accumulator, undo.captured, undo.captured_square.0, undo.captured_square.1, false);
+        }
+        if matches!(undo.moved, Piece::KingWhite | Piece::KingBlack) && mv.from_col.abs_diff(mv.to_col) == 2 {
+            let (rook_from_col, rook_to_col) = if mv.to_col == 6 { (7, 5) } else { (0, 3) };
+            let rook = board.squares[mv.from_row][rook_to_col];
+            self.update(accumulator, rook, mv.from_row, rook_from_col, false);
+            self.update(accumulator, rook, mv.from_row, rook_to_col, true);
+        }
+    }
+
+    /// Evaluation in centipawns from the point of view of the side to move
+    pub fn evaluate(&self, accumulator: &Accumulator, white_to_move: bool) -> i32 {
+        let (us, them) = if white_to_move { (0, 1) } else { (1, 0) };
+        let (own_weights, enemy_weights) = self.output_weights.split_at(self.hidden);
+        let sum = self.output(&accumulator.values[us], own_weights) + self.output(&accumulator.values[them], enemy_weights);
+        let score = (sum as i64 + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64;
+        score.clamp(-MAX_EVAL as i64, MAX_EVAL as i64) as i32
+    }
+
+    /// Evaluates a position without an incremental accumulator (UCI `eval`)
+    pub fn evaluate_board(&self, board: &Board) -> i32 {
+        self.evaluate(&self.refresh(board), board.white_to_move)
+    }
+
+    /// Clipped ReLU of the hidden values dotted with the output weights
+    fn output(&self, values: &[i16], weights: &[i8]) -> i32 {
+        #[cfg(target_arch = "x86_64")]
+        if self.avx2 {
+            // SAFETY: AVX2 support was detected when the network was loaded
+            return unsafe { output_avx2(values, weights) };
+        }
+        output_scalar(values, weights)
+    }
+}
+
+fn update_scalar(values: &mut [i16], weights: &[i16], add: bool) {
+    if add {
+        values.iter_mut().zip(weights).for_each(|(value, weight)| *value = value.wrapping_add(*weight));
+    } else {
+        values.iter_mut().zip(weights).for_each(|(value, weight)| *value = value.wrapping_sub(*weight));
+    }
+}
+
+fn output_scalar(values: &[i16], weights: &[i8]) -> i32 {
+    values.iter().zip(weights).map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32).sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+fn avx2_available() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn avx2_available() -> bool {
+    false
+}
+
+/// Same as `output_scalar`, 16 values at a time; the lengths are multiples of LANES
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn output_avx2(values: &[i16], weights: &[i8]) -> i32 {
+    use std::arch::x86_64::*;
+
+    let zero = _mm256_setzero_si256();
+    let ceiling = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+    for (values, weights) in values.chunks_exact(LANES).zip(weights.chunks_exact(LANES)) {
+        let clipped = _mm256_min_epi16(_mm256_max_epi16(_mm256_loadu_si256(values.as_ptr().cast()), zero), ceiling);
+        let weights = _mm256_cvtepi8_epi16(_mm_loadu_si128(weights.as_ptr().cast()));
+        // Products fit in i16 pairs summed to i32: 255 * 127 * 2 < 2^31
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, weights));
+    }
+    let halves = _mm_add_epi32(_mm256_castsi256_si128(sum), _mm256_extracti128_si256(sum, 1));
+    let pairs = _mm_add_epi32(halves, _mm_shuffle_epi32(halves, 0b01_00_11_10));
+    let total = _mm_add_epi32(pairs, _mm_shuffle_epi32(pairs, 0b10_11_00_01));
+    _mm_cvtsi128_si32(total)
+}
+
+/// Same as `update_scalar`, 16 values at a time; the lengths are multiples of LANES
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn update_avx2(values: &mut [i16], weights: &[i16], add: bool) {
+    use std::arch::x86_64::*;
+
+    for (values, weights) in values.chunks_exact_mut(LANES).zip(weights.chunks_exact(LANES)) {
+        let current = _mm256_loadu_si256(values.as_ptr().cast());
+        let weights = _mm256_loadu_si256(weights.as_ptr().cast());
+        let updated = if add { _mm256_add_epi16(current, weights) } else { _mm256_sub_epi16(current, weights) };
+        _mm256_storeu_si256(values.as_mut_ptr().cast(), updated);
+    }
+}
+
+/// Accumulators along the current search path, updated move by move
+///
+/// The board does not know about the network: every `apply_move` / `unmake_move` on the searched
+/// board must be followed by `push` / `pop`, and `evaluate` checks that in debug builds
+pub struct NnueState<'a> {
+    network: &'a Network,
+    stack: Vec<Accumulator>,  // stack[ply] belongs to the position at that distance from the root
+    top: usize,
+}
+
+impl<'a> NnueState<'a> {
+    pub fn new(network: &'a Network, board: &Board) -> Self {
+        NnueState { network, stack: vec![network.refresh(board)], top: 0 }
+    }
+
+    /// Called after `board.apply_move`: derives the new accumulator from the previous one
+    pub fn push(&mut self, board: &Board) {
+        if self.top + 1 == self.stack.len() {
+            self.stack.push(self.stack[self.top].clone());
+        }
+        let (done, rest) = self.stack.split_at_mut(self.top + 1);
+        let next = &mut rest[0];
+        for (target, source) in next.values.iter_mut().zip(&done[self.top].values) {
+            target.copy_from_slice(source);
+        }
+        self.network.apply_last_move(next, board);
+        self.top += 1;
+    }
+
+    /// Called after `board.unmake_move`
+    pub fn pop(&mut self) {
+        self.top -= 1;
+    }
+
+    /// Evaluation of `board`, which must be the position the accumulators were last pushed or popped to
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        debug_assert!(
+            self.stack[self.top].values == self.network.refresh(board).values,
+            "accumulatore NNUE non aggiornato per {}",
+            board.to_fen()
+        );
+        self.network.evaluate(&self.stack[self.top], board.white_to_move)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::rng::Rng;
+
+    const TEST_HIDDEN: usize = 32;
+
+    /// A small network with random weights; biases are spread so that the clipping matters on both ends
+    fn random_network(seed: u64) -> Network {
+        random_network_of_size(seed, TEST_HIDDEN)
+    }
+
+    fn random_network_of_size(seed: u64, hidden: usize) -> Network {
+        let mut rng = Rng::new(seed);
+        let mut values = |count: usize, low: i64, high: i64| -> Vec<i64> {
+            (0..count).map(|_| low + rng.below((high - low + 1) as u64) as i64).collect()
+        };
+        Network {
+            hidden,
+            feature_weights: values(FEATURES * hidden, -40, 40).into_iter().map(|value| value as i16).collect(),
+            feature_biases: values(hidden, -200, 400).into_iter().map(|value| value as i16).collect(),
+            output_weights: values(2 * hidden, -127, 127).into_iter().map(|value| value as i8).collect(),
+            output_bias: 1000,
+            avx2: avx2_available(),
+        }
+    }
+
+    #[test]
+    fn avx2_output_matches_scalar() {
+        if !avx2_available() {
+            return;
+        }
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let values: Vec<i16> = (0..4 * LANES).map(|_| rng.below(2001) as i16 - 1000).collect();
+            let weights: Vec<i8> = (0..4 * LANES).map(|_| (rng.below(255) as i16 - 127) as i8).collect();
+            #[cfg(target_arch = "x86_64")]
+            // SAFETY: AVX2 support was checked above
+            assert_eq!(unsafe { output_avx2(&values, &weights) }, output_scalar(&values, &weights));
+        }
+
+        // Accumulator updates, adding and removing
+        for add in [true, false] {
+            let start: Vec<i16> = (0..4 * LANES).map(|_| rng.below(65536) as u16 as i16).collect();
+            let weights: Vec<i16> = (0..4 * LANES).map(|_| rng.below(65536) as u16 as i16).collect();
+            let (mut simd, mut scalar) = (start.clone(), start);
+            #[cfg(target_arch = "x86_64")]
+            // SAFETY: AVX2 support was checked above
+            unsafe { update_avx2(&mut simd, &weights, add) };
+            update_scalar(&mut scalar, &weights, add);
+            assert_eq!(simd, scalar);
+        }
+
+        // The whole evaluation, through both paths
+        let mut network = random_network(3);
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let simd = network.evaluate_board(&board);
+        network.avx2 = false;
+        assert_eq!(simd, network.evaluate_board(&board));
+    }
+
+    /// Plays `moves` on `fen`, checking after every push and pop that the incremental accumulator equals a refresh
+    fn check_incremental(network: &Network, fen: &str, moves: &[&str]) {
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut state = NnueState::new(network, &board);
+        for text in moves {
+            let mv = board.parse_uci_move(text).unwrap_or_else(|| panic!("{} illegale in {}", text, board.to_fen()));
+            board.apply_move(mv);
+            state.push(&board);
+            assert_eq!(state.stack[state.top].values, network.refresh(&board).values, "dopo {} ({})", text, board.to_fen());
+        }
+        while board.unmake_move().is_some() {
+            state.pop();
+            assert_eq!(state.stack[state.top].values, network.refresh(&board).values, "indietro a {}", board.to_fen());
+        }
+    }
+
+    #[test]
+    fn incremental_updates_match_refresh() {
+        let mut network = random_network(11);
+        // The scalar updates first, then the SIMD ones where available
+        network.avx2 = false;
+        check_incremental(&network, "r3k2r/1P6/8/3pP3/8/8/6p1/R3K2R w KQkq d6 0 1", &["e5d6", "g2h1n", "e1c1", "e8g8", "b7a8q"]);
+        network.avx2 = avx2_available();
+        // En passant, capture promotion to a knight, castling on both wings, capture promotion to a queen
+        check_incremental(&network, "r3k2r/1P6/8/3pP3/8/8/6p1/R3K2R w KQkq d6 0 1", &["e5d6", "g2h1n", "e1c1", "e8g8", "b7a8q"]);
+        // Quiet promotion and a capture by the new piece
+        check_incremental(&network, "8/P7/4k3/8/8/8/8/4K2n w - - 0 1", &["a7a8r", "h1g3", "a8a3", "e6d5", "a3g3"]);
+
+        // Random games from positions full of special moves, with take-backs along the way
+        let mut rng = Rng::new(5);
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            let mut state = NnueState::new(&network, &board);
+            for _ in 0..300 {
+                let moves = board.legal_moves();
+                if moves.is_empty() || (!board.history.is_empty() && rng.chance(0.3)) {
+                    if board.unmake_move().is_none() {
+                        break;
+                    }
+                    state.pop();
+                } else {
+                    board.apply_move(moves[rng.below(moves.len() as u64) as usize]);
+                    state.push(&board);
+                }
+                assert_eq!(state.stack[state.top].values, network.refresh(&board).values, "in {}", board.to_fen());
+                assert_eq!(state.evaluate(&board), network.evaluate_board(&board));
+            }
+        }
+    }
+
+    #[test]
+    fn search_keeps_the_accumulators_in_step() {
+        // `NnueState::evaluate` checks every evaluated node against a refresh in debug builds
+        use crate::search::{search, EndgameTables, SearchControl, SearchLimits};
+        use crate::tt::TranspositionTable;
+
+        let network = random_network(19);
+        // Random weights give no quiescence cutoffs, hence the node limit
+        let board = Board::from_fen("rnbqkb1r/pppp1ppp/5n2/4p3/3PP3/8/PPP2PPP/RNBQKBNR w KQkq - 1 3").unwrap();
+        let limits = SearchLimits { depth: Some(4), nodes: Some(20_000), ..Default::default() };
+        let mut tt = TranspositionTable::new(1);
+        let result = search(&board, &limits, &mut tt, EndgameTables::default(), Some(&network), &SearchControl::default(), &mut |_| {});
+        assert!(result.best_move.is_some());
+    }
+
+    /// The `.gknn` file contents of `network`, with the header fields given explicitly
+    fn file_bytes(network: &Network, version: u32, hidden: u32) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&hidden.to_le_bytes());
+        for value in network.feature_weights.iter().chain(&network.feature_biases) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend(network.output_weights.iter().map(|&value| value as u8));
+        bytes.extend_from_slice(&network.output_bias.to_le_bytes());
+        bytes
+    }
+
+    /// Writes `bytes` to a temporary file of its own and loads it
+    fn load_bytes(bytes: &[u8]) -> Result<Network, String> {
+        static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+        let number = NEXT_FILE.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("gk_nnue_test_{}_{}.gknn", std::process::id(), number));
+        fs::write(&path, bytes).unwrap();
+        let network = Network::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        network
+    }
+
+    #[test]
+    fn load_reads_what_was_written() {
+        let network = random_network_of_size(13, 16);
+        let loaded = load_bytes(&file_bytes(&network, VERSION, 16)).unwrap();
+        assert_eq!(loaded.hidden_size(), 16);
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(loaded.evaluate_board(&board), network.evaluate_board(&board), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn load_rejects_malformed_files() {
+        let network = random_network_of_size(17, 16);
+        let bytes = file_bytes(&network, VERSION, 16);
+        let error = |bytes: &[u8]| load_bytes(bytes).err().unwrap_or_default();
+        assert!(error(&bytes[..bytes.len() - 1]).contains("troncato"));
+        assert!(error(&bytes[..10]).contains("troncato"));
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(error(&extra).contains("in eccesso"));
+        assert!(error(&file_bytes(&network, VERSION + 1, 16)).contains("non è una rete"));
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(error(&magic).contains("non è una rete"));
+        // A whole network of 24, which is not a multiple of 16
+        let odd = random_network_of_size(17, 24);
+        assert!(error(&file_bytes(&odd, VERSION, 24)).contains("non valida"));
+    }
+}
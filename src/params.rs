@@ -0,0 +1,88 @@
+//! Evaluation weights (middlegame, endgame) in the layout of `eval::PARAM_SECTIONS`: hand-set
+//! starting values, not tuned yet. `gk_chess_engine tune` writes a replacement for this file
+
+use crate::eval::{Score, PARAM_COUNT};
+
+const fn s(mg: i32, eg: i32) -> Score {
+    Score::new(mg, eg)
+}
+
+#[rustfmt::skip]
+pub const PARAMS: [Score; PARAM_COUNT] = [
+    // Material, pawn to queen
+    s( 100,  100), s( 320,  320), s( 330,  330), s( 500,  500), s( 900,  900),
+    // Piece-square table: pawn
+    s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0),
+    s(  50,   80), s(  50,   80), s(  50,   80), s(  50,   80), s(  50,   80), s(  50,   80), s(  50,   80), s(  50,   80),
+    s(  10,   50), s(  10,   50), s(  20,   50), s(  30,   50), s(  30,   50), s(  20,   50), s(  10,   50), s(  10,   50),
+    s(   5,   30), s(   5,   30), s(  10,   30), s(  25,   30), s(  25,   30), s(  10,   30), s(   5,   30), s(   5,   30),
+    s(   0,   15), s(   0,   15), s(   0,   15), s(  20,   15), s(  20,   15), s(   0,   15), s(   0,   15), s(   0,   15),
+    s(   5,    5), s(  -5,    5), s( -10,    5), s(   0,    5), s(   0,    5), s( -10,    5), s(  -5,    5), s(   5,    5),
+    s(   5,    0), s(  10,    0), s(  10,    0), s( -20,    0), s( -20,    0), s(  10,    0), s(  10,    0), s(   5,    0),
+    s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0),
+    // Piece-square table: knight
+    s( -50,  -50), s( -40,  -40), s( -30,  -30), s( -30,  -30), s( -30,  -30), s( -30,  -30), s( -40,  -40), s( -50,  -50),
+    s( -40,  -40), s( -20,  -20), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s( -20,  -20), s( -40,  -40),
+    s( -30,  -30), s(   0,    0), s(  10,   10), s(  15,   15), s(  15,   15), s(  10,   10), s(   0,    0), s( -30,  -30),
+    s( -30,  -30), s(   5,    5), s(  15,   15), s(  20,   20), s(  20,   20), s(  15,   15), s(   5,    5), s( -30,  -30),
+    s( -30,  -30), s(   0,    0), s(  15,   15), s(  20,   20), s(  20,   20), s(  15,   15), s(   0,    0), s( -30,  -30),
+    s( -30,  -30), s(   5,    5), s(  10,   10), s(  15,   15), s(  15,   15), s(  10,   10), s(   5,    5), s( -30,  -30),
+    s( -40,  -40), s( -20,  -20), s(   0,    0), s(   5,    5), s(   5,    5), s(   0,    0), s( -20,  -20), s( -40,  -40),
+    s( -50,  -50), s( -40,  -40), s( -30,  -30), s( -30,  -30), s( -30,  -30), s( -30,  -30), s( -40,  -40), s( -50,  -50),
+    // Piece-square table: bishop
+    s( -20,  -20), s( -10,  -10), s( -10,  -10), s( -10,  -10), s( -10,  -10), s( -10,  -10), s( -10,  -10), s( -20,  -20),
+    s( -10,  -10), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s( -10,  -10),
+    s( -10,  -10), s(   0,    0), s(   5,    5), s(  10,   10), s(  10,   10), s(   5,    5), s(   0,    0), s( -10,  -10),
+    s( -10,  -10), s(   5,    5), s(   5,    5), s(  10,   10), s(  10,   10), s(   5,    5), s(   5,    5), s( -10,  -10),
+    s( -10,  -10), s(   0,    0), s(  10,   10), s(  10,   10), s(  10,   10), s(  10,   10), s(   0,    0), s( -10,  -10),
+    s( -10,  -10), s(  10,   10), s(  10,   10), s(  10,   10), s(  10,   10), s(  10,   10), s(  10,   10), s( -10,  -10),
+    s( -10,  -10), s(   5,    5), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   5,    5), s( -10,  -10),
+    s( -20,  -20), s( -10,  -10), s( -10,  -10), s( -10,  -10), s( -10,  -10), s( -10,  -10), s( -10,  -10), s( -20,  -20),
+    // Piece-square table: rook
+    s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0),
+    s(   5,    5), s(  10,   10), s(  10,   10), s(  10,   10), s(  10,   10), s(  10,   10), s(  10,   10), s(   5,    5),
+    s(  -5,   -5), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(  -5,   -5),
+    s(  -5,   -5), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(  -5,   -5),
+    s(  -5,   -5), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(  -5,   -5),
+    s(  -5,   -5), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(  -5,   -5),
+    s(  -5,   -5), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(  -5,   -5),
+    s(   0,    0), s(   0,    0), s(   0,    0), s(   5,    5), s(   5,    5), s(   0,    0), s(   0,    0), s(   0,    0),
+    // Piece-square table: queen
+    s( -20,  -20), s( -10,  -10), s( -10,  -10), s(  -5,   -5), s(  -5,   -5), s( -10,  -10), s( -10,  -10), s( -20,  -20),
+    s( -10,  -10), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s( -10,  -10),
+    s( -10,  -10), s(   0,    0), s(   5,    5), s(   5,    5), s(   5,    5), s(   5,    5), s(   0,    0), s( -10,  -10),
+    s(  -5,   -5), s(   0,    0), s(   5,    5), s(   5,    5), s(   5,    5), s(   5,    5), s(   0,    0), s(  -5,   -5),
+    s(   0,    0), s(   0,    0), s(   5,    5), s(   5,    5), s(   5,    5), s(   5,    5), s(   0,    0), s(  -5,   -5),
+    s( -10,  -10), s(   5,    5), s(   5,    5), s(   5,    5), s(   5,    5), s(   5,    5), s(   0,    0), s( -10,  -10),
+    s( -10,  -10), s(   0,    0), s(   5,    5), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s( -10,  -10),
+    s( -20,  -20), s( -10,  -10), s( -10,  -10), s(  -5,   -5), s(  -5,   -5), s( -10,  -10), s( -10,  -10), s( -20,  -20),
+    // Piece-square table: king
+    s( -30,  -50), s( -40,  -40), s( -40,  -30), s( -50,  -20), s( -50,  -20), s( -40,  -30), s( -40,  -40), s( -30,  -50),
+    s( -30,  -30), s( -40,  -20), s( -40,  -10), s( -50,    0), s( -50,    0), s( -40,  -10), s( -40,  -20), s( -30,  -30),
+    s( -30,  -30), s( -40,  -10), s( -40,   20), s( -50,   30), s( -50,   30), s( -40,   20), s( -40,  -10), s( -30,  -30),
+    s( -30,  -30), s( -40,  -10), s( -40,   30), s( -50,   40), s( -50,   40), s( -40,   30), s( -40,  -10), s( -30,  -30),
+    s( -20,  -30), s( -30,  -10), s( -30,   30), s( -40,   40), s( -40,   40), s( -30,   30), s( -30,  -10), s( -20,  -30),
+    s( -10,  -30), s( -20,  -10), s( -20,   20), s( -20,   30), s( -20,   30), s( -20,   20), s( -20,  -10), s( -10,  -30),
+    s(  20,  -30), s(  20,  -30), s(   0,    0), s(   0,    0), s(   0,    0), s(   0,    0), s(  20,  -30), s(  20,  -30),
+    s(  20,  -50), s(  30,  -30), s(  10,  -30), s(   0,  -30), s(   0,  -30), s(  10,  -30), s(  30,  -30), s(  20,  -50),
+    // Mobility per square beyond the normal count, knight to queen
+    s(   4,    4), s(   5,    5), s(   2,    4), s(   1,    2),
+    // Doubled pawn
+    s( -10,  -20),
+    // Isolated pawn
+    s( -10,  -15),
+    // Backward pawn
+    s(  -8,  -10),
+    // Pawn island beyond the first
+    s(  -5,  -10),
+    // Passed pawn by relative rank
+    s(   0,    0), s(   5,   10), s(  10,   15), s(  15,   25), s(  25,   45), s(  45,   75), s(  70,  110), s(   0,    0),
+    // Blocked passed pawn by relative rank
+    s(   0,    0), s(   2,    5), s(   5,    7), s(   7,   12), s(  12,   22), s(  22,   37), s(  35,   55), s(   0,    0),
+    // King shelter by distance of the own pawn (0 = none)
+    s( -25,    0), s(  25,    0), s(  15,    0), s(   5,    0),
+    // Pawn storm by distance of the enemy pawn (0 = none near)
+    s(   0,    0), s(  -5,    0), s( -25,    0), s( -15,    0), s(  -5,    0),
+    // King zone attack per square, knight to queen
+    s( -14,    0), s( -14,    0), s( -21,    0), s( -35,    0),
+];
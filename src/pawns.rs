@@ -0,0 +1,206 @@
+//! Pawn structure evaluation, cached in a pawn hash table keyed by the pawn placement
+
+use crate::zobrist::piece_key;
+use crate::Board;
+
+/// Entries in the pawn hash table (each search owns one)
+const PAWN_TABLE_SIZE: usize = 8192;
+
+/// Pawn structure weaknesses of one side (valued by the `eval` parameters)
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct PawnCounts {
+    pub doubled: i32,   // Extra pawns on a file
+    pub isolated: i32,  // No friendly pawn on the adjacent files
+    pub backward: i32,  // Left behind its neighbours, stop square held by an enemy pawn
+    pub islands: i32,   // Groups of adjacent pawn files beyond the first
+}
+
+/// Everything that depends only on the pawns, by colour (White, Black)
+#[derive(Copy, Clone, Default, Debug)]
+pub struct PawnEntry {
+    key: u64,
+    pub counts: [PawnCounts; 2],
+    pub passed: [u64; 2],  // Passed pawns, bit `row * 8 + col`
+}
+
+/// Hash table of analysed pawn structures
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        PawnTable { entries: vec![PawnEntry::default(); PAWN_TABLE_SIZE] }
+    }
+}
+
+impl PawnTable {
+    /// Pawn analysis of `board`, from the table when the same pawns were seen before
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let key = pawn_key(board);
+        let slot = &mut self.entries[(key % PAWN_TABLE_SIZE as u64) as usize];
+        if slot.key != key {
+            *slot = analyze(board);
+            slot.key = key;
+        }
+        *slot
+    }
+}
+
+/// Zobrist key of the pawns alone (never 0, so that empty slots do not match)
+fn pawn_key(board: &Board) -> u64 {
+    let mut key = 1;
+    for row in 1..7 {
+        for col in 0..8 {
+            let piece = board.squares[row][col];
+            if piece.type_index() == 0 {
+                key ^= piece_key(piece, row, col);
+            }
+        }
+    }
+    key
+}
+
+/// Rank of `row` from the point of view of `white` (0 = own back rank)
+pub fn relative_rank(row: usize, white: bool) -> usize {
+    if white { 7 - row } else { row }
+}
+
+/// Analyses the pawns of both sides without using the table
+pub fn analyze(board: &Board) -> PawnEntry {
+    let mut pawns: [Vec<(usize, usize)>; 2] = [Vec::new(), Vec::new()];
+    for row in 1..7 {
+        for col in 0..8 {
+            let piece = board.squares[row][col];
+            if piece.type_index() == 0 {
+                pawns[usize::from(!piece.is_white())].push((row, col));
+            }
+        }
+    }
+
+    let mut entry = PawnEntry::default();
+    for (side, white) in [(0, true), (1, false)] {
+        let (own, enemy) = (&pawns[side], &pawns[1 - side]);
+        let counts = &mut entry.counts[side];
+        let mut files = [0; 8];
+        for &(_, col) in own {
+            files[col] += 1;
+        }
+        // Moving forward means decreasing the row for White
+        let ahead = |row: usize, than: usize| if white { row < than } else { row > than };
+
+        for &(row, col) in own {
+            let adjacent = |c: usize| c.abs_diff(col) == 1;
+            let isolated = own.iter().all(|&(_, c)| !adjacent(c));
+            // Passed: no enemy pawn can stop it, and it is the front pawn of its file
+            let passed = !enemy.iter().any(|&(r, c)| c.abs_diff(col) <= 1 && ahead(r, row))
+                && !own.iter().any(|&(r, c)| c == col && ahead(r, row));
+            if isolated {
+                counts.isolated += 1;
+            }
+            if passed {
+                entry.passed[side] |= 1 << (row * 8 + col);
+            }
+            // Backward: every neighbour is further up and an enemy pawn guards the square in front
+            let guard_row = if white { row.checked_sub(2) } else { Some(row + 2) };
+            let guarded = enemy.iter().any(|&(r, c)| Some(r) == guard_row && adjacent(c));
+            let left_behind = own.iter().filter(|&&(_, c)| adjacent(c)).all(|&(r, _)| ahead(r, row));
+            if !isolated && !passed && left_behind && guarded {
+                counts.backward += 1;
+            }
+        }
+
+        counts.doubled = files.iter().map(|&count| (count - 1).max(0)).sum();
+        let islands = files.iter().zip(files.iter().skip(1).chain(std::iter::once(&0))).filter(|&(&here, &next)| here > 0 && next == 0).count() as i32;
+        counts.islands = (islands - 1).max(0);
+    }
+    entry
+}
+
+/// Relative rank of each passed pawn of one side, and whether the square in front of it is occupied
+pub fn passed_pawns(board: &Board, passed: u64, white: bool) -> impl Iterator<Item = (usize, bool)> + '_ {
+    let mut bits = passed;
+    std::iter::from_fn(move || {
+        if bits == 0 {
+            return None;
+        }
+        let square = bits.trailing_zeros() as usize;
+        bits &= bits - 1;
+        let (row, col) = (square / 8, square % 8);
+        let stop_row = if white { row - 1 } else { row + 1 };
+        Some((relative_rank(row, white), !board.squares[stop_row][col].is_empty()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::mirror_fen;
+
+    fn analyze_fen(fen: &str) -> PawnEntry {
+        analyze(&Board::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn passed_pawns() {
+        // e5 has no black pawn in front on the d, e or f file; a7 does not stop it
+        let entry = analyze_fen("4k3/p7/8/4P3/8/8/8/4K3 w - - 0 1");
+        assert_eq!(entry.passed[0], 1 << (3 * 8 + 4));
+        assert_eq!(entry.passed[1], 1 << 8);
+        // A black pawn on f6 guards e5's path; of two doubled pawns only the front one is passed
+        assert_eq!(analyze_fen("4k3/8/5p2/4P3/8/8/8/4K3 w - - 0 1").passed[0], 0);
+        assert_eq!(analyze_fen("4k3/8/8/4P3/4P3/8/8/4K3 w - - 0 1").passed[0], 1 << (3 * 8 + 4));
+    }
+
+    #[test]
+    fn isolated_doubled_and_islands() {
+        // a2 alone, c2 and c3 doubled with no neighbour: three isolated pawns on two islands
+        let counts = analyze_fen("4k3/8/8/8/8/2P5/P1P5/4K3 w - - 0 1").counts[0];
+        assert_eq!(counts, PawnCounts { doubled: 1, isolated: 3, backward: 0, islands: 1 });
+        let counts = analyze_fen("4k3/8/8/8/8/8/PPP5/4K3 w - - 0 1").counts[0];
+        assert_eq!(counts, PawnCounts::default());
+    }
+
+    #[test]
+    fn backward_pawn() {
+        // d2 is behind e3 and the black e4 pawn guards d3
+        assert_eq!(analyze_fen("4k3/8/8/8/4p3/4P3/3P4/4K3 w - - 0 1").counts[0].backward, 1);
+        // Without the guard, or level with its neighbour, it is not backward
+        assert_eq!(analyze_fen("4k3/8/8/8/8/4P3/3P4/4K3 w - - 0 1").counts[0].backward, 0);
+        assert_eq!(analyze_fen("4k3/8/8/8/4p3/8/3PP3/4K3 w - - 0 1").counts[0].backward, 0);
+    }
+
+    #[test]
+    fn mirrored_positions_swap_colours() {
+        let fens = [
+            "4k3/8/8/8/4p3/4P3/3P4/4K3 w - - 0 1",
+            "4k3/p7/8/4P3/8/2P5/P1P5/4K3 w - - 0 1",
+            "r1bqkb1r/pp3ppp/2n1pn2/3p4/2PP4/2N2N2/PP3PPP/R1BQKB1R w KQkq - 0 6",
+        ];
+        for fen in fens {
+            let entry = analyze_fen(fen);
+            let mirrored = analyze_fen(&mirror_fen(fen));
+            assert_eq!(entry.counts, [mirrored.counts[1], mirrored.counts[0]], "{}", fen);
+            assert_eq!(entry.passed[0], mirrored.passed[1].swap_bytes(), "{}", fen);
+            assert_eq!(entry.passed[1], mirrored.passed[0].swap_bytes(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn table_hits_return_the_stored_entry() {
+        let mut table = PawnTable::default();
+        let board = Board::from_fen("4k3/p7/8/4P3/8/2P5/P1P5/4K3 w - - 0 1").unwrap();
+        let entry = table.probe(&board);
+        assert_eq!(entry.counts, analyze(&board).counts);
+
+        // Same pawns with other pieces: the key matches and the stored entry comes back unchanged
+        let slot = (pawn_key(&board) % PAWN_TABLE_SIZE as u64) as usize;
+        table.entries[slot].counts[0].doubled = 99;
+        let moved_king = Board::from_fen("3k4/p7/8/4P3/8/2P5/P1P5/3RK3 w - - 0 1").unwrap();
+        assert_eq!(pawn_key(&moved_king), pawn_key(&board));
+        assert_eq!(table.probe(&moved_king).counts[0].doubled, 99);
+        // Different pawns miss and are analysed again
+        let other = Board::from_fen("4k3/p7/8/4P3/8/8/P1P5/4K3 w - - 0 1").unwrap();
+        assert_eq!(table.probe(&other).counts, analyze(&other).counts);
+    }
+}
@@ -0,0 +1,295 @@
+//! Reading PGN game collections (tag pairs and the main line of SAN moves) and writing games
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::fen::START_FEN;
+use crate::movegen::Move;
+use crate::search::MATE_SCORE;
+use crate::Board;
+
+/// Movetext lines are wrapped at this width, as the PGN standard recommends
+const LINE_WIDTH: usize = 80;
+
+/// One game of a PGN file; NAGs, variations and comments (apart from `[%eval]`) are skipped
+#[derive(Clone, Debug, Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,  // Tag pairs in file order, e.g. ("White", "Carlsen")
+    pub moves: Vec<String>,           // Main line in SAN
+    pub evals: Vec<Option<i32>>,      // `[%eval]` after each move, White's point of view (search scale for mates)
+    pub result: String,               // "1-0", "0-1", "1/2-1/2" or "*"
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Parses every game of a PGN text
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut in_movetext = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            // A tag after some movetext starts a new game (even if the result was missing)
+            '[' => {
+                chars.next();
+                if in_movetext {
+                    games.push(std::mem::take(&mut game));
+                    in_movetext = false;
+                }
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some((name, value)) = tag.split_once(' ') {
+                    let value = value.trim();
+                    let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value);
+                    let value = value.replace("\\\"", "\"");
+                    game.tags.push((name.trim().to_string(), value));
+                }
+            },
+            '{' => {
+                chars.next();
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                if let (Some(eval), Some(last)) = (parse_eval(&comment), game.evals.last_mut()) {
+                    *last = Some(eval);
+                }
+            },
+            ';' | '%' => {
+                chars.by_ref().find(|&c| c == '\n');
+            },
+            '(' => {
+                // Variations can be nested and contain comments with parentheses
+                let mut level = 0;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '(' => level += 1,
+                        ')' => level -= 1,
+                        '{' => {
+                            chars.by_ref().find(|&c| c == '}');
+                        },
+                        _ => {},
+                    }
+                    if level == 0 {
+                        break;
+                    }
+                }
+            },
+            c if c.is_whitespace() || c == ')' => {
+                chars.next();
+            },
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}();[".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                in_movetext = true;
+                if RESULTS.contains(&token.as_str()) {
+                    game.result = token;
+                    games.push(std::mem::take(&mut game));
+                    in_movetext = false;
+                } else if let Some(san) = move_from_token(&token) {
+                    game.moves.push(san.to_string());
+                    game.evals.push(None);
+                }
+            },
+        }
+    }
+    if in_movetext || !game.tags.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+/// Score of an `[%eval 0.35]` or `[%eval #-2]` command inside a comment
+fn parse_eval(comment: &str) -> Option<i32> {
+    let value = comment.split("[%eval ").nth(1)?.split(|c: char| c == ']' || c.is_whitespace()).next()?;
+    match value.strip_prefix('#') {
+        Some(moves) => {
+            let moves: i32 = moves.parse().ok()?;
+            Some(if moves > 0 { MATE_SCORE - 2 * moves + 1 } else { -MATE_SCORE - 2 * moves })
+        },
+        None => Some((value.parse::<f64>().ok()? * 100.0).round() as i32),
+    }
+}
+
+/// Strips a move number ("12.", "12...", "12.e4") from a token; NAGs ("$1") yield nothing
+fn move_from_token(token: &str) -> Option<&str> {
+    if token.starts_with('$') || token == "e.p." {
+        return None;
+    }
+    let san = match token.rfind('.') {
+        Some(dot) if token[..dot].chars().all(|c| c.is_ascii_digit() || c == '.') => &token[dot + 1..],
+        _ => token,
+    };
+    (!san.is_empty()).then_some(san)
+}
+
+/// A move to write, followed by its annotation (NAGs, `{comments}`, `(variations)`), which may be empty
+pub struct PgnMove {
+    pub mv: Move,
+    pub annotation: String,
+}
+
+/// Writes a game: the tags in the given order (plus SetUp/FEN for a non-standard start), then the
+/// numbered SAN moves wrapped at 80 columns and the result
+pub fn write_game(tags: &[(&str, String)], start: &Board, moves: &[PgnMove], result: &str) -> String {
+    let mut text = String::new();
+    for (name, value) in tags {
+        text.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "\\\"")));
+    }
+    let fen = start.to_fen();
+    if fen != START_FEN {
+        text.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen));
+    }
+    text.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut board = start.clone();
+    let mut numbered = false;
+    for entry in moves {
+        // Black moves get their number again at the start and after an annotation
+        if board.white_to_move {
+            tokens.push(format!("{}.", board.fullmove_number));
+        } else if !numbered {
+            tokens.push(format!("{}...", board.fullmove_number));
+        }
+        tokens.push(board.to_san(entry.mv));
+        tokens.extend(entry.annotation.split_whitespace().map(str::to_string));
+        numbered = board.white_to_move && entry.annotation.is_empty();
+        board.apply_move(entry.mv);
+    }
+    tokens.push(result.to_string());
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    text.push_str(&line);
+    text.push_str("\n\n");
+    text
+}
+
+/// Today's date as a PGN Date tag value (UTC)
+pub fn pgn_date() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() / 86_400) as i64;
+    // Civil date from days since 1970-01-01 (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// Numbered SAN of a line played from `start`, as written inside a variation: "12... Nf6 13. e4"
+pub fn format_line(start: &Board, moves: &[Move]) -> String {
+    let mut tokens = Vec::new();
+    let mut board = start.clone();
+    for (index, &mv) in moves.iter().enumerate() {
+        if board.white_to_move {
+            tokens.push(format!("{}.", board.fullmove_number));
+        } else if index == 0 {
+            tokens.push(format!("{}...", board.fullmove_number));
+        }
+        tokens.push(board.to_san(mv));
+        board.apply_move(mv);
+    }
+    tokens.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: &str = r#"[Event "Torneo di prova"]
+[White "Carlsen, \"Magnus\""]
+[Black "Nepomniachtchi"]
+
+1. e4 {[%eval 0.35]} e5 2. Nf3 (2. f4 exf4 (2... d5 {una (finta) variante}) 3. Nf3) Nc6 $1
+; commento fino a fine riga 9. Qxf7
+3. Bb5 {Spagnola} a6 {[%eval #-2]} 1-0
+
+[Event "Secondo"]
+
+1.d4 d5 2.c4 1/2-1/2
+1. c4 *
+"#;
+
+    #[test]
+    fn games_with_tags_comments_and_variations() {
+        let games = parse_pgn(GAMES);
+        assert_eq!(games.len(), 3);
+
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("Torneo di prova"));
+        assert_eq!(game.tag("White"), Some("Carlsen, \"Magnus\""));
+        assert_eq!(game.tag("Round"), None);
+        assert_eq!(game.moves, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        assert_eq!(game.evals, [Some(35), None, None, None, None, Some(-MATE_SCORE + 4)]);
+        assert_eq!(game.result, "1-0");
+
+        assert_eq!(games[1].moves, ["d4", "d5", "c4"]);
+        assert_eq!(games[1].result, "1/2-1/2");
+        assert!(games[2].tags.is_empty());
+        assert_eq!((games[2].moves.as_slice(), games[2].result.as_str()), (["c4".to_string()].as_slice(), "*"));
+    }
+
+    #[test]
+    fn evals_in_comments() {
+        assert_eq!(parse_eval("[%eval -1.2]"), Some(-120));
+        assert_eq!(parse_eval("buona [%eval #3] mossa"), Some(MATE_SCORE - 5));
+        assert_eq!(parse_eval("[%clk 0:10:00]"), None);
+    }
+
+    #[test]
+    fn written_games_read_back() {
+        let start = Board::new();
+        let mut board = start.clone();
+        let texts = ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O", "Be7", "Re1", "b5", "Bb3", "d6", "c3", "O-O"];
+        let moves: Vec<PgnMove> = texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| {
+                let mv = board.parse_san(text).unwrap();
+                board.apply_move(mv);
+                let annotation = if index == 0 { "$1 {la migliore}".to_string() } else { String::new() };
+                PgnMove { mv, annotation }
+            })
+            .collect();
+        let text = write_game(&[("Event", "Test \"rapido\"".to_string())], &start, &moves, "*");
+        assert!(text.starts_with("[Event \"Test \\\"rapido\\\"\"]\n\n1. e4 $1 {la migliore} 1... e5 2. Nf3 Nc6 3. Bb5"), "{}", text);
+        assert!(text.lines().all(|line| line.len() <= LINE_WIDTH), "{}", text);
+        assert!(text.ends_with("8. c3 O-O *\n\n"), "{}", text);
+
+        let games = parse_pgn(&text);
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].tag("Event"), Some("Test \"rapido\""));
+        assert_eq!(games[0].moves, texts);
+
+        // A different start position is recorded in SetUp and FEN tags
+        let start = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+        let mv = start.parse_san("Kd7").unwrap();
+        let text = write_game(&[], &start, &[PgnMove { mv, annotation: String::new() }], "*");
+        assert_eq!(text, "[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n\n1... Kd7 *\n\n");
+    }
+}
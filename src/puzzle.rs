@@ -0,0 +1,428 @@
+//! Tactical puzzles from games: positions where a single move wins, with a forced solution line and
+//! detected themes: `gk_chess_engine puzzles`
+
+use std::collections::HashSet;
+use std::fs;
+
+use crate::annotate::judged;
+use crate::eval::PIECE_VALUES;
+use crate::movegen::{Move, BISHOP_DIRECTIONS, KING_OFFSETS, ROOK_DIRECTIONS};
+use crate::pgn::{parse_pgn, PgnGame};
+use crate::search::{self, EndgameTables, SearchControl, SearchLimits, MATE_SCORE};
+use crate::tt::TranspositionTable;
+use crate::{Board, Piece};
+
+/// The solver's best move must score at least this (centipawns, side to move's point of view)
+const WINNING_SCORE: i32 = 200;
+/// ...and beat the second-best move by at least this much
+const ONLY_MOVE_GAP: i32 = 300;
+/// In analysed games only positions after a move that lost this much are searched
+const CANDIDATE_SWING: i32 = 200;
+/// Longest solution, in moves of the solver
+const MAX_SOLVER_MOVES: usize = 6;
+/// Value of a king when deciding what a fork attacks
+const KING_VALUE: i32 = 10_000;
+
+/// Tactical motif found in a solution
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Theme {
+    Fork,
+    Pin,
+    MateIn(usize),
+    BackRankMate,
+}
+
+impl Theme {
+    /// Identifier written in puzzle files
+    fn tag(self) -> String {
+        match self {
+            Theme::Fork => "fork".to_string(),
+            Theme::Pin => "pin".to_string(),
+            Theme::MateIn(moves) => format!("mateIn{}", moves),
+            Theme::BackRankMate => "backRankMate".to_string(),
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Theme> {
+        match tag {
+            "fork" => Some(Theme::Fork),
+            "pin" => Some(Theme::Pin),
+            "backRankMate" => Some(Theme::BackRankMate),
+            _ => tag.strip_prefix("mateIn")?.parse().ok().map(Theme::MateIn),
+        }
+    }
+
+    /// Name shown to the user
+    pub fn name(self) -> String {
+        match self {
+            Theme::Fork => "forchetta".to_string(),
+            Theme::Pin => "inchiodatura".to_string(),
+            Theme::MateIn(moves) => format!("matto in {}", moves),
+            Theme::BackRankMate => "matto sulla traversa".to_string(),
+        }
+    }
+}
+
+/// A position with the solver to move and the solution: solver and opponent moves alternating,
+/// starting and ending with the solver's
+pub struct Puzzle {
+    pub board: Board,
+    pub solution: Vec<Move>,
+    pub themes: Vec<Theme>,
+    pub source: String,  // Game and move the position comes from
+}
+
+impl Puzzle {
+    /// One line of a puzzle file: `<FEN>;<solution in UCI>;<themes>;<source>`
+    fn to_line(&self) -> String {
+        let moves: Vec<String> = self.solution.iter().map(|mv| mv.to_uci()).collect();
+        let themes: Vec<String> = self.themes.iter().map(|theme| theme.tag()).collect();
+        format!("{};{};{};{}", self.board.to_fen(), moves.join(" "), themes.join(" "), self.source.replace(';', ","))
+    }
+
+    /// Reads a line written by `to_line`; unknown themes are ignored and the source may be missing
+    fn parse_line(line: &str) -> Result<Puzzle, String> {
+        let mut fields = line.splitn(4, ';');
+        let board = Board::from_fen(fields.next().unwrap_or_default().trim())?;
+        let mut position = board.clone();
+        let mut solution = Vec::new();
+        for text in fields.next().unwrap_or_default().split_whitespace() {
+            let mv = position.parse_uci_move(text).ok_or_else(|| format!("mossa non valida {}", text))?;
+            position.apply_move(mv);
+            solution.push(mv);
+        }
+        if solution.is_empty() {
+            return Err("soluzione mancante".to_string());
+        }
+        let themes = fields.next().unwrap_or_default().split_whitespace().filter_map(Theme::from_tag).collect();
+        let source = fields.next().unwrap_or_default().trim().to_string();
+        Ok(Puzzle { board, solution, themes, source })
+    }
+
+    /// Number of moves the solver has to find
+    pub fn solver_moves(&self) -> usize {
+        self.solution.len().div_ceil(2)
+    }
+}
+
+/// Reads a puzzle file; empty lines and lines starting with '#' are skipped
+pub fn load_puzzles(path: &str) -> Result<Vec<Puzzle>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("Impossibile leggere {}: {}", path, err))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| Puzzle::parse_line(line).map_err(|err| format!("{}, riga {}: {}", path, number + 1, err)))
+        .collect()
+}
+
+/// The move that wins in `board` if it is the only one (MultiPV search of the two best moves)
+fn only_winning_move(board: &Board, limits: &SearchLimits, tt: &mut TranspositionTable) -> Option<Move> {
+    let limits = SearchLimits { multipv: 2, ..limits.clone() };
+    let control = SearchControl::default();
+    let result = search::search(board, &limits, tt, EndgameTables::default(), None, &control, &mut |_| {});
+    let best = result.lines.first()?;
+    if judged(best.score) < WINNING_SCORE {
+        return None;
+    }
+    // Any mate in one solves the last step, so alternatives do not matter there
+    let unique = best.score == MATE_SCORE - 1
+        || result.lines.get(1).is_none_or(|second| judged(best.score) - judged(second.score) >= ONLY_MOVE_GAP);
+    unique.then(|| best.pv[0])
+}
+
+/// Solution of the puzzle in `board`, if it has one: the line goes on while the opponent's best
+/// reply leaves the solver a single winning move
+fn find_solution(board: &Board, limits: &SearchLimits, tt: &mut TranspositionTable) -> Option<Vec<Move>> {
+    if board.legal_moves().len() < 2 {
+        return None;
+    }
+    let first = only_winning_move(board, limits, tt)?;
+    // Taking back a piece just captured is no puzzle
+    if let Some(last) = board.history.last() {
+        if last.captured != Piece::Empty && (last.mv.to_row, last.mv.to_col) == (first.to_row, first.to_col) {
+            return None;
+        }
+    }
+
+    let mut solution = vec![first];
+    let mut board = board.clone();
+    board.apply_move(first);
+    while solution.len() < 2 * MAX_SOLVER_MOVES - 1 && !board.legal_moves().is_empty() && !board.is_draw_by_rule() {
+        let control = SearchControl::default();
+        let reply = search::search(&board, limits, tt, EndgameTables::default(), None, &control, &mut |_| {});
+        let Some(reply) = reply.best_move else { break };
+        board.apply_move(reply);
+        let Some(next) = only_winning_move(&board, limits, tt) else { break };
+        board.apply_move(next);
+        solution.extend([reply, next]);
+    }
+    Some(solution)
+}
+
+/// Squares attacked by the piece on (row, col)
+fn attacked_squares(board: &Board, row: usize, col: usize) -> Vec<(usize, usize)> {
+    let piece = board.squares[row][col];
+    let mut squares = Vec::new();
+    let mut push = |r: i32, c: i32| {
+        if (0..8).contains(&r) && (0..8).contains(&c) {
+            squares.push((r as usize, c as usize));
+        }
+    };
+    match piece.type_index() {
+        0 => {
+            let r = if piece.is_white() { row as i32 - 1 } else { row as i32 + 1 };
+            push(r, col as i32 - 1);
+            push(r, col as i32 + 1);
+        },
+        5 => KING_OFFSETS.iter().for_each(|&(dr, dc)| push(row as i32 + dr, col as i32 + dc)),
+        _ => board.for_each_attack(row, col, |r, c| push(r as i32, c as i32)),
+    }
+    squares
+}
+
+fn value(piece: Piece) -> i32 {
+    if piece.type_index() == 5 { KING_VALUE } else { PIECE_VALUES[piece.type_index()] }
+}
+
+/// The piece on (row, col) attacks two enemy pieces (pawns aside) that are worth more than it or undefended
+fn is_fork(board: &Board, row: usize, col: usize) -> bool {
+    let piece = board.squares[row][col];
+    let targets = attacked_squares(board, row, col)
+        .into_iter()
+        .filter(|&(r, c)| {
+            let target = board.squares[r][c];
+            target.is_color(!piece.is_white())
+                && target.type_index() != 0
+                && (value(target) > value(piece) || !board.is_attacked_by(r, c, target.is_white()))
+        })
+        .count();
+    targets >= 2
+}
+
+/// The slider on (row, col) pins an enemy piece to its king or to a more valuable piece
+fn is_pin(board: &Board, row: usize, col: usize) -> bool {
+    let piece = board.squares[row][col];
+    let directions: &[(i32, i32)] = match piece.type_index() {
+        2 => &BISHOP_DIRECTIONS,
+        3 => &ROOK_DIRECTIONS,
+        4 => &KING_OFFSETS,
+        _ => return false,
+    };
+    directions.iter().any(|&(dr, dc)| {
+        let mut ray = (1..8)
+            .map(|step| (row as i32 + dr * step, col as i32 + dc * step))
+            .take_while(|&(r, c)| (0..8).contains(&r) && (0..8).contains(&c))
+            .map(|(r, c)| board.squares[r as usize][c as usize])
+            .filter(|square| !square.is_empty());
+        match (ray.next(), ray.next()) {
+            (Some(pinned), Some(behind)) => {
+                let enemy = !piece.is_white();
+                pinned.is_color(enemy) && pinned.type_index() != 5 && behind.is_color(enemy) && value(behind) > value(pinned)
+            },
+            _ => false,
+        }
+    })
+}
+
+/// Checkmate by a rook or queen along the back rank of a king walled in by its own pieces
+fn is_back_rank_mate(board: &Board, last: Move) -> bool {
+    let mated_white = board.white_to_move;
+    let Some((row, col)) = board.find_king(mated_white) else { return false };
+    let back_row = if mated_white { 7 } else { 0 };
+    let front_row = if mated_white { 6 } else { 1 };
+    row == back_row
+        && last.to_row == back_row
+        && matches!(board.squares[last.to_row][last.to_col].type_index(), 3 | 4)
+        && board.squares[front_row][col].is_color(mated_white)
+}
+
+/// Themes of a solution played from `start`
+fn detect_themes(start: &Board, solution: &[Move]) -> Vec<Theme> {
+    let mut themes = Vec::new();
+    let mut board = start.clone();
+    for (index, &mv) in solution.iter().enumerate() {
+        board.apply_move(mv);
+        if index % 2 != 0 {
+            continue;
+        }
+        if !themes.contains(&Theme::Fork) && is_fork(&board, mv.to_row, mv.to_col) {
+            themes.push(Theme::Fork);
+        }
+        if !themes.contains(&Theme::Pin) && is_pin(&board, mv.to_row, mv.to_col) {
+            themes.push(Theme::Pin);
+        }
+    }
+    if let (true, Some(&last)) = (board.is_checkmate(), solution.last()) {
+        themes.push(Theme::MateIn(solution.len().div_ceil(2)));
+        if is_back_rank_mate(&board, last) {
+            themes.push(Theme::BackRankMate);
+        }
+    }
+    themes
+}
+
+/// Whether the move with index `index` lost enough, by the game's `[%eval]` comments, for the position
+/// after it to be worth searching
+fn is_candidate(game: &PgnGame, index: usize, mover_white: bool) -> bool {
+    let before = if index == 0 { Some(0) } else { game.evals[index - 1] };
+    let (Some(before), Some(after)) = (before, game.evals[index]) else { return false };
+    let swing = judged(before) - judged(after);
+    (if mover_white { swing } else { -swing }) >= CANDIDATE_SWING
+}
+
+/// Puzzles of one game; `seen` holds the positions already used
+fn game_puzzles(game: &PgnGame, limits: &SearchLimits, tt: &mut TranspositionTable, seen: &mut HashSet<String>) -> Result<Vec<Puzzle>, String> {
+    let mut board = match game.tag("FEN") {
+        Some(fen) => Board::from_fen(fen)?,
+        None => Board::new(),
+    };
+    // Without an analysis every position is searched
+    let analysed = game.evals.iter().any(Option::is_some);
+    let source = format!("{} - {}, {}", game.tag("White").unwrap_or("?"), game.tag("Black").unwrap_or("?"), game.tag("Date").unwrap_or("?"));
+    let mut puzzles = Vec::new();
+    for (index, san) in game.moves.iter().enumerate() {
+        let mv = board.parse_san(san).ok_or_else(|| format!("mossa non valida {}", san))?;
+        let mover_white = board.white_to_move;
+        board.apply_move(mv);
+        if analysed && !is_candidate(game, index, mover_white) {
+            continue;
+        }
+        let position = board.to_fen().split_whitespace().take(4).collect::<Vec<_>>().join(" ");
+        if !seen.insert(position) {
+            continue;
+        }
+        if let Some(solution) = find_solution(&board, limits, tt) {
+            let number = format!("{}{}", board.fullmove_number, if board.white_to_move { "." } else { "..." });
+            puzzles.push(Puzzle {
+                themes: detect_themes(&board, &solution),
+                board: board.clone(),
+                solution,
+                source: format!("{}, mossa {}", source, number),
+            });
+        }
+    }
+    Ok(puzzles)
+}
+
+/// `puzzles <games.pgn> <puzzles.txt> [--movetime ms | --depth N]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "Uso: gk_chess_engine puzzles <partite.pgn> <puzzle.txt> [--movetime ms | --depth N]";
+    let (limits, paths) = search::parse_search_limits(args, 1000)?;
+    let [input, output] = paths[..] else { return Err(USAGE.to_string()) };
+
+    let text = fs::read_to_string(input).map_err(|err| format!("Impossibile leggere {}: {}", input, err))?;
+    let games = parse_pgn(&text);
+    let mut tt = TranspositionTable::new(64);
+    let mut seen = HashSet::new();
+    let mut lines = Vec::new();
+    for (number, game) in games.iter().enumerate() {
+        let puzzles = game_puzzles(game, &limits, &mut tt, &mut seen).map_err(|err| format!("Partita {}: {}", number + 1, err))?;
+        println!("Partita {}/{}: {} puzzle", number + 1, games.len(), puzzles.len());
+        for puzzle in &puzzles {
+            lines.push(puzzle.to_line());
+            println!("  {}", lines.last().unwrap());
+        }
+    }
+    let text: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    fs::write(output, text).map_err(|err| format!("Impossibile scrivere {}: {}", output, err))?;
+    println!("{} puzzle scritti in {}", lines.len(), output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(fen: &str) -> Option<(Board, Vec<Move>)> {
+        let board = Board::from_fen(fen).unwrap();
+        let limits = SearchLimits { depth: Some(5), ..Default::default() };
+        let solution = find_solution(&board, &limits, &mut TranspositionTable::new(4))?;
+        Some((board, solution))
+    }
+
+    #[test]
+    fn unique_winning_fork_is_a_puzzle() {
+        // Nc7+ forks king and rook; every other move leaves White only a pawn up
+        let (board, solution) = solve("4k3/8/r7/3N4/8/8/5PPP/4K3 w - - 0 1").expect("la forchetta è un puzzle");
+        assert_eq!(board.to_san(solution[0]), "Nc7+");
+        assert_eq!(solution.len(), 3);
+        assert_eq!((solution[2].to_row, solution[2].to_col), (2, 0), "Nxa6");
+        assert!(detect_themes(&board, &solution).contains(&Theme::Fork));
+    }
+
+    #[test]
+    fn back_rank_mates() {
+        let (board, solution) = solve("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").expect("matto in una");
+        assert_eq!(board.to_san(solution[0]), "Ra8#");
+        assert_eq!(detect_themes(&board, &solution), [Theme::MateIn(1), Theme::BackRankMate]);
+
+        // Re8+ Rxe8 Rxe8#: only the front rook can start it
+        let (board, solution) = solve("r5k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1").expect("matto in due");
+        let moves: Vec<String> = solution.iter().map(|mv| mv.to_uci()).collect();
+        assert_eq!(moves, ["e2e8", "a8e8", "e1e8"]);
+        let themes = detect_themes(&board, &solution);
+        assert!(themes.contains(&Theme::MateIn(2)) && themes.contains(&Theme::BackRankMate), "{:?}", themes);
+
+        // A mate with the king free to step forward is not a back rank mate
+        let board = Board::from_fen("7k/R7/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let mate = board.parse_uci_move("a7a8").unwrap();
+        assert_eq!(detect_themes(&board, &[mate]), [Theme::MateIn(1)]);
+    }
+
+    #[test]
+    fn pins() {
+        // Bb5 pins the knight to its king, Bd3 does not
+        let board = Board::from_fen("4k3/8/2n5/8/8/8/8/4KB2 w - - 0 1").unwrap();
+        let pin = board.parse_uci_move("f1b5").unwrap();
+        assert_eq!(detect_themes(&board, &[pin]), [Theme::Pin]);
+        let quiet = board.parse_uci_move("f1d3").unwrap();
+        assert!(detect_themes(&board, &[quiet]).is_empty());
+
+        // A rook on h4's rank is pinned only with a more valuable piece behind it
+        let board = Board::from_fen("4k3/q7/8/8/3r4/8/8/4K2R w - - 0 1").unwrap();
+        let pin = board.parse_uci_move("h1h4").unwrap();
+        assert!(!detect_themes(&board, &[pin]).contains(&Theme::Pin));
+        let board = Board::from_fen("4k3/8/8/8/r2r4/8/8/4K2R w - - 0 1").unwrap();
+        assert!(!detect_themes(&board, &[pin]).contains(&Theme::Pin));
+        let board = Board::from_fen("4k3/8/8/8/q2r4/8/8/4K2R w - - 0 1").unwrap();
+        assert_eq!(detect_themes(&board, &[pin]), [Theme::Pin]);
+    }
+
+    #[test]
+    fn puzzle_lines_round_trip() {
+        let board = Board::from_fen("r5k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1").unwrap();
+        let mut position = board.clone();
+        let solution: Vec<Move> = ["e2e8", "a8e8", "e1e8"]
+            .iter()
+            .map(|text| {
+                let mv = position.parse_uci_move(text).unwrap();
+                position.apply_move(mv);
+                mv
+            })
+            .collect();
+        let puzzle = Puzzle {
+            board,
+            solution,
+            themes: vec![Theme::MateIn(2), Theme::BackRankMate, Theme::Fork, Theme::Pin],
+            source: "Bianco - Nero; 2026.10.18, mossa 1.".to_string(),
+        };
+        let line = puzzle.to_line();
+        assert_eq!(line, "r5k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1;e2e8 a8e8 e1e8;mateIn2 backRankMate fork pin;Bianco - Nero, 2026.10.18, mossa 1.");
+
+        let read = Puzzle::parse_line(&line).unwrap();
+        assert_eq!(read.board.to_fen(), puzzle.board.to_fen());
+        assert_eq!(read.solution, puzzle.solution);
+        assert_eq!(read.themes, puzzle.themes);
+        assert_eq!(read.source, "Bianco - Nero, 2026.10.18, mossa 1.");
+        assert_eq!(read.solver_moves(), 2);
+
+        assert!(Puzzle::parse_line("r5k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1;;fork").is_err(), "soluzione mancante");
+        assert!(Puzzle::parse_line("r5k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1;e2e9").is_err());
+    }
+
+    #[test]
+    fn quiet_positions_are_rejected() {
+        assert!(solve(crate::fen::START_FEN).is_none());
+        assert!(solve("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").is_none());
+    }
+}
@@ -0,0 +1,395 @@
+//! "Puzzle" panel of the GUI: the user solves the puzzles of a file on the board, with a rating and a
+//! streak kept on disk
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+
+use crate::puzzle::{load_puzzles, Puzzle};
+use crate::rng::Rng;
+use crate::Board;
+
+/// Where rating, streak and totals are kept between sessions, next to the puzzle file
+const PROGRESS_FILE: &str = "progressi_puzzle.txt";
+/// Rating of a new solver
+const START_RATING: f64 = 1500.0;
+/// How much one puzzle can move the rating
+const RATING_K: f64 = 32.0;
+/// Pause before the opponent's reply is played
+const REPLY_DELAY: Duration = Duration::from_millis(500);
+
+/// Rating, streak and totals of the solver
+struct Progress {
+    rating: f64,
+    streak: u32,       // Puzzles solved in a row
+    best_streak: u32,
+    solved: u32,
+    failed: u32,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress { rating: START_RATING, streak: 0, best_streak: 0, solved: 0, failed: 0 }
+    }
+}
+
+/// The progress file of the puzzles in `puzzle_file`: `PROGRESS_FILE` in the same directory
+fn progress_path(puzzle_file: &str) -> PathBuf {
+    Path::new(puzzle_file).parent().unwrap_or(Path::new("")).join(PROGRESS_FILE)
+}
+
+impl Progress {
+    /// Reads a progress file (`key=value` lines); a missing file means a new solver
+    fn load(path: &Path) -> Progress {
+        let mut progress = Progress::default();
+        let Ok(text) = fs::read_to_string(path) else { return progress };
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            let value = value.trim();
+            match key.trim() {
+                "rating" => progress.rating = value.parse().unwrap_or(START_RATING),
+                "streak" => progress.streak = value.parse().unwrap_or(0),
+                "best_streak" => progress.best_streak = value.parse().unwrap_or(0),
+                "solved" => progress.solved = value.parse().unwrap_or(0),
+                "failed" => progress.failed = value.parse().unwrap_or(0),
+                _ => {},
+            }
+        }
+        progress
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let text = format!(
+            "rating={:.1}\nstreak={}\nbest_streak={}\nsolved={}\nfailed={}\n",
+            self.rating, self.streak, self.best_streak, self.solved, self.failed
+        );
+        fs::write(path, text).map_err(|err| format!("Impossibile scrivere {}: {}", path.display(), err))
+    }
+
+    /// Elo update against a puzzle of rating `puzzle_rating`
+    fn record(&mut self, puzzle_rating: f64, solved: bool) {
+        let expected = 1.0 / (1.0 + 10f64.powf((puzzle_rating - self.rating) / 400.0));
+        self.rating += RATING_K * (if solved { 1.0 } else { 0.0 } - expected);
+        if solved {
+            self.solved += 1;
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        } else {
+            self.failed += 1;
+            self.streak = 0;
+        }
+    }
+}
+
+/// Estimated difficulty of a puzzle: every move to find makes it harder
+fn puzzle_rating(puzzle: &Puzzle) -> f64 {
+    1000.0 + 300.0 * puzzle.solver_moves() as f64
+}
+
+/// What the board has to do after the panel was drawn
+pub enum PuzzleAction {
+    Show(Board),  // Set up the position of a new puzzle
+    Leave,        // Back to normal play
+}
+
+pub struct PuzzleTrainer {
+    path: String,                 // Contents of the file field
+    puzzles: Vec<Puzzle>,
+    unplayed: Vec<usize>,         // Puzzles not shown yet in this round
+    current: Option<usize>,       // Puzzle on the board; None in normal play
+    step: usize,                  // Moves of the solution already on the board
+    recorded: bool,               // The current puzzle already counted in the progress
+    hint: bool,                   // Highlight the piece to move
+    reply_at: Option<Instant>,    // When the opponent's reply is due
+    progress: Progress,
+    progress_path: Option<PathBuf>,  // Progress file of the loaded puzzles; None until a file is loaded
+    message: String,
+    rng: Rng,
+}
+
+impl Default for PuzzleTrainer {
+    fn default() -> Self {
+        PuzzleTrainer {
+            path: "puzzle.txt".to_string(),
+            puzzles: Vec::new(),
+            unplayed: Vec::new(),
+            current: None,
+            step: 0,
+            recorded: false,
+            hint: false,
+            reply_at: None,
+            progress: Progress::default(),
+            progress_path: None,
+            message: String::new(),
+            rng: Rng::new(Rng::time_seed()),
+        }
+    }
+}
+
+impl PuzzleTrainer {
+    /// True while a puzzle is on the board
+    pub fn is_active(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Back to normal play
+    pub fn leave(&mut self) {
+        self.current = None;
+        self.reply_at = None;
+    }
+
+    /// True when the user is expected to play the next move of the solution
+    pub fn accepts_moves(&self) -> bool {
+        self.reply_at.is_none() && self.current.is_some_and(|index| self.step < self.puzzles[index].solution.len())
+    }
+
+    /// Square of the piece to move, once a hint was asked for
+    pub fn hint_square(&self) -> Option<(usize, usize)> {
+        let index = self.current.filter(|_| self.hint && self.accepts_moves())?;
+        let mv = self.puzzles[index].solution[self.step];
+        Some((mv.from_row, mv.from_col))
+    }
+
+    /// Counts the result of the current puzzle, once
+    fn record(&mut self, solved: bool) {
+        let Some(index) = self.current.filter(|_| !self.recorded) else { return };
+        self.recorded = true;
+        self.progress.record(puzzle_rating(&self.puzzles[index]), solved);
+        if let Some(Err(err)) = self.progress_path.as_ref().map(|path| self.progress.save(path)) {
+            self.message = err;
+        }
+    }
+
+    /// Highlights the piece to move; the puzzle then no longer counts as solved
+    fn show_hint(&mut self) {
+        self.hint = true;
+        self.record(false);
+    }
+
+    /// Checks the move the user just played on `board`: a wrong move is taken back
+    pub fn user_moved(&mut self, board: &mut Board) {
+        let (Some(index), Some(undo)) = (self.current, board.history.last()) else { return };
+        let solution = &self.puzzles[index].solution;
+        // Any mate ends the puzzle, even one other than the stored solution
+        let last_step = self.step + 1 == solution.len();
+        if undo.mv != solution[self.step] && !(last_step && board.is_checkmate()) {
+            board.unmake_move();
+            self.record(false);
+            self.message = "Mossa sbagliata, riprova".to_string();
+            return;
+        }
+        self.step += 1;
+        self.hint = false;
+        if last_step {
+            self.record(true);
+            self.message = "Puzzle risolto!".to_string();
+        } else {
+            self.reply_at = Some(Instant::now() + REPLY_DELAY);
+            self.message = "Giusto!".to_string();
+        }
+    }
+
+    /// Plays the opponent's reply once it is due
+    pub fn update(&mut self, board: &mut Board, ctx: &egui::Context) {
+        let (Some(index), Some(reply_at)) = (self.current, self.reply_at) else { return };
+        let now = Instant::now();
+        if now < reply_at {
+            ctx.request_repaint_after(reply_at - now);
+            return;
+        }
+        board.apply_move(self.puzzles[index].solution[self.step]);
+        self.step += 1;
+        self.reply_at = None;
+        // A file may end a solution with the opponent's reply: the user already found every move
+        if self.step == self.puzzles[index].solution.len() {
+            self.record(true);
+            self.message = "Puzzle risolto!".to_string();
+        }
+    }
+
+    /// Picks a puzzle not shown yet in this round
+    fn next_puzzle(&mut self) -> PuzzleAction {
+        if self.unplayed.is_empty() {
+            self.unplayed = (0..self.puzzles.len()).collect();
+        }
+        let index = self.unplayed.swap_remove(self.rng.below(self.unplayed.len() as u64) as usize);
+        self.current = Some(index);
+        self.step = 0;
+        self.recorded = false;
+        self.hint = false;
+        self.reply_at = None;
+        self.message.clear();
+        PuzzleAction::Show(self.puzzles[index].board.clone())
+    }
+
+    /// Draws the panel; returns what the board has to do
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<PuzzleAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.text_edit_singleline(&mut self.path);
+            if ui.button("Carica").clicked() {
+                match load_puzzles(self.path.trim()) {
+                    Ok(puzzles) => {
+                        self.message = format!("Puzzle caricati: {}", puzzles.len());
+                        self.puzzles = puzzles;
+                        let path = progress_path(self.path.trim());
+                        self.progress = Progress::load(&path);
+                        self.progress_path = Some(path);
+                    },
+                    Err(err) => self.message = err,
+                }
+                self.unplayed.clear();
+                if self.is_active() {
+                    self.leave();
+                    action = Some(PuzzleAction::Leave);
+                }
+            }
+        });
+        let progress = &self.progress;
+        ui.label(format!(
+            "Punteggio {:.0}, serie {} (record {}), risolti {}, sbagliati {}",
+            progress.rating, progress.streak, progress.best_streak, progress.solved, progress.failed
+        ));
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!self.puzzles.is_empty(), egui::Button::new("Nuovo puzzle")).clicked() {
+                action = Some(self.next_puzzle());
+            }
+            if ui.add_enabled(self.accepts_moves() && !self.hint, egui::Button::new("Suggerimento")).clicked() {
+                self.show_hint();
+            }
+            if ui.add_enabled(self.is_active(), egui::Button::new("Esci")).clicked() {
+                self.leave();
+                self.message.clear();
+                action = Some(PuzzleAction::Leave);
+            }
+        });
+
+        if let Some(index) = self.current {
+            let puzzle = &self.puzzles[index];
+            let side = if puzzle.board.white_to_move { "il Bianco" } else { "il Nero" };
+            ui.label(format!("Muove {}: trova {} mosse (difficoltà {:.0})", side, puzzle.solver_moves(), puzzle_rating(puzzle)));
+            // Themes and origin would give the solution away
+            if !self.accepts_moves() && self.reply_at.is_none() {
+                let themes: Vec<String> = puzzle.themes.iter().map(|theme| theme.name()).collect();
+                if !themes.is_empty() {
+                    ui.label(format!("Temi: {}", themes.join(", ")));
+                }
+                if !puzzle.source.is_empty() {
+                    ui.label(format!("Da: {}", puzzle.source));
+                }
+            }
+        }
+        if !self.message.is_empty() {
+            ui.label(&self.message);
+        }
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rating_and_streak() {
+        let mut progress = Progress::default();
+        // Against an equal rating a result moves the rating by half of RATING_K
+        progress.record(START_RATING, true);
+        assert!((progress.rating - (START_RATING + RATING_K / 2.0)).abs() < 1e-9);
+        progress.record(1000.0, true);
+        assert!(progress.rating - (START_RATING + RATING_K / 2.0) < 2.0, "un puzzle facile vale poco");
+        assert_eq!((progress.streak, progress.best_streak, progress.solved, progress.failed), (2, 2, 2, 0));
+
+        let before = progress.rating;
+        progress.record(2500.0, false);
+        assert!(before - progress.rating < 1.0, "sbagliare un puzzle difficile costa poco");
+        assert_eq!((progress.streak, progress.best_streak, progress.solved, progress.failed), (0, 2, 2, 1));
+        progress.record(progress.rating, true);
+        assert_eq!((progress.streak, progress.best_streak), (1, 2));
+    }
+
+    #[test]
+    fn progress_file_round_trip() {
+        assert_eq!(progress_path("puzzle/tattica.txt"), Path::new("puzzle").join(PROGRESS_FILE));
+        assert_eq!(progress_path("tattica.txt"), Path::new(PROGRESS_FILE));
+
+        let path = std::env::temp_dir().join(format!("gk_progressi_{}.txt", std::process::id()));
+        let progress = Progress { rating: 1612.5, streak: 3, best_streak: 7, solved: 12, failed: 4 };
+        progress.save(&path).unwrap();
+        let read = Progress::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!((read.rating, read.streak, read.best_streak, read.solved, read.failed), (1612.5, 3, 7, 12, 4));
+        assert_eq!(Progress::load(&path).rating, START_RATING, "file mancante");
+    }
+
+    /// A trainer with one puzzle on the board: 1. Re8+ Rxe8 2. Rxe8#
+    fn trainer() -> (PuzzleTrainer, Board) {
+        let board = Board::from_fen("r5k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1").unwrap();
+        let mut position = board.clone();
+        let solution = ["e2e8", "a8e8", "e1e8"]
+            .iter()
+            .map(|text| {
+                let mv = position.parse_uci_move(text).unwrap();
+                position.apply_move(mv);
+                mv
+            })
+            .collect();
+        let puzzle = Puzzle { board, solution, themes: Vec::new(), source: String::new() };
+        let mut trainer = PuzzleTrainer { puzzles: vec![puzzle], ..Default::default() };
+        let PuzzleAction::Show(board) = trainer.next_puzzle() else { panic!("nessun puzzle") };
+        (trainer, board)
+    }
+
+    fn play(trainer: &mut PuzzleTrainer, board: &mut Board, text: &str) {
+        let mv = board.parse_uci_move(text).unwrap();
+        board.apply_move(mv);
+        trainer.user_moved(board);
+    }
+
+    #[test]
+    fn solving_a_puzzle() {
+        let (mut trainer, mut board) = trainer();
+        play(&mut trainer, &mut board, "e2e8");
+        assert!(!trainer.accepts_moves(), "aspetta la risposta");
+        trainer.reply_at = Some(Instant::now());
+        trainer.update(&mut board, &egui::Context::default());
+        assert_eq!(board.history.len(), 2);
+        assert!(trainer.accepts_moves());
+        play(&mut trainer, &mut board, "e1e8");
+        assert!(!trainer.accepts_moves());
+        assert_eq!((trainer.progress.solved, trainer.progress.streak), (1, 1));
+        assert!(trainer.progress.rating > START_RATING);
+    }
+
+    #[test]
+    fn wrong_moves_are_taken_back_and_count_once() {
+        let (mut trainer, mut board) = trainer();
+        let start = board.to_fen();
+        play(&mut trainer, &mut board, "e2e3");
+        assert_eq!(board.to_fen(), start, "mossa ritirata");
+        assert!(trainer.accepts_moves());
+        play(&mut trainer, &mut board, "e2e4");
+        assert_eq!((trainer.progress.failed, trainer.progress.solved), (1, 0));
+
+        // Solving it afterwards does not turn the failure into a success
+        play(&mut trainer, &mut board, "e2e8");
+        trainer.reply_at = Some(Instant::now());
+        trainer.update(&mut board, &egui::Context::default());
+        play(&mut trainer, &mut board, "e1e8");
+        assert_eq!((trainer.progress.failed, trainer.progress.solved), (1, 0));
+    }
+
+    #[test]
+    fn hints_show_the_piece_and_fail_the_puzzle() {
+        let (mut trainer, mut board) = trainer();
+        assert_eq!(trainer.hint_square(), None);
+        trainer.show_hint();
+        assert_eq!(trainer.hint_square(), Some((6, 4)), "la torre in e2");
+        assert_eq!((trainer.progress.failed, trainer.progress.streak), (1, 0));
+        play(&mut trainer, &mut board, "e2e8");
+        assert_eq!(trainer.hint_square(), None, "il suggerimento vale per una mossa");
+    }
+}
@@ -0,0 +1,218 @@
+//! Standard algebraic notation (SAN), as used in PGN files: "Nf3", "exd5", "O-O", "e8=Q+"
+
+use crate::movegen::{parse_square, square_name, Move};
+use crate::{Board, Piece};
+
+/// SAN letters by piece type index (pawns have none)
+const PIECE_LETTERS: [&str; 6] = ["", "N", "B", "R", "Q", "K"];
+
+/// Piece type index for a SAN piece letter
+fn type_from_letter(letter: char) -> Option<usize> {
+    match letter {
+        'N' => Some(1),
+        'B' => Some(2),
+        'R' => Some(3),
+        'Q' => Some(4),
+        'K' => Some(5),
+        _ => None,
+    }
+}
+
+impl Board {
+    /// Parses a SAN move, returning it only if it is legal in this position
+    pub fn parse_san(&self, text: &str) -> Option<Move> {
+        // Check marks and annotation glyphs ("+", "#", "!", "?") carry no move information
+        let text = text.trim_end_matches(['+', '#', '!', '?']);
+        let text = text.strip_suffix("e.p.").unwrap_or(text);
+        let legal = self.legal_moves();
+
+        // Castling is written with letter O (some files use zeros)
+        let castle_col = match text {
+            "O-O" | "0-0" => Some(6),
+            "O-O-O" | "0-0-0" => Some(2),
+            _ => None,
+        };
+        if let Some(to_col) = castle_col {
+            return legal.into_iter().find(|mv| {
+                matches!(self.squares[mv.from_row][mv.from_col], Piece::KingWhite | Piece::KingBlack)
+                    && mv.from_col == 4
+                    && mv.to_col == to_col
+            });
+        }
+
+        let mut chars: Vec<char> = text.chars().collect();
+        let type_index = match chars.first().copied().and_then(type_from_letter) {
+            Some(type_index) => {
+                chars.remove(0);
+                type_index
+            },
+            None => 0,
+        };
+
+        // Promotion: "e8=Q" or "e8Q"
+        let mut promotion = None;
+        if let Some(promoted) = chars.last().copied().and_then(type_from_letter) {
+            promotion = Some(Piece::from_type_index(promoted, self.white_to_move));
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        if chars.len() < 2 {
+            return None;
+        }
+        let destination: String = chars[chars.len() - 2..].iter().collect();
+        let (to_row, to_col) = parse_square(&destination)?;
+
+        // Whatever is left before the destination is disambiguation (file, rank or both) and "x"
+        let mut from_col = None;
+        let mut from_row = None;
+        for &c in &chars[..chars.len() - 2] {
+            match c {
+                'a'..='h' => from_col = Some(c as usize - 'a' as usize),
+                '1'..='8' => from_row = Some(8 - (c as usize - '0' as usize)),
+                'x' | ':' | '-' => {},
+                _ => return None,
+            }
+        }
+
+        let mut candidates = legal.into_iter().filter(|mv| {
+            self.squares[mv.from_row][mv.from_col].type_index() == type_index
+                && (mv.to_row, mv.to_col) == (to_row, to_col)
+                && mv.promotion == promotion
+                && from_col.is_none_or(|col| col == mv.from_col)
+                && from_row.is_none_or(|row| row == mv.from_row)
+        });
+        let mv = candidates.next()?;
+        // An ambiguous move is an error rather than a guess
+        candidates.next().is_none().then_some(mv)
+    }
+
+    /// Writes a legal move in SAN, with the check or mate mark
+    pub fn to_san(&self, mv: Move) -> String {
+        let piece = self.squares[mv.from_row][mv.from_col];
+        let type_index = piece.type_index();
+        let mut text = if type_index == 5 && mv.from_col.abs_diff(mv.to_col) == 2 {
+            if mv.to_col == 6 { "O-O" } else { "O-O-O" }.to_string()
+        } else {
+            let capture = !self.squares[mv.to_row][mv.to_col].is_empty()
+                || (type_index == 0 && mv.from_col != mv.to_col);
+            let mut text = PIECE_LETTERS[type_index].to_string();
+            if type_index == 0 {
+                if capture {
+                    text.push((b'a' + mv.from_col as u8) as char);
+                }
+            } else {
+                // Disambiguate by file if that is enough, then by rank, then by both
+                let rivals: Vec<Move> = self
+                    .legal_moves()
+                    .into_iter()
+                    .filter(|other| {
+                        (other.to_row, other.to_col) == (mv.to_row, mv.to_col)
+                            && (other.from_row, other.from_col) != (mv.from_row, mv.from_col)
+                            && self.squares[other.from_row][other.from_col] == piece
+                    })
+                    .collect();
+                if !rivals.is_empty() {
+                    let square = square_name(mv.from_row, mv.from_col);
+                    if rivals.iter().all(|other| other.from_col != mv.from_col) {
+                        text.push_str(&square[..1]);
+                    } else if rivals.iter().all(|other| other.from_row != mv.from_row) {
+                        text.push_str(&square[1..]);
+                    } else {
+                        text.push_str(&square);
+                    }
+                }
+            }
+            if capture {
+                text.push('x');
+            }
+            text.push_str(&square_name(mv.to_row, mv.to_col));
+            if let Some(promoted) = mv.promotion {
+                text.push('=');
+                text.push_str(PIECE_LETTERS[promoted.type_index()]);
+            }
+            text
+        };
+
+        let mut after = self.clone();
+        after.apply_move(mv);
+        if after.in_check() {
+            text.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Board;
+
+    fn uci(board: &Board, text: &str) -> Option<crate::movegen::Move> {
+        board.parse_uci_move(text)
+    }
+
+    #[test]
+    fn disambiguation() {
+        let board = Board::from_fen("4k3/8/8/8/R7/8/4K3/R6R w - - 0 1").unwrap();
+        assert_eq!(board.parse_san("Rad1"), uci(&board, "a1d1"));
+        assert_eq!(board.parse_san("Rhd1"), uci(&board, "h1d1"));
+        assert_eq!(board.parse_san("R1a3"), uci(&board, "a1a3"));
+        assert_eq!(board.parse_san("R4a3"), uci(&board, "a4a3"));
+        assert_eq!(board.parse_san("Ra1a3"), uci(&board, "a1a3"));
+        assert_eq!(board.to_san(uci(&board, "h1d1").unwrap()), "Rhd1");
+        assert_eq!(board.to_san(uci(&board, "a4a3").unwrap()), "R4a3");
+        assert_eq!(board.to_san(uci(&board, "a4b4").unwrap()), "Rb4");
+
+        // Rivals on the same file and on the same rank need the whole square
+        let board = Board::from_fen("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1").unwrap();
+        assert_eq!(board.to_san(uci(&board, "a1b2").unwrap()), "Qa1b2");
+        assert_eq!(board.parse_san("Qa1b2"), uci(&board, "a1b2"));
+    }
+
+    #[test]
+    fn ambiguous_and_illegal_moves_are_rejected() {
+        let board = Board::from_fen("4k3/8/8/8/R7/8/4K3/R6R w - - 0 1").unwrap();
+        for text in ["Rd1", "Ra3", "Rd8", "Kf4", "Nf3", "O-O", "e4", "Zz9", "R", ""] {
+            assert_eq!(board.parse_san(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn promotions_and_castling() {
+        let board = Board::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.parse_san("a8=Q"), uci(&board, "a7a8q"));
+        assert_eq!(board.parse_san("a8N"), uci(&board, "a7a8n"));
+        assert_eq!(board.parse_san("axb8=R+"), uci(&board, "a7b8r"));
+        assert_eq!(board.parse_san("a8"), None, "manca il pezzo promosso");
+        assert_eq!(board.to_san(uci(&board, "a7b8q").unwrap()), "axb8=Q+");
+        assert_eq!(board.to_san(uci(&board, "a7a8n").unwrap()), "a8=N");
+
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        for (texts, expected) in [(["O-O", "0-0"], "e1g1"), (["O-O-O", "0-0-0"], "e1c1")] {
+            for text in texts {
+                assert_eq!(board.parse_san(text), uci(&board, expected), "{}", text);
+            }
+            assert_eq!(board.to_san(uci(&board, expected).unwrap()), texts[0]);
+        }
+    }
+
+    #[test]
+    fn every_legal_move_round_trips() {
+        let fens = [
+            crate::fen::START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "4k3/8/8/8/R7/8/4K3/R6R w - - 0 1",
+        ];
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+            for mv in board.legal_moves() {
+                let san = board.to_san(mv);
+                assert_eq!(board.parse_san(&san), Some(mv), "{} in {}", san, fen);
+            }
+        }
+    }
+}
@@ -0,0 +1,738 @@
+//! Iterative deepening alpha-beta search with transposition table, time management and pondering
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::dtm::DtmTables;
+use crate::endgame;
+use crate::eval::{evaluate, has_non_pawn_material, PIECE_VALUES};
+use crate::movegen::Move;
+use crate::nnue::{Network, NnueState};
+use crate::pawns::PawnTable;
+use crate::rng::Rng;
+use crate::skill::Skill;
+use crate::syzygy::Tablebases;
+use crate::tt::{Bound, TranspositionTable};
+use crate::{Board, Piece};
+
+pub const INFINITY: i32 = 32_001;
+pub const MATE_SCORE: i32 = 32_000;
+pub const MAX_PLY: usize = 128;
+/// Scores beyond this bound are mate scores
+pub const MATE_BOUND: i32 = MATE_SCORE - MAX_PLY as i32;
+
+/// Time kept in reserve for communication lag, in milliseconds
+const MOVE_OVERHEAD_MS: u64 = 30;
+/// How often (in nodes) the clock and the stop flag are polled
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Limits for one search, as given by the UCI `go` command or by the GUI
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,   // Milliseconds per move
+    pub wtime: Option<u64>,      // Remaining clock times and increments, in milliseconds
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+    pub ponder: bool,            // Search on the opponent's time until ponderhit or stop
+    pub multipv: usize,          // Number of best root moves to report (0 or 1 = just the best)
+    pub skill: Option<Skill>,    // Deliberately weakened play; None = full strength
+}
+
+impl SearchLimits {
+    /// Soft (don't start another iteration) and hard (abort now) time budgets for the side to move
+    fn time_budget(&self, white: bool) -> (Option<Duration>, Option<Duration>) {
+        if self.infinite {
+            return (None, None);
+        }
+        if let Some(movetime) = self.movetime {
+            let budget = Duration::from_millis(movetime.saturating_sub(MOVE_OVERHEAD_MS).max(1));
+            return (Some(budget), Some(budget));
+        }
+        let (time, increment) = if white { (self.wtime, self.winc) } else { (self.btime, self.binc) };
+        let Some(time) = time else {
+            return (None, None);
+        };
+        let available = time.saturating_sub(MOVE_OVERHEAD_MS).max(1);
+        let moves_to_go = self.movestogo.unwrap_or(30).clamp(1, 50) as u64;
+        let soft = (available / moves_to_go + increment.unwrap_or(0) * 3 / 4).min(available / 2).max(1);
+        let hard = (soft * 4).min(available * 3 / 4).max(soft);
+        (Some(Duration::from_millis(soft)), Some(Duration::from_millis(hard)))
+    }
+}
+
+/// Flags shared between a running search and the thread controlling it
+#[derive(Default)]
+pub struct SearchControl {
+    stop: AtomicBool,
+    pondering: AtomicBool,
+}
+
+impl SearchControl {
+    /// Prepares the flags for a new search
+    pub fn reset(&self, ponder: bool) {
+        self.stop.store(false, Ordering::SeqCst);
+        self.pondering.store(ponder, Ordering::SeqCst);
+    }
+
+    /// Asks the search to finish as soon as possible
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// The opponent played the expected move: the ponder search becomes a normal timed search
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress report emitted after every completed iteration
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub seldepth: usize,
+    pub score: i32,
+    pub nodes: u64,
+    pub time_ms: u64,
+    pub hashfull: usize,
+    pub multipv: usize,  // 1-based index of the reported line
+    pub tbhits: u64,     // Successful tablebase probes
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    /// Nodes per second
+    pub fn nps(&self) -> u64 {
+        self.nodes * 1000 / self.time_ms.max(1)
+    }
+}
+
+/// One root move with its score and principal variation (MultiPV)
+#[derive(Clone, Debug)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+/// Final outcome of a search
+#[derive(Clone, Debug, Default)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub ponder_move: Option<Move>,  // Expected reply, to be searched on the opponent's time
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub tbhits: u64,
+    pub pv: Vec<Move>,
+    pub lines: Vec<PvLine>,         // Best root moves of the last completed iteration, best first
+}
+
+/// Endgame tables the search may consult
+#[derive(Copy, Clone, Default)]
+pub struct EndgameTables<'a> {
+    pub syzygy: Option<&'a Tablebases>,
+    pub dtm: Option<&'a DtmTables>,
+}
+
+/// Splits the arguments of an analysis tool into `--movetime ms` / `--depth N` (a search of
+/// `default_movetime` ms when neither is given) and the other arguments
+pub fn parse_search_limits(args: &[String], default_movetime: u64) -> Result<(SearchLimits, Vec<&str>), String> {
+    let mut limits = SearchLimits { movetime: Some(default_movetime), ..Default::default() };
+    let mut rest = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).ok_or_else(|| format!("Valore mancante per {}", args[i]));
+        let number = || value.clone()?.parse::<u64>().map_err(|_| format!("Valore non valido per {}", args[i]));
+        match args[i].as_str() {
+            "--movetime" => limits = SearchLimits { movetime: Some(number()?), ..Default::default() },
+            "--depth" => limits = SearchLimits { depth: Some(number()? as u32), ..Default::default() },
+            other => {
+                rest.push(other);
+                i += 1;
+                continue;
+            },
+        }
+        i += 2;
+    }
+    Ok((limits, rest))
+}
+
+/// Formats a score for UCI output: "cp 35" or "mate -3"
+pub fn format_score(score: i32) -> String {
+    if score >= MATE_BOUND {
+        format!("mate {}", (MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("mate -{}", (MATE_SCORE + score) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+/// Runs a complete search of `board` and returns the best move found
+/// While pondering (or in infinite mode) the result is held back until ponderhit or stop
+pub fn search(
+    board: &Board,
+    limits: &SearchLimits,
+    tt: &mut TranspositionTable,
+    tables: EndgameTables<'_>,
+    network: Option<&Network>,
+    control: &SearchControl,
+    on_info: &mut dyn FnMut(&SearchInfo),
+) -> SearchResult {
+    // A limited skill caps the search and may make it blind to captures at the horizon; it searches
+    // extra lines to choose from, but only the requested ones are reported
+    let reported_lines = limits.multipv.max(1);
+    let mut limits = limits.clone();
+    let mut skill_rng = None;
+    let mut horizon_error = false;
+    if let Some(skill) = limits.skill {
+        skill.restrict(&mut limits);
+        let mut rng = Rng::new(skill.seed ^ board.hash);
+        horizon_error = rng.chance(skill.horizon_error_probability());
+        skill_rng = Some(rng);
+    }
+
+    let (soft_limit, hard_limit) = limits.time_budget(board.white_to_move);
+    let mut searcher = Searcher {
+        board: board.clone(),
+        tt,
+        pawns: PawnTable::default(),
+        nnue: network.map(|network| NnueState::new(network, board)),
+        tables,
+        control,
+        node_limit: limits.nodes,
+        started: Instant::now(),
+        soft_limit,
+        hard_limit,
+        pondering: control.is_pondering(),
+        nodes: 0,
+        tbhits: 0,
+        seldepth: 0,
+        stopped: false,
+        killers: [[None; 2]; MAX_PLY],
+        history: [[0; 64]; 12],
+        pv: vec![Vec::new(); MAX_PLY + 1],
+        excluded_root_moves: Vec::new(),
+        reported_lines,
+        horizon_error,
+    };
+    let mut result = searcher.iterative_deepening(&limits, on_info);
+    if let (Some(skill), Some(rng)) = (limits.skill, skill_rng.as_mut()) {
+        skill.pick_line(&mut result, rng);
+    }
+
+    // UCI forbids sending bestmove while pondering or in infinite mode before the GUI says so
+    while (limits.infinite || control.is_pondering()) && !control.is_stopped() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    result
+}
+
+struct Searcher<'a> {
+    board: Board,
+    tt: &'a mut TranspositionTable,
+    pawns: PawnTable,                       // Pawn structure cache, private to this search
+    nnue: Option<NnueState<'a>>,            // Network accumulators, when NNUE replaces the handcrafted evaluation
+    tables: EndgameTables<'a>,
+    control: &'a SearchControl,
+    node_limit: Option<u64>,
+    started: Instant,              // Search start; time spent pondering counts against the budget
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    pondering: bool,               // Clock is not running while pondering
+    nodes: u64,
+    tbhits: u64,
+    seldepth: usize,
+    stopped: bool,
+    killers: [[Option<Move>; 2]; MAX_PLY],  // Quiet moves that caused a beta cutoff, per ply
+    history: [[i32; 64]; 12],               // Quiet move success counters [piece][to square]
+    pv: Vec<Vec<Move>>,                     // Triangular principal variation table
+    excluded_root_moves: Vec<Move>,         // Root moves already reported in earlier MultiPV lines
+    reported_lines: usize,                  // MultiPV asked by the user; a limited skill searches more lines
+    horizon_error: bool,                    // Skip the quiescence search (weakened play)
+}
+
+impl Searcher<'_> {
+    fn iterative_deepening(&mut self, limits: &SearchLimits, on_info: &mut dyn FnMut(&SearchInfo)) -> SearchResult {
+        let root_moves = self.board.legal_moves();
+        let mut result = SearchResult { best_move: root_moves.first().copied(), ..Default::default() };
+        if root_moves.is_empty() {
+            return result;
+        }
+
+        // In a tablebase position the tables already know the best move; DTM tables mate fastest
+        let root_probe = self.tables.dtm.and_then(|dtm| dtm.probe_root(&self.board)).or_else(|| {
+            self.tables.syzygy.filter(|tb| tb.can_probe(&self.board)).and_then(|tb| tb.probe_root(&self.board))
+        });
+        if let Some(probe) = root_probe {
+            self.tbhits += 1;
+            let line = PvLine { score: probe.score, pv: vec![probe.best_move] };
+            on_info(&SearchInfo {
+                depth: 1,
+                seldepth: 1,
+                score: probe.score,
+                nodes: self.nodes,
+                time_ms: self.started.elapsed().as_millis() as u64,
+                hashfull: self.tt.hashfull(),
+                multipv: 1,
+                tbhits: self.tbhits,
+                pv: line.pv.clone(),
+            });
+            return SearchResult {
+                best_move: Some(probe.best_move),
+                score: probe.score,
+                depth: 1,
+                tbhits: self.tbhits,
+                pv: line.pv.clone(),
+                lines: vec![line],
+                ..Default::default()
+            };
+        }
+
+        let max_depth = limits.depth.unwrap_or(MAX_PLY as u32 - 1).clamp(1, MAX_PLY as u32 - 1);
+        let multipv = limits.multipv.clamp(1, root_moves.len());
+        for depth in 1..=max_depth {
+            // Each extra line is searched with the better root moves excluded
+            let mut lines: Vec<PvLine> = Vec::with_capacity(multipv);
+            for pv_index in 0..multipv {
+                self.seldepth = 0;
+                self.excluded_root_moves = lines.iter().map(|line| line.pv[0]).collect();
+                let score = self.negamax(depth as i32, -INFINITY, INFINITY, 0, true);
+                if self.stopped && (depth > 1 || self.pv[0].is_empty()) {
+                    break;
+                }
+                lines.push(PvLine { score, pv: self.pv[0].clone() });
+                if pv_index < self.reported_lines {
+                    on_info(&SearchInfo {
+                        depth,
+                        seldepth: self.seldepth,
+                        score,
+                        nodes: self.nodes,
+                        time_ms: self.started.elapsed().as_millis() as u64,
+                        hashfull: self.tt.hashfull(),
+                        multipv: pv_index + 1,
+                        tbhits: self.tbhits,
+                        pv: self.pv[0].clone(),
+                    });
+                }
+                if self.stopped {
+                    break;
+                }
+            }
+
+            // An interrupted iteration is only trusted when nothing better is available
+            if lines.is_empty() || (lines.len() < multipv && depth > 1) {
+                break;
+            }
+            lines.sort_by_key(|line| -line.score);
+            result.best_move = Some(lines[0].pv[0]);
+            result.pv = lines[0].pv.clone();
+            result.score = lines[0].score;
+            result.depth = depth;
+            result.lines = lines;
+
+            if self.stopped {
+                break;
+            }
+            self.update_ponder_state();
+            if !self.pondering {
+                // Not enough time left for another iteration, or nothing to think about
+                if self.soft_limit.is_some_and(|soft| self.started.elapsed() >= soft) {
+                    break;
+                }
+                if root_moves.len() == 1 && self.soft_limit.is_some() {
+                    break;
+                }
+            }
+            // A forced mate shorter than the current depth will not change
+            if result.score.abs() >= MATE_BOUND && MATE_SCORE - result.score.abs() < depth as i32 {
+                break;
+            }
+        }
+        self.excluded_root_moves.clear();
+
+        result.nodes = self.nodes;
+        result.tbhits = self.tbhits;
+        result.ponder_move = result.pv.get(1).copied().or_else(|| self.ponder_move_from_tt(result.best_move));
+        result
+    }
+
+    /// Guesses the opponent's reply from the transposition table when the PV is too short
+    fn ponder_move_from_tt(&mut self, best_move: Option<Move>) -> Option<Move> {
+        let best_move = best_move?;
+        self.board.apply_move(best_move);
+        let reply = self
+            .tt
+            .probe(self.board.hash, 0)
+            .and_then(|entry| entry.best_move)
+            .filter(|mv| self.board.legal_moves().contains(mv));
+        self.board.unmake_move();
+        reply
+    }
+
+    /// Plays a pseudo-legal move, keeping the network accumulators in step; false (and nothing
+    /// played) if it leaves the king in check
+    fn make_move(&mut self, mv: Move) -> bool {
+        self.board.apply_move(mv);
+        if self.board.king_attacked(!self.board.white_to_move) {
+            self.board.unmake_move();
+            return false;
+        }
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push(&self.board);
+        }
+        true
+    }
+
+    fn unmake_move(&mut self) {
+        self.board.unmake_move();
+        if let Some(nnue) = &mut self.nnue {
+            nnue.pop();
+        }
+    }
+
+    /// Static evaluation with the network if one is in use; known endgames keep their own evaluators
+    fn evaluate(&mut self) -> i32 {
+        match &self.nnue {
+            Some(nnue) => endgame::evaluate(&self.board).unwrap_or_else(|| nnue.evaluate(&self.board)),
+            None => evaluate(&self.board, &mut self.pawns),
+        }
+    }
+
+    /// Starts enforcing the time budget when a ponder search turns into a real one; the time
+    /// already spent thinking is part of the budget, so only what is left of it remains
+    fn update_ponder_state(&mut self) {
+        if self.pondering && !self.control.is_pondering() {
+            self.pondering = false;
+        }
+    }
+
+    /// Polls node limit, stop flag and clock
+    fn check_limits(&mut self) {
+        if self.node_limit.is_some_and(|limit| self.nodes >= limit) {
+            self.stopped = true;
+        }
+        if !self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            return;
+        }
+        if self.control.is_stopped() {
+            self.stopped = true;
+        }
+        self.update_ponder_state();
+        if !self.pondering && self.hard_limit.is_some_and(|hard| self.started.elapsed() >= hard) {
+            self.stopped = true;
+        }
+    }
+
+    fn negamax(&mut self, depth: i32, mut alpha: i32, beta: i32, ply: usize, allow_null: bool) -> i32 {
+        self.pv[ply].clear();
+        let in_check = self.board.in_check();
+        // Check extension
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth <= 0 {
+            return self.quiescence(alpha, beta, ply);
+        }
+
+        self.nodes += 1;
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+
+        let is_pv = beta - alpha > 1;
+        if ply > 0 {
+            if self.board.is_repetition() || self.board.is_draw_by_rule() {
+                return 0;
+            }
+            if ply >= MAX_PLY - 1 {
+                return self.evaluate();
+            }
+
+            // DTM tables know the exact distance to mate
+            if let Some(dtm) = self.tables.dtm.and_then(|tables| tables.probe(&self.board)) {
+                self.tbhits += 1;
+                let score = dtm.score(ply);
+                self.tt.store(self.board.hash, depth, score, Bound::Exact, None, ply);
+                return score;
+            }
+
+            // Right after a capture or pawn move the WDL tables give the exact outcome
+            if let Some(tablebases) = self.tables.syzygy {
+                if depth >= tablebases.probe_depth && self.board.halfmove_clock == 0 && tablebases.can_probe(&self.board) {
+                    if let Some(score) = tablebases.probe_wdl(&self.board, ply) {
+                        self.tbhits += 1;
+                        self.tt.store(self.board.hash, depth, score, Bound::Exact, None, ply);
+                        return score;
+                    }
+                }
+            }
+        }
+
+        // Transposition table cutoff
+        let tt_entry = self.tt.probe(self.board.hash, ply);
+        let tt_move = tt_entry.and_then(|entry| entry.best_move);
+        if let Some(entry) = tt_entry {
+            if ply > 0 && !is_pv && entry.depth >= depth {
+                let cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => entry.score >= beta,
+                    Bound::Upper => entry.score <= alpha,
+                };
+                if cutoff {
+                    return entry.score;
+                }
+            }
+        }
+
+        // Null move pruning: if passing still fails high, the position is good enough
+        if allow_null && !is_pv && !in_check && depth >= 3 && ply > 0
+            && has_non_pawn_material(&self.board, self.board.white_to_move)
+            && self.evaluate() >= beta
+        {
+            let reduction = 2 + depth / 6;
+            let en_passant = self.board.make_null_move();
+            let score = -self.negamax(depth - 1 - reduction, -beta, -beta + 1, ply + 1, false);
+            self.board.unmake_null_move(en_passant);
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta && score < MATE_BOUND {
+                return beta;
+            }
+        }
+
+        let mut moves = Vec::with_capacity(64);
+        self.board.generate_pseudo_moves(false, &mut moves);
+        let mut scores: Vec<i32> = moves.iter().map(|&mv| self.move_order_score(mv, tt_move, ply)).collect();
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut legal_moves = 0;
+
+        for i in 0..moves.len() {
+            let mv = pick_move(&mut moves, &mut scores, i);
+            if ply == 0 && self.excluded_root_moves.contains(&mv) {
+                continue;
+            }
+            let is_quiet = self.is_quiet(mv);
+            let piece = self.board.squares[mv.from_row][mv.from_col];
+
+            if !self.make_move(mv) {
+                continue;
+            }
+            legal_moves += 1;
+
+            let score = if legal_moves == 1 {
+                -self.negamax(depth - 1, -beta, -alpha, ply + 1, true)
+            } else {
+                // Late move reductions for quiet moves that are unlikely to matter
+                let reduction = if depth >= 3 && legal_moves > 3 && is_quiet && !in_check && !self.board.in_check() {
+                    if legal_moves > 10 { 2 } else { 1 }
+                } else {
+                    0
+                };
+                // Principal variation search: null window first, re-search if it beats alpha
+                let mut score = -self.negamax(depth - 1 - reduction, -alpha - 1, -alpha, ply + 1, true);
+                if score > alpha && reduction > 0 {
+                    score = -self.negamax(depth - 1, -alpha - 1, -alpha, ply + 1, true);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(depth - 1, -beta, -alpha, ply + 1, true);
+                }
+                score
+            };
+            self.unmake_move();
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mv);
+                    if score >= beta {
+                        if is_quiet {
+                            self.record_quiet_cutoff(mv, piece, depth, ply);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        if legal_moves == 0 {
+            return if in_check { -MATE_SCORE + ply as i32 } else { 0 };
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(self.board.hash, depth, best_score, bound, best_move, ply);
+        best_score
+    }
+
+    /// Searches captures only until the position is quiet, to avoid horizon blunders
+    fn quiescence(&mut self, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+
+        let stand_pat = self.evaluate();
+        if ply >= MAX_PLY - 1 || stand_pat >= beta || self.horizon_error {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = Vec::with_capacity(32);
+        self.board.generate_pseudo_moves(true, &mut moves);
+        let mut scores: Vec<i32> = moves.iter().map(|&mv| self.move_order_score(mv, None, ply)).collect();
+
+        let mut best_score = stand_pat;
+        for i in 0..moves.len() {
+            let mv = pick_move(&mut moves, &mut scores, i);
+            if !self.make_move(mv) {
+                continue;
+            }
+            let score = -self.quiescence(-beta, -alpha, ply + 1);
+            self.unmake_move();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        best_score
+    }
+
+    /// Quiet = neither a capture (including en passant) nor a promotion
+    fn is_quiet(&self, mv: Move) -> bool {
+        let piece = self.board.squares[mv.from_row][mv.from_col];
+        let is_en_passant = matches!(piece, Piece::PawnWhite | Piece::PawnBlack) && mv.from_col != mv.to_col;
+        mv.promotion.is_none() && self.board.squares[mv.to_row][mv.to_col].is_empty() && !is_en_passant
+    }
+
+    /// Ordering: TT move, captures by MVV-LVA, promotions, killers, then history
+    fn move_order_score(&self, mv: Move, tt_move: Option<Move>, ply: usize) -> i32 {
+        if Some(mv) == tt_move {
+            return 1_000_000;
+        }
+        let piece = self.board.squares[mv.from_row][mv.from_col];
+        let victim = self.board.squares[mv.to_row][mv.to_col];
+        if !victim.is_empty() {
+            return 100_000 + PIECE_VALUES[victim.type_index()] * 10 - piece.type_index() as i32;
+        }
+        if let Some(promotion) = mv.promotion {
+            return 90_000 + PIECE_VALUES[promotion.type_index()];
+        }
+        if !self.is_quiet(mv) {
+            // En passant
+            return 100_000 + PIECE_VALUES[0] * 10;
+        }
+        if self.killers[ply][0] == Some(mv) {
+            return 80_000;
+        }
+        if self.killers[ply][1] == Some(mv) {
+            return 79_000;
+        }
+        self.history[piece.index()][mv.to_row * 8 + mv.to_col]
+    }
+
+    /// Remembers a quiet move that refuted this node (killer and history heuristics)
+    fn record_quiet_cutoff(&mut self, mv: Move, piece: Piece, depth: i32, ply: usize) {
+        if self.killers[ply][0] != Some(mv) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(mv);
+        }
+        let entry = &mut self.history[piece.index()][mv.to_row * 8 + mv.to_col];
+        *entry = (*entry + depth * depth).min(50_000);
+    }
+
+    /// Principal variation at `ply` becomes `mv` followed by the child's variation
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        let (head, tail) = self.pv.split_at_mut(ply + 1);
+        head[ply].clear();
+        head[ply].push(mv);
+        head[ply].extend_from_slice(&tail[0]);
+    }
+}
+
+/// Moves the highest-scored remaining move to position `index` and returns it
+fn pick_move(moves: &mut [Move], scores: &mut [i32], index: usize) -> Move {
+    let mut best = index;
+    for j in index + 1..moves.len() {
+        if scores[j] > scores[best] {
+            best = j;
+        }
+    }
+    moves.swap(index, best);
+    scores.swap(index, best);
+    moves[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skill_reports_only_the_requested_lines() {
+        let board = Board::new();
+        let mut tt = TranspositionTable::new(1);
+        let control = SearchControl::default();
+        let limits = SearchLimits { multipv: 1, skill: Skill::from_level(5, 1), ..Default::default() };
+        let mut reported = Vec::new();
+        let result = search(&board, &limits, &mut tt, EndgameTables::default(), None, &control, &mut |info| reported.push(info.multipv));
+        assert!(result.lines.len() >= 4, "il livello limitato sceglie tra più linee");
+        assert!(!reported.is_empty());
+        assert!(reported.iter().all(|&multipv| multipv == 1), "righe info: {:?}", reported);
+    }
+
+    #[test]
+    fn search_limits_from_arguments() {
+        let args = |text: &str| text.split_whitespace().map(String::from).collect::<Vec<_>>();
+        let input = args("a.pgn --depth 7 b.txt");
+        let (limits, rest) = parse_search_limits(&input, 500).unwrap();
+        assert_eq!((limits.depth, limits.movetime), (Some(7), None));
+        assert_eq!(rest, ["a.pgn", "b.txt"]);
+
+        let input = args("a.pgn");
+        let (limits, _) = parse_search_limits(&input, 500).unwrap();
+        assert_eq!((limits.depth, limits.movetime), (None, Some(500)));
+        let input = args("a.pgn --movetime 250");
+        assert_eq!(parse_search_limits(&input, 500).unwrap().0.movetime, Some(250));
+
+        assert!(parse_search_limits(&args("a.pgn --depth"), 500).is_err(), "valore mancante");
+        assert!(parse_search_limits(&args("--movetime abc"), 500).is_err(), "valore non numerico");
+    }
+}
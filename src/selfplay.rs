@@ -0,0 +1,185 @@
+//! In-process engine-vs-engine games, used for strength calibration and data generation
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::search::{self, EndgameTables, SearchControl, SearchLimits};
+use crate::tt::TranspositionTable;
+use crate::Board;
+
+/// Short, balanced opening lines (UCI moves) so that deterministic players produce different games
+pub const OPENINGS: &[&str] = &[
+    "e2e4 e7e5 g1f3 b8c6",
+    "e2e4 c7c5 g1f3 d7d6",
+    "e2e4 e7e6 d2d4 d7d5",
+    "e2e4 c7c6 d2d4 d7d5",
+    "d2d4 d7d5 c2c4 e7e6",
+    "d2d4 g8f6 c2c4 g7g6",
+    "d2d4 d7d5 c2c4 c7c6",
+    "c2c4 e7e5 b1c3 g8f6",
+    "g1f3 d7d5 g2g3 g8f6",
+    "e2e4 e7e5 f1c4 g8f6",
+    "e2e4 d7d5 e4d5 d8d5",
+    "d2d4 g8f6 c2c4 e7e6",
+    "e2e4 g7g6 d2d4 f8g7",
+    "d2d4 f7f5 g2g3 g8f6",
+    "e2e4 c7c5 b1c3 b8c6",
+    "c2c4 c7c5 g1f3 g8f6",
+];
+
+/// Games longer than this are adjudicated as draws
+pub const MAX_GAME_PLIES: usize = 400;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    /// Result as written in PGN files
+    pub fn pgn_text(self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+}
+
+/// Checks whether the game is over in `board`, returning the result if so
+pub fn game_result(board: &Board) -> Option<GameResult> {
+    if board.legal_moves().is_empty() {
+        return Some(if !board.in_check() {
+            GameResult::Draw
+        } else if board.white_to_move {
+            GameResult::BlackWins
+        } else {
+            GameResult::WhiteWins
+        });
+    }
+    if board.repetition_count() >= 2 || board.is_draw_by_rule() {
+        return Some(GameResult::Draw);
+    }
+    None
+}
+
+/// Plays one game from `start`, each side searching with its own limits and hash table
+pub fn play_game(start: &Board, white: &SearchLimits, black: &SearchLimits) -> GameResult {
+    let mut board = start.clone();
+    let mut tables = [TranspositionTable::new(2), TranspositionTable::new(2)];
+    let control = SearchControl::default();
+
+    for _ in 0..MAX_GAME_PLIES {
+        if let Some(result) = game_result(&board) {
+            return result;
+        }
+        let (limits, tt) = if board.white_to_move { (white, &mut tables[0]) } else { (black, &mut tables[1]) };
+        let outcome = search::search(&board, limits, tt, EndgameTables::default(), None, &control, &mut |_| {});
+        let Some(mv) = outcome.best_move else { break };
+        board.apply_move(mv);
+    }
+    game_result(&board).unwrap_or(GameResult::Draw)
+}
+
+/// Plays out an opening line from the standard position
+pub fn opening_board(line: &str) -> Board {
+    let mut board = Board::new();
+    for text in line.split_whitespace() {
+        if let Some(mv) = board.parse_uci_move(text) {
+            board.apply_move(mv);
+        }
+    }
+    board
+}
+
+/// Opening of a match round: round 0 plays `line`, round r adds the r-th legal move (cycling)
+fn round_opening(line: &str, round: usize) -> Board {
+    let mut board = opening_board(line);
+    let moves = board.legal_moves();
+    if round > 0 && !moves.is_empty() {
+        board.apply_move(moves[(round - 1) % moves.len()]);
+    }
+    board
+}
+
+/// Wins, draws and losses from the first player's point of view
+#[derive(Copy, Clone, Default, Debug)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Fraction of points scored, 0.0 to 1.0
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games().max(1) as f64
+    }
+
+    /// Elo difference implied by the score, clamped to avoid infinities on 0% / 100%
+    pub fn elo_difference(&self) -> f64 {
+        let score = self.score().clamp(0.01, 0.99);
+        -400.0 * (1.0 / score - 1.0).log10()
+    }
+
+    /// Standard error of `elo_difference`, from the spread of the game results
+    pub fn elo_error(&self) -> f64 {
+        let games = self.games().max(1) as f64;
+        let score = self.score();
+        let spread = self.wins as f64 * (1.0 - score).powi(2) + self.draws as f64 * (0.5 - score).powi(2) + self.losses as f64 * score.powi(2);
+        let score_error = (spread / games / games).sqrt();
+        let score = score.clamp(0.01, 0.99);
+        score_error * 400.0 / (std::f64::consts::LN_10 * score * (1.0 - score))
+    }
+
+    /// Both scores together
+    pub fn combined(&self, other: &MatchScore) -> MatchScore {
+        MatchScore { wins: self.wins + other.wins, draws: self.draws + other.draws, losses: self.losses + other.losses }
+    }
+}
+
+/// Plays every opening twice with colours swapped, `rounds` times, spreading games over `threads` threads
+/// `player_a` and `player_b` build the limits for a given game index (e.g. to vary skill seeds). After
+/// the first round each opening gets one more move, a different one every round, so that
+/// deterministic players play new games too
+pub fn play_match(
+    player_a: &(dyn Fn(usize) -> SearchLimits + Sync),
+    player_b: &(dyn Fn(usize) -> SearchLimits + Sync),
+    openings: &[&str],
+    rounds: usize,
+    threads: usize,
+) -> MatchScore {
+    let next_game = AtomicUsize::new(0);
+    let score = Mutex::new(MatchScore::default());
+    let game_count = openings.len() * 2 * rounds;
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| loop {
+                let game = next_game.fetch_add(1, Ordering::SeqCst);
+                if game >= game_count {
+                    break;
+                }
+                let start = round_opening(openings[game / 2 % openings.len()], game / 2 / openings.len());
+                let a_is_white = game.is_multiple_of(2);
+                let (a, b) = (player_a(game), player_b(game));
+                let result = if a_is_white { play_game(&start, &a, &b) } else { play_game(&start, &b, &a) };
+
+                let mut score = score.lock().unwrap();
+                match (result, a_is_white) {
+                    (GameResult::Draw, _) => score.draws += 1,
+                    (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => score.wins += 1,
+                    _ => score.losses += 1,
+                }
+            });
+        }
+    });
+    score.into_inner().unwrap()
+}
@@ -0,0 +1,331 @@
+//! Adjustable playing strength: skill levels 0-20 and UCI_LimitStrength / UCI_Elo
+
+use crate::rng::Rng;
+use crate::search::{SearchLimits, SearchResult};
+use crate::selfplay::{self, MatchScore, OPENINGS};
+
+/// Level 20 is full strength and is represented by "no skill limit"
+pub const MAX_SKILL_LEVEL: u32 = 20;
+
+/// Rough rating of a one-ply search with quiescence, which fixes the scale of the tables
+const ANCHOR_ELO: f64 = 1400.0;
+
+/// Elo of fixed-depth searches, anchored at depth 1 = ANCHOR_ELO and measured with `calibrate`
+#[rustfmt::skip]
+const DEPTH_ELO: [i32; 6] = [1400, 1732, 1980, 2220, 2437, 2624];
+
+/// Measured Elo of the skill levels that `calibrate` could tell apart, as (level, Elo). A level
+/// missing from the table plays like the one before it, whose Elo it shares
+#[rustfmt::skip]
+const SKILL_ELO: [(u32, i32); 17] = [
+    (0, 471), (1, 529), (2, 572), (3, 640), (4, 761), (5, 866), (6, 1066), (7, 1191), (9, 1351), (10, 1416),
+    (11, 1532), (12, 1621), (13, 1695), (15, 1969), (16, 2103), (17, 2179), (19, 2259),
+];
+
+/// Lowest and highest Elo accepted by UCI_Elo
+pub fn elo_range() -> (i32, i32) {
+    (SKILL_ELO[0].1, SKILL_ELO[SKILL_ELO.len() - 1].1)
+}
+
+/// Elo of a level: that of the last table entry at or below it
+fn level_elo(level: u32) -> (u32, i32) {
+    SKILL_ELO.iter().copied().take_while(|&(entry, _)| entry <= level).last().unwrap_or(SKILL_ELO[0])
+}
+
+/// A weakened player; `level` may be fractional when derived from an Elo
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Skill {
+    pub level: f64,
+    pub seed: u64,  // Randomness source; combined with the position so games are reproducible
+}
+
+impl Skill {
+    /// Skill for a level in 0..20; level 20 (full strength) returns None
+    pub fn from_level(level: u32, seed: u64) -> Option<Skill> {
+        (level < MAX_SKILL_LEVEL).then_some(Skill { level: level as f64, seed })
+    }
+
+    /// Skill whose calibrated strength matches `elo`. Between two table entries the strongest level of
+    /// the lower group is interpolated with the upper entry
+    pub fn from_elo(elo: i32, seed: u64) -> Skill {
+        let level = match SKILL_ELO.iter().position(|&(_, level_elo)| level_elo >= elo) {
+            None => (MAX_SKILL_LEVEL - 1) as f64,
+            Some(0) => 0.0,
+            Some(upper) => {
+                let ((_, low), (level, high)) = (SKILL_ELO[upper - 1], SKILL_ELO[upper]);
+                (level - 1) as f64 + (elo - low) as f64 / (high - low).max(1) as f64
+            },
+        };
+        Skill { level, seed }
+    }
+
+    /// Maximum search depth: weak levels only look one or two moves ahead
+    pub fn depth_cap(&self) -> u32 {
+        1 + (self.level / 4.0) as u32
+    }
+
+    /// Node budget per move, growing geometrically with the level
+    pub fn node_cap(&self) -> u64 {
+        (200.0 * 1.4f64.powf(self.level)) as u64
+    }
+
+    /// Chance per move of ignoring captures beyond the search horizon
+    pub fn horizon_error_probability(&self) -> f64 {
+        ((16.0 - self.level) / 64.0).max(0.0)
+    }
+
+    /// Applies the depth / node caps and asks for enough lines to choose from
+    pub fn restrict(&self, limits: &mut SearchLimits) {
+        limits.depth = Some(limits.depth.map_or(self.depth_cap(), |depth| depth.min(self.depth_cap())));
+        limits.nodes = Some(limits.nodes.map_or(self.node_cap(), |nodes| nodes.min(self.node_cap())));
+        limits.multipv = limits.multipv.max(4);
+    }
+
+    /// Picks a move among the near-best lines: the weaker the level, the more a random
+    /// bonus can outweigh the score difference (never beyond about a pawn)
+    pub fn pick_line(&self, result: &mut SearchResult, rng: &mut Rng) {
+        if result.lines.len() < 2 {
+            return;
+        }
+        let top = result.lines[0].score;
+        let delta = (top - result.lines[result.lines.len() - 1].score).min(100);
+        let weakness = (120.0 - 2.0 * self.level) as i32;
+
+        let mut chosen = 0;
+        let mut best_value = i32::MIN;
+        for (i, line) in result.lines.iter().enumerate() {
+            let push = (weakness * (top - line.score) + delta * rng.below(weakness as u64) as i32) / 128;
+            if line.score + push >= best_value {
+                best_value = line.score + push;
+                chosen = i;
+            }
+        }
+
+        let line = &result.lines[chosen];
+        result.best_move = line.pv.first().copied();
+        result.ponder_move = line.pv.get(1).copied();
+        result.score = line.score;
+        result.pv = line.pv.clone();
+    }
+}
+
+/// Outside this score range (and its mirror) a match says little about the Elo difference
+const MIN_CALIBRATION_SCORE: f64 = 0.15;
+/// Games per pairing of skill levels, unless `--games` says otherwise
+const CALIBRATION_GAMES: usize = 256;
+
+/// Limits for a fixed-depth anchor player
+fn depth_player(depth: u32) -> SearchLimits {
+    SearchLimits { depth: Some(depth), ..Default::default() }
+}
+
+/// Limits for a skill level, with a different seed every game
+fn skill_player(level: u32) -> impl Fn(usize) -> SearchLimits + Sync {
+    move |game: usize| SearchLimits {
+        skill: Some(Skill { level: level as f64, seed: game as u64 }),
+        ..Default::default()
+    }
+}
+
+/// The depth anchor (index into the ladder) a level is measured against: starting from `start`, one
+/// round of openings at a time, the first one it neither crushes nor is crushed by. None when even
+/// depth 1 is too strong
+fn pick_anchor(level: u32, start: usize, anchors: usize, threads: usize) -> Option<usize> {
+    let mut anchor = start;
+    let mut step = 0;
+    loop {
+        let score = selfplay::play_match(&skill_player(level), &|_| depth_player(anchor as u32 + 1), OPENINGS, 1, threads).score();
+        let next = if score < MIN_CALIBRATION_SCORE {
+            -1
+        } else if score > 1.0 - MIN_CALIBRATION_SCORE {
+            1
+        } else {
+            return Some(anchor);
+        };
+        // Between two neighbouring anchors either one will do
+        if next == -step || (next > 0 && anchor + 1 == anchors) {
+            return Some(anchor);
+        }
+        if next < 0 && anchor == 0 {
+            return None;
+        }
+        anchor = if next > 0 { anchor + 1 } else { anchor - 1 };
+        step = next;
+    }
+}
+
+fn print_match(name: &str, opponent: &str, score: &MatchScore) {
+    println!(
+        "{:>10} vs {:<10} +{} ={} -{}  {:.1}%  {:+.0} ± {:.0} Elo",
+        name,
+        opponent,
+        score.wins,
+        score.draws,
+        score.losses,
+        score.score() * 100.0,
+        score.elo_difference(),
+        1.96 * score.elo_error()
+    );
+}
+
+/// A measured Elo with its standard error split by the match it comes from, so that the error of
+/// the difference between two ratings leaves out the matches they share
+#[derive(Clone, Debug)]
+struct Rating {
+    elo: f64,
+    errors: Vec<f64>,  // Indexed by match number
+}
+
+impl Rating {
+    fn anchor() -> Rating {
+        Rating { elo: ANCHOR_ELO, errors: Vec::new() }
+    }
+
+    /// The rating of the player that scored `score` against `opponent` in match number `number`
+    fn after_match(opponent: &Rating, score: &MatchScore, number: usize) -> Rating {
+        let mut errors = opponent.errors.clone();
+        errors.resize(number + 1, 0.0);
+        errors[number] = score.elo_error();
+        Rating { elo: opponent.elo + score.elo_difference(), errors }
+    }
+
+    /// Standard error relative to the depth-1 anchor
+    fn error(&self) -> f64 {
+        self.errors.iter().map(|error| error * error).sum::<f64>().sqrt()
+    }
+
+    /// Standard error of `self.elo - other.elo`
+    fn difference_error(&self, other: &Rating) -> f64 {
+        let term = |errors: &[f64], number: usize| errors.get(number).copied().unwrap_or(0.0);
+        (0..self.errors.len().max(other.errors.len()))
+            .map(|number| (term(&self.errors, number) - term(&other.errors, number)).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+/// `calibrate [--games N]`: measures the Elo of each skill level against fixed-depth versions of the
+/// engine and prints the tables to paste into this file. A level whose lead over the last level kept
+/// in the table is within the 95% interval of the difference is merged with it
+pub fn run_calibration(args: &[String], threads: usize) -> Result<(), String> {
+    let games = match args {
+        [] => CALIBRATION_GAMES,
+        [flag, value] if flag == "--games" => value.parse::<usize>().map_err(|_| format!("Valore non valido per {}", flag))?,
+        _ => return Err("Uso: gk_chess_engine calibrate [--games N]".to_string()),
+    };
+    let rounds = games.div_ceil(OPENINGS.len() * 2).max(1);
+    let mut matches = 0;
+
+    // Depth ladder: each depth plays the next one
+    let mut depths = vec![Rating::anchor()];
+    for depth in 1..DEPTH_ELO.len() as u32 {
+        let score = selfplay::play_match(&|_| depth_player(depth + 1), &|_| depth_player(depth), OPENINGS, rounds, threads);
+        print_match(&format!("depth {}", depth + 1), &format!("depth {}", depth), &score);
+        depths.push(Rating::after_match(&depths[depth as usize - 1], &score, matches));
+        matches += 1;
+    }
+
+    // Every level plays a depth anchor, the one of the level above unless `pick_anchor` moves it; the
+    // strongest level starts one ply below its depth cap, as its node cap stops it earlier. Levels
+    // that even depth 1 crushes play the level above instead
+    let top_cap = Skill { level: (MAX_SKILL_LEVEL - 1) as f64, seed: 0 }.depth_cap() as usize;
+    let mut anchor = Some(top_cap.clamp(2, DEPTH_ELO.len()) - 2);
+    let mut levels: Vec<Rating> = Vec::new();
+    for level in (0..MAX_SKILL_LEVEL).rev() {
+        anchor = anchor.and_then(|start| pick_anchor(level, start, depths.len(), threads));
+        let rating = match anchor {
+            Some(anchor) => {
+                let score = selfplay::play_match(&skill_player(level), &|_| depth_player(anchor as u32 + 1), OPENINGS, rounds, threads);
+                print_match(&format!("level {}", level), &format!("depth {}", anchor + 1), &score);
+                Rating::after_match(&depths[anchor], &score, matches)
+            },
+            None => {
+                let above = levels.last().ok_or(format!("Il livello {} perde contro tutte le profondità", level))?;
+                let score = selfplay::play_match(&skill_player(level), &skill_player(level + 1), OPENINGS, rounds, threads);
+                print_match(&format!("level {}", level), &format!("level {}", level + 1), &score);
+                Rating::after_match(above, &score, matches)
+            },
+        };
+        matches += 1;
+        levels.push(rating);
+    }
+    levels.reverse();
+
+    println!("Livello      Elo  (95%)");
+    for (level, rating) in levels.iter().enumerate() {
+        println!("{:>7} {:>8.0}  ± {:.0}", level, rating.elo, 1.96 * rating.error());
+    }
+    let mut kept = vec![0];
+    for level in 1..levels.len() {
+        let group = kept[kept.len() - 1];
+        let gap = levels[level].elo - levels[group].elo;
+        let error = levels[level].difference_error(&levels[group]);
+        if gap > 1.96 * error {
+            kept.push(level);
+        } else {
+            println!("Livello {} unito al livello {} ({:+.0} ± {:.0})", level, group, gap, 1.96 * error);
+        }
+    }
+
+    let depth_table: Vec<String> = depths.iter().map(|rating| format!("{:.0}", rating.elo)).collect();
+    let skill_table: Vec<String> = kept.iter().map(|&level| format!("({}, {:.0})", level, levels[level].elo)).collect();
+    println!("const DEPTH_ELO: [i32; {}] = [{}];", depths.len(), depth_table.join(", "));
+    println!("const SKILL_ELO: [(u32, i32); {}] = [{}];", kept.len(), skill_table.join(", "));
+    Ok(())
+}
+
+/// Prints the calibrated Elo table
+pub fn print_elo_table() {
+    println!("Profondità fissa:");
+    for (depth, elo) in DEPTH_ELO.iter().enumerate() {
+        println!("  profondità {:>2}: {:>5} Elo", depth + 1, elo);
+    }
+    println!("Livelli di abilità:");
+    for level in 0..MAX_SKILL_LEVEL {
+        let (group, elo) = level_elo(level);
+        let skill = Skill { level: level as f64, seed: 0 };
+        println!(
+            "  livello {:>2}: {:>5} Elo{}  (profondità max {}, nodi max {}, errori d'orizzonte {:.0}%)",
+            level,
+            elo,
+            if group == level { String::new() } else { format!(" (come il livello {})", group) },
+            skill.depth_cap(),
+            skill.node_cap(),
+            skill.horizon_error_probability() * 100.0
+        );
+    }
+    println!("  livello 20: piena forza");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_table_entry_is_reachable() {
+        assert_eq!(SKILL_ELO[0].0, 0);
+        assert!(SKILL_ELO.windows(2).all(|pair| pair[0].0 < pair[1].0 && pair[0].1 < pair[1].1), "{:?}", SKILL_ELO);
+        assert!(SKILL_ELO.iter().all(|&(level, _)| level < MAX_SKILL_LEVEL));
+        for &(level, elo) in &SKILL_ELO {
+            assert_eq!(Skill::from_elo(elo, 0).level, level as f64);
+            assert_eq!(level_elo(level), (level, elo));
+        }
+        let (low, high) = elo_range();
+        assert_eq!(Skill::from_elo(low - 100, 0).level, 0.0);
+        assert_eq!(Skill::from_elo(high + 100, 0).level, (MAX_SKILL_LEVEL - 1) as f64);
+    }
+
+    #[test]
+    fn merged_levels_share_an_elo() {
+        for level in 0..MAX_SKILL_LEVEL {
+            let (group, elo) = level_elo(level);
+            assert!(group <= level);
+            // The group's strongest level stands for its Elo; the next entry is a level further
+            let next = SKILL_ELO.iter().find(|&&(entry, _)| entry > group).map_or(MAX_SKILL_LEVEL, |&(entry, _)| entry);
+            assert!(level < next);
+            if next < MAX_SKILL_LEVEL {
+                assert_eq!(Skill::from_elo(elo + 1, 0).level.floor(), (next - 1) as f64);
+            }
+        }
+    }
+}
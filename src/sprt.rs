@@ -0,0 +1,194 @@
+//! Sequential probability ratio test on game pairs (pentanomial model), with Elo and LOS estimates
+
+/// Hypotheses and error rates of a test: H0 "the gain is elo0", H1 "the gain is elo1" (logistic Elo)
+#[derive(Copy, Clone, Debug)]
+pub struct SprtConfig {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,  // Chance of accepting H1 when H0 is true
+    pub beta: f64,   // Chance of accepting H0 when H1 is true
+}
+
+/// Outcome of a finished test
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SprtVerdict {
+    AcceptH0,
+    AcceptH1,
+}
+
+/// Running test: how many game pairs (same opening, colours swapped) scored 0, 0.5, 1, 1.5 and 2
+/// points for the tested engine
+#[derive(Clone, Debug)]
+pub struct Sprt {
+    pub config: SprtConfig,
+    pub pairs: [u32; 5],
+}
+
+/// Expected score for an Elo difference
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Elo difference for an expected score
+fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score.clamp(1e-6, 1.0 - 1e-6) - 1.0).log10()
+}
+
+/// Error function (Abramowitz and Stegun 7.1.26, error below 1.5e-7)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let value = 1.0 - poly * (-x * x).exp();
+    if x < 0.0 { -value } else { value }
+}
+
+impl Sprt {
+    pub fn new(config: SprtConfig) -> Sprt {
+        Sprt { config, pairs: [0; 5] }
+    }
+
+    /// Records a pair of games; `half_points` is the tested engine's total in half points (0 to 4)
+    pub fn add_pair(&mut self, half_points: usize) {
+        self.pairs[half_points] += 1;
+    }
+
+    pub fn pair_count(&self) -> u32 {
+        self.pairs.iter().sum()
+    }
+
+    /// Mean score per game and variance of the pair scores (both on a 0..1 scale). The variance comes
+    /// from counts with half a pair added to every outcome, so that a few one-sided pairs (all won,
+    /// say) do not look like a certainty
+    fn mean_and_variance(&self) -> (f64, f64) {
+        const PRIOR: f64 = 0.5;
+        let moments = |counts: [f64; 5]| {
+            let total: f64 = counts.iter().sum();
+            let mean = counts.iter().enumerate().map(|(points, n)| n * points as f64 / 4.0).sum::<f64>() / total;
+            let variance = counts.iter().enumerate().map(|(points, n)| n * (points as f64 / 4.0 - mean).powi(2)).sum::<f64>() / total;
+            (mean, variance)
+        };
+        let (mean, _) = moments(self.pairs.map(|n| n as f64 + f64::EPSILON));
+        let (_, variance) = moments(self.pairs.map(|n| n as f64 + PRIOR));
+        (mean, variance)
+    }
+
+    /// Log-likelihood ratio of H1 against H0 (normal approximation of the pair scores)
+    pub fn llr(&self) -> f64 {
+        let (mean, variance) = self.mean_and_variance();
+        let (score0, score1) = (expected_score(self.config.elo0), expected_score(self.config.elo1));
+        self.pair_count() as f64 * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    /// LLR at which the test accepts H0 and H1
+    pub fn bounds(&self) -> (f64, f64) {
+        let SprtConfig { alpha, beta, .. } = self.config;
+        ((beta / (1.0 - alpha)).ln(), ((1.0 - beta) / alpha).ln())
+    }
+
+    /// The verdict once a bound is crossed
+    pub fn verdict(&self) -> Option<SprtVerdict> {
+        let llr = self.llr();
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(SprtVerdict::AcceptH1)
+        } else if llr <= lower {
+            Some(SprtVerdict::AcceptH0)
+        } else {
+            None
+        }
+    }
+
+    /// Elo estimate with its 95% confidence margin
+    pub fn elo(&self) -> (f64, f64) {
+        let (mean, variance) = self.mean_and_variance();
+        let deviation = (variance / self.pair_count().max(1) as f64).sqrt();
+        let elo = elo_from_score(mean);
+        let margin = (elo_from_score(mean + 1.96 * deviation) - elo_from_score(mean - 1.96 * deviation)) / 2.0;
+        (elo, margin)
+    }
+
+    /// Likelihood of superiority: probability that the tested engine is stronger
+    pub fn los(&self) -> f64 {
+        let (mean, variance) = self.mean_and_variance();
+        let deviation = (variance / self.pair_count().max(1) as f64).sqrt();
+        0.5 * (1.0 + erf((mean - 0.5) / deviation / std::f64::consts::SQRT_2))
+    }
+
+    /// One-line status: LLR with bounds, Elo, LOS and pentanomial counts
+    pub fn report(&self) -> String {
+        let (lower, upper) = self.bounds();
+        let (elo, margin) = self.elo();
+        let counts: Vec<String> = self.pairs.iter().map(u32::to_string).collect();
+        format!(
+            "SPRT [{:.1}, {:.1}]: LLR {:.2} ({:.2}, {:.2}), Elo {:+.1} ± {:.1}, LOS {:.1}%, coppie {} [{}]",
+            self.config.elo0,
+            self.config.elo1,
+            self.llr(),
+            lower,
+            upper,
+            elo,
+            margin,
+            self.los() * 100.0,
+            self.pair_count(),
+            counts.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: SprtConfig = SprtConfig { elo0: 0.0, elo1: 2.0, alpha: 0.05, beta: 0.05 };
+
+    fn sprt(pairs: [u32; 5]) -> Sprt {
+        Sprt { config: CONFIG, pairs }
+    }
+
+    fn assert_close(value: f64, expected: f64, tolerance: f64) {
+        assert!((value - expected).abs() <= tolerance, "{} invece di {}", value, expected);
+    }
+
+    #[test]
+    fn bounds_and_elo_conversions() {
+        let (lower, upper) = sprt([0; 5]).bounds();
+        assert_close(lower, -2.944, 1e-3);
+        assert_close(upper, 2.944, 1e-3);
+
+        assert_close(elo_from_score(0.6), 70.44, 1e-2);
+        assert_close(elo_from_score(0.75), 190.85, 1e-2);
+        for elo in [-300.0, -5.0, 0.0, 2.0, 120.0] {
+            assert_close(elo_from_score(expected_score(elo)), elo, 1e-9);
+        }
+        assert_close(erf(0.5), 0.520_499_88, 2e-7);
+    }
+
+    /// Reference values computed separately (Python) from the pentanomial normal approximation:
+    /// mean score 0.503929, pair variance 0.0281442 with half a pair added to every outcome
+    #[test]
+    fn pentanomial_sample() {
+        let test = sprt([60, 1250, 4300, 1300, 90]);
+        assert_eq!(test.pair_count(), 7000);
+        assert_close(test.llr(), 1.7821, 1e-3);
+        let (elo, margin) = test.elo();
+        assert_close(elo, 2.730, 1e-2);
+        assert_close(margin, 2.731, 1e-2);
+        assert_close(test.los(), 0.97496, 1e-4);
+        assert_eq!(test.verdict(), None);
+    }
+
+    #[test]
+    fn verdicts() {
+        // Mean score 0.5075: LLR 4.35 against elo0 = 0, elo1 = 2
+        let better = sprt([55, 1210, 4300, 1340, 95]);
+        assert_close(better.llr(), 4.345, 1e-2);
+        assert_eq!(better.verdict(), Some(SprtVerdict::AcceptH1));
+        let worse = sprt([95, 1340, 4300, 1210, 55]);
+        assert_eq!(worse.verdict(), Some(SprtVerdict::AcceptH0));
+
+        // A perfectly even match: no Elo difference, LOS 50%
+        let even = sprt([100, 900, 2000, 900, 100]);
+        assert_close(even.elo().0, 0.0, 1e-9);
+        assert_close(even.los(), 0.5, 1e-9);
+    }
+}
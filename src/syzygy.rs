@@ -0,0 +1,98 @@
+//! Syzygy endgame tablebases (WDL and DTZ tables), read through the shakmaty-syzygy crate
+
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty_syzygy::{Tablebase, Wdl};
+
+use crate::movegen::Move;
+use crate::search::{MATE_BOUND, MAX_PLY};
+use crate::Board;
+
+/// Score of a tablebase win at the root; stays below mate scores so both can be told apart
+pub const TB_WIN_SCORE: i32 = MATE_BOUND - MAX_PLY as i32 - 1;
+
+/// Directories in SyzygyPath are separated like the PATH environment variable
+const PATH_SEPARATOR: char = if cfg!(windows) { ';' } else { ':' };
+
+/// Result of a root probe: the move that keeps the best outcome and its score
+#[derive(Copy, Clone, Debug)]
+pub struct RootProbe {
+    pub best_move: Move,
+    pub score: i32,
+}
+
+/// Loaded tables plus the probing settings of the search
+#[derive(Debug)]
+pub struct Tablebases {
+    tables: Tablebase<Chess>,
+    pub probe_depth: i32,  // Minimum remaining depth for probes inside the search
+    pub rule50: bool,      // Treat cursed wins and blessed losses as draws
+}
+
+impl Tablebases {
+    /// Adds every table found in the directories of `paths`; fails if none is found
+    pub fn open(paths: &str, probe_depth: i32, rule50: bool) -> Result<Tablebases, String> {
+        let mut tables = Tablebase::new();
+        let mut count = 0;
+        for directory in paths.split(PATH_SEPARATOR).map(str::trim).filter(|dir| !dir.is_empty()) {
+            count += tables
+                .add_directory(directory)
+                .map_err(|err| format!("Impossibile leggere le tablebase in {}: {}", directory, err))?;
+        }
+        if count == 0 {
+            return Err(format!("Nessuna tablebase Syzygy trovata in {}", paths));
+        }
+        Ok(Tablebases { tables, probe_depth, rule50 })
+    }
+
+    /// Largest number of pieces (kings included) covered by the tables
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    /// Positions that can be probed: few enough pieces and no castling rights
+    pub fn can_probe(&self, board: &Board) -> bool {
+        board.game_state.castling_rights() == 0 && board.piece_count() <= self.max_pieces()
+    }
+
+    /// Win/draw/loss from the side to move's point of view, valid right after a capture or
+    /// pawn move; returns a search score relative to `ply`
+    pub fn probe_wdl(&self, board: &Board, ply: usize) -> Option<i32> {
+        let position = to_position(board)?;
+        let wdl = self.tables.probe_wdl_after_zeroing(&position).ok()?;
+        Some(self.wdl_score(wdl, ply))
+    }
+
+    /// DTZ-optimal move at the root, so that wins are converted within the 50-move rule
+    pub fn probe_root(&self, board: &Board) -> Option<RootProbe> {
+        let position = to_position(board)?;
+        let (mv, dtz) = self.tables.best_move(&position).ok()??;
+        let best_move = board.parse_uci_move(&mv.to_uci(CastlingMode::Standard).to_string())?;
+        let wdl = self.tables.probe_wdl(&position).ok()?.after_zeroing();
+        // A win with a longer distance to zeroing scores a little less
+        let score = match self.wdl_score(wdl, 0) {
+            score if score > 0 => score - dtz.ignore_rounding().0.abs().min(MAX_PLY as i32),
+            score => score,
+        };
+        Some(RootProbe { best_move, score })
+    }
+
+    fn wdl_score(&self, wdl: Wdl, ply: usize) -> i32 {
+        match wdl {
+            Wdl::Win => TB_WIN_SCORE - ply as i32,
+            Wdl::Loss => -TB_WIN_SCORE + ply as i32,
+            Wdl::CursedWin if !self.rule50 => TB_WIN_SCORE - ply as i32,
+            Wdl::BlessedLoss if !self.rule50 => -TB_WIN_SCORE + ply as i32,
+            // Wins that the 50-move rule turns into draws are still worth a little more than a draw
+            Wdl::CursedWin => 1,
+            Wdl::BlessedLoss => -1,
+            Wdl::Draw => 0,
+        }
+    }
+}
+
+/// Converts our board to a shakmaty position through FEN
+fn to_position(board: &Board) -> Option<Chess> {
+    let fen = Fen::from_ascii(board.to_fen().as_bytes()).ok()?;
+    fen.into_position(CastlingMode::Standard).ok()
+}
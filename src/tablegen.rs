@@ -0,0 +1,405 @@
+//! Retrograde generation of distance-to-mate tables: `gk_chess_engine gentb`
+
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::dtm::{Dtm, DtmTables, Material, Table, DRAW, EXTENSION, ILLEGAL, MAX_PLIES};
+use crate::movegen::{Move, BISHOP_DIRECTIONS, KING_OFFSETS, KNIGHT_OFFSETS, ROOK_DIRECTIONS};
+use crate::{Board, Piece};
+
+/// Where `gentb` writes its tables unless told otherwise
+const DEFAULT_DIRECTORY: &str = "tablebases";
+
+/// Squares the piece on (row, col) may have come from with a non-capturing move
+fn origins(board: &Board, row: usize, col: usize) -> Vec<(usize, usize)> {
+    let piece = board.squares[row][col];
+    let empty = |r: i32, c: i32| (0..8).contains(&r) && (0..8).contains(&c) && board.squares[r as usize][c as usize].is_empty();
+    let (row, col) = (row as i32, col as i32);
+    let mut origins = Vec::new();
+    let mut steps = |offsets: &[(i32, i32)], sliding: bool| {
+        for &(dr, dc) in offsets {
+            let (mut r, mut c) = (row + dr, col + dc);
+            while empty(r, c) {
+                origins.push((r as usize, c as usize));
+                if !sliding {
+                    break;
+                }
+                r += dr;
+                c += dc;
+            }
+        }
+    };
+    match piece.type_index() {
+        0 => {
+            // Pawns came from one square behind, or two from their starting rank
+            let (back, start_row) = if piece.is_white() { (1, 6) } else { (-1, 1) };
+            if row + back != 0 && row + back != 7 && empty(row + back, col) {
+                origins.push(((row + back) as usize, col as usize));
+                if row + 2 * back == start_row && empty(row + 2 * back, col) {
+                    origins.push(((row + 2 * back) as usize, col as usize));
+                }
+            }
+        },
+        1 => steps(&KNIGHT_OFFSETS, false),
+        2 => steps(&BISHOP_DIRECTIONS, true),
+        3 => steps(&ROOK_DIRECTIONS, true),
+        4 => {
+            steps(&ROOK_DIRECTIONS, true);
+            steps(&BISHOP_DIRECTIONS, true);
+        },
+        5 => steps(&KING_OFFSETS, false),
+        _ => {},
+    }
+    origins
+}
+
+/// Positions from which the side that just moved reached `board` without a capture or promotion
+fn predecessors(board: &Board) -> Vec<Board> {
+    let mover = !board.white_to_move;
+    let mut boards = Vec::new();
+    for row in 0..8 {
+        for col in 0..8 {
+            let piece = board.squares[row][col];
+            if piece.is_empty() || !piece.is_color(mover) {
+                continue;
+            }
+            for (from_row, from_col) in origins(board, row, col) {
+                let mut previous = board.clone();
+                previous.squares[row][col] = Piece::Empty;
+                previous.squares[from_row][from_col] = piece;
+                previous.white_to_move = mover;
+                boards.push(previous);
+            }
+        }
+    }
+    boards
+}
+
+/// The outcome the side to move prefers: the quickest win, then a draw, then the slowest loss
+fn better(a: Dtm, b: Dtm) -> Dtm {
+    let rank = |dtm: Dtm| match dtm {
+        Dtm::Win(plies) => (2, -(plies as i64)),
+        Dtm::Draw => (1, 0),
+        Dtm::Loss(plies) => (0, plies as i64),
+    };
+    if rank(b) > rank(a) { b } else { a }
+}
+
+/// State of one table being generated; positions are numbered `side * size + index`
+struct Generator<'a> {
+    table: Table,
+    smaller: &'a DtmTables,             // Tables reached by captures and promotions
+    next: Vec<u64>,                     // Bitset of positions to examine at the next distance
+    scheduled: Vec<Vec<usize>>,         // Positions to examine at a given distance because of a capture
+}
+
+impl Generator<'_> {
+    fn size(&self) -> usize {
+        self.table.size()
+    }
+
+    fn value(&self, position: usize) -> u8 {
+        self.table.values[position / self.size()][position % self.size()]
+    }
+
+    fn set_value(&mut self, position: usize, dtm: Dtm) {
+        let size = self.size();
+        self.table.values[position / size][position % size] = dtm.to_byte();
+    }
+
+    fn position_board(&self, position: usize) -> Option<Board> {
+        let squares = self.table.squares(position % self.size());
+        self.table.board(&squares, position < self.size())
+    }
+
+    /// Position number of a board with the table's material
+    fn position_of(&self, board: &Board) -> Option<usize> {
+        let index = self.table.index(&self.table.board_squares(board, false)?)?;
+        Some(if board.white_to_move { index } else { self.size() + index })
+    }
+
+    /// Outcome of the position after `mv`; inside this table only distances below `level` count
+    fn child_outcome(&self, board: &Board, mv: Move, level: u32) -> Result<Option<Dtm>, String> {
+        let leaves_table = mv.promotion.is_some() || !board.squares[mv.to_row][mv.to_col].is_empty();
+        let mut child = board.clone();
+        child.apply_move(mv);
+        if leaves_table {
+            return self
+                .smaller
+                .probe(&child)
+                .map(Some)
+                .ok_or_else(|| format!("Manca la tablebase {}", Material::of(&child).normalized().name()));
+        }
+        let position = self.position_of(&child).ok_or("Indice di posizione non valido")?;
+        let decided = |dtm: &Dtm| match dtm {
+            Dtm::Win(plies) | Dtm::Loss(plies) => *plies < level,
+            Dtm::Draw => false,
+        };
+        let stored = Dtm::from_byte(self.value(position)).filter(decided);
+        // The table does not know that the opponent may capture en passant after a double push
+        Ok(match (stored, self.en_passant_outcome(&child)?) {
+            (Some(stored), Some(en_passant)) => Some(better(stored, en_passant)).filter(decided),
+            // A quicker win than any distance still undecided
+            (None, Some(Dtm::Win(plies))) if plies < level => Some(Dtm::Win(plies)),
+            (stored, _) => stored,
+        })
+    }
+
+    /// Best outcome of the en passant captures in `board` for the side to move, if there are any
+    fn en_passant_outcome(&self, board: &Board) -> Result<Option<Dtm>, String> {
+        let Some(target) = board.game_state.en_passant_target else { return Ok(None) };
+        let mut best = None;
+        for mv in board.legal_moves() {
+            if (mv.to_row, mv.to_col) != target || board.squares[mv.from_row][mv.from_col].type_index() != 0 {
+                continue;
+            }
+            let mut after = board.clone();
+            after.apply_move(mv);
+            let outcome = self
+                .smaller
+                .probe(&after)
+                .ok_or_else(|| format!("Manca la tablebase {}", Material::of(&after).normalized().name()))?
+                .parent();
+            best = Some(best.map_or(outcome, |best| better(best, outcome)));
+        }
+        Ok(best)
+    }
+
+    fn schedule(&mut self, level: u32, position: usize) -> Result<(), String> {
+        if level > MAX_PLIES {
+            return Err(format!("Matto più lungo di {} semimosse: non rappresentabile", MAX_PLIES));
+        }
+        if self.scheduled.len() <= level as usize {
+            self.scheduled.resize(level as usize + 1, Vec::new());
+        }
+        self.scheduled[level as usize].push(position);
+        Ok(())
+    }
+
+    /// Marks legal positions as undecided, finds the mates and schedules captures into smaller tables
+    fn initialize(&mut self) -> Result<Vec<usize>, String> {
+        let mut mates = Vec::new();
+        for position in 0..2 * self.size() {
+            let index = position % self.size();
+            let squares = self.table.squares(index);
+            if self.table.index(&squares) != Some(index) {
+                continue;
+            }
+            let Some(board) = self.position_board(position) else { continue };
+            if board.king_attacked(!board.white_to_move) {
+                continue;
+            }
+            let moves = board.legal_moves();
+            if moves.is_empty() {
+                let dtm = if board.in_check() { Dtm::Loss(0) } else { Dtm::Draw };
+                self.set_value(position, dtm);
+                if dtm != Dtm::Draw {
+                    mates.push(position);
+                }
+                continue;
+            }
+            self.set_value(position, Dtm::Draw);
+            for mv in moves {
+                let outcome = if mv.promotion.is_some() || !board.squares[mv.to_row][mv.to_col].is_empty() {
+                    self.child_outcome(&board, mv, 0)?
+                } else if board.squares[mv.from_row][mv.from_col].type_index() == 0 && mv.from_row.abs_diff(mv.to_row) == 2 {
+                    // En passant replies lead into smaller tables too
+                    let mut child = board.clone();
+                    child.apply_move(mv);
+                    self.en_passant_outcome(&child)?
+                } else {
+                    None
+                };
+                if let Some(Dtm::Win(plies) | Dtm::Loss(plies)) = outcome {
+                    self.schedule(plies + 1, position)?;
+                }
+            }
+        }
+        Ok(mates)
+    }
+
+    /// Decides a position at distance `level` if one of its moves allows it
+    fn examine(&self, position: usize, level: u32) -> Result<Option<Dtm>, String> {
+        let Some(board) = self.position_board(position) else { return Ok(None) };
+        let moves = board.legal_moves();
+        if moves.is_empty() {
+            return Ok(None);
+        }
+        let mut all_lose = true;  // Every move leads to a win for the opponent
+        let mut longest = 0;
+        for mv in moves {
+            match self.child_outcome(&board, mv, level)? {
+                Some(Dtm::Loss(plies)) if plies + 1 == level => return Ok(Some(Dtm::Win(level))),
+                Some(Dtm::Win(plies)) => longest = longest.max(plies + 1),
+                _ => all_lose = false,
+            }
+        }
+        Ok((all_lose && longest == level).then_some(Dtm::Loss(level)))
+    }
+
+    /// Queues the undecided positions that can reach `position` with a quiet move
+    fn queue_predecessors(&mut self, position: usize) {
+        let Some(board) = self.position_board(position) else { return };
+        for previous in predecessors(&board) {
+            if let Some(previous) = self.position_of(&previous).filter(|&p| self.value(p) == DRAW) {
+                self.next[previous / 64] |= 1 << (previous % 64);
+            }
+        }
+    }
+
+    /// Retrograde analysis: positions are decided in order of distance to mate, starting from
+    /// the mates and walking backwards through un-moves
+    fn run(&mut self) -> Result<(), String> {
+        let mut decided = self.initialize()?;
+        let mut level = 0;
+        while !decided.is_empty() || self.next.iter().any(|&bits| bits != 0) || self.scheduled.len() > level as usize + 1 {
+            for &position in &decided {
+                self.queue_predecessors(position);
+            }
+            level += 1;
+            if level > MAX_PLIES && self.next.iter().any(|&bits| bits != 0) {
+                return Err(format!("Matto più lungo di {} semimosse: non rappresentabile", MAX_PLIES));
+            }
+
+            let mut candidates: Vec<usize> = self.scheduled.get_mut(level as usize).map(std::mem::take).unwrap_or_default();
+            for (word, bits) in self.next.iter_mut().enumerate() {
+                while *bits != 0 {
+                    candidates.push(word * 64 + bits.trailing_zeros() as usize);
+                    *bits &= *bits - 1;
+                }
+            }
+            candidates.sort_unstable();
+            candidates.dedup();
+
+            let mut results = Vec::new();
+            for position in candidates {
+                if self.value(position) == DRAW {
+                    if let Some(dtm) = self.examine(position, level)? {
+                        results.push((position, dtm));
+                    }
+                }
+            }
+            // Only set now, so that nothing decided at this distance influences its own level
+            decided = results.iter().map(|&(position, _)| position).collect();
+            for (position, dtm) in results {
+                self.set_value(position, dtm);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Generates the table of `material`; every smaller table it leads to must be in `smaller`
+pub fn generate(material: &Material, smaller: &DtmTables) -> Result<Table, String> {
+    let table = Table::new(material.clone());
+    let positions = 2 * table.size();
+    let mut generator = Generator { table, smaller, next: vec![0; positions.div_ceil(64)], scheduled: Vec::new() };
+    generator.run()?;
+    Ok(generator.table)
+}
+
+/// Counts of decided positions, for the report printed by `gentb`
+fn summary(table: &Table) -> String {
+    let (mut wins, mut losses, mut draws, mut longest) = (0, 0, 0, 0);
+    for &value in table.values.iter().flatten().filter(|&&value| value != ILLEGAL) {
+        match Dtm::from_byte(value) {
+            Some(Dtm::Win(plies)) => {
+                wins += 1;
+                longest = longest.max(plies);
+            },
+            Some(Dtm::Loss(_)) => losses += 1,
+            _ => draws += 1,
+        }
+    }
+    format!("{} vinte e {} perse dal lato al tratto, {} patte, matto più lungo in {} mosse", wins, losses, draws, longest.div_ceil(2))
+}
+
+/// Generates (or loads, if already on disk) the table of `material` and all smaller ones first
+fn build(material: &Material, directory: &Path, tables: &mut DtmTables) -> Result<(), String> {
+    if tables.get(material).is_some() {
+        return Ok(());
+    }
+    for child in material.children() {
+        build(&child, directory, tables)?;
+    }
+    let name = material.name();
+    let path = directory.join(format!("{}.{}", name, EXTENSION));
+    if path.exists() {
+        println!("{}: già presente in {}", name, path.display());
+        tables.insert(Table::load(&path)?);
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let table = generate(material, tables)?;
+    table.save(&path)?;
+    println!("{}: {} ({:.1} s) -> {}", name, summary(&table), started.elapsed().as_secs_f64(), path.display());
+    tables.insert(table);
+    Ok(())
+}
+
+/// `gentb <material> [directory]`, e.g. `gentb KRPvKR tablebases`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let Some(name) = args.first() else {
+        return Err("Uso: gk_chess_engine gentb <materiale, es. KQvKR> [cartella]".to_string());
+    };
+    let material = Material::parse(name)?.normalized();
+    let directory = Path::new(args.get(1).map_or(DEFAULT_DIRECTORY, String::as_str));
+    fs::create_dir_all(directory).map_err(|err| format!("Impossibile creare {}: {}", directory.display(), err))?;
+    build(&material, directory, &mut DtmTables::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generates `material` and every table it leads to, in memory
+    fn build_in_memory(material: &Material, tables: &mut DtmTables) {
+        if tables.get(material).is_some() {
+            return;
+        }
+        for child in material.children() {
+            build_in_memory(&child, tables);
+        }
+        tables.insert(generate(material, tables).unwrap());
+    }
+
+    /// Longest mate in the table, in moves of the winning side
+    fn longest_mate(tables: &DtmTables, name: &str) -> u32 {
+        let table = tables.get(&Material::parse(name).unwrap()).unwrap();
+        let plies = table.values.iter().flatten().filter_map(|&value| match Dtm::from_byte(value) {
+            Some(Dtm::Win(plies)) => Some(plies),
+            _ => None,
+        });
+        plies.max().unwrap_or(0).div_ceil(2)
+    }
+
+    /// Generates each table and checks its longest mate
+    fn check_longest_mates(expected: &[(&str, u32)]) {
+        let mut tables = DtmTables::default();
+        for &(name, moves) in expected {
+            build_in_memory(&Material::parse(name).unwrap(), &mut tables);
+            assert_eq!(longest_mate(&tables, name), moves, "{}", name);
+        }
+    }
+
+    #[test]
+    fn longest_mates_match_the_known_values() {
+        check_longest_mates(&[("KQvK", 10), ("KRvK", 16)]);
+    }
+
+    #[test]
+    #[ignore = "KBNvK richiede alcuni minuti senza ottimizzazioni: cargo test --release -- --ignored"]
+    fn longest_bishop_and_knight_mate() {
+        check_longest_mates(&[("KBNvK", 33)]);
+    }
+
+    #[test]
+    fn better_prefers_quick_wins_and_slow_losses() {
+        assert_eq!(better(Dtm::Win(5), Dtm::Win(3)), Dtm::Win(3));
+        assert_eq!(better(Dtm::Draw, Dtm::Win(41)), Dtm::Win(41));
+        assert_eq!(better(Dtm::Loss(2), Dtm::Draw), Dtm::Draw);
+        assert_eq!(better(Dtm::Loss(8), Dtm::Loss(2)), Dtm::Loss(8));
+    }
+}
@@ -0,0 +1,156 @@
+//! Take-backs in the GUI: moves are taken back until a human is to move and can be replayed until
+//! another move is played
+
+use crate::engine_player::{GameSetup, PlayerKind};
+use crate::movegen::Move;
+use crate::Board;
+
+#[derive(Default)]
+pub struct TakeBacks {
+    redo: Vec<Move>,  // Moves taken back, the last one is replayed first
+    hash: u64,        // Position the redo moves start from; any other position makes them stale
+}
+
+impl TakeBacks {
+    /// Forgets the moves taken back
+    pub fn clear(&mut self) {
+        self.redo.clear();
+    }
+
+    /// True when there are moves to replay in `board`: none once a new move was played
+    pub fn can_redo(&self, board: &Board) -> bool {
+        !self.redo.is_empty() && board.hash == self.hash
+    }
+
+    /// Takes moves back until a human is to move: against the engine both its reply and the
+    /// player's move. Does nothing if no human move is left to take back (the engine opened the game)
+    pub fn take_back(&mut self, board: &mut Board, setup: &GameSetup) -> bool {
+        if !self.can_redo(board) {
+            self.redo.clear();
+        }
+        let mut taken = Vec::new();
+        while let Some(mv) = board.unmake_move() {
+            taken.push(mv);
+            if setup.player(board.white_to_move) == PlayerKind::Human {
+                self.redo.extend(taken);
+                self.hash = board.hash;
+                return true;
+            }
+        }
+        for mv in taken.into_iter().rev() {
+            board.apply_move(mv);
+        }
+        false
+    }
+
+    /// Replays the moves taken back, up to the next human turn
+    pub fn redo(&mut self, board: &mut Board, setup: &GameSetup) -> bool {
+        if !self.can_redo(board) {
+            return false;
+        }
+        while let Some(mv) = self.redo.pop() {
+            board.apply_move(mv);
+            if setup.player(board.white_to_move) == PlayerKind::Human {
+                break;
+            }
+        }
+        self.hash = board.hash;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(white: PlayerKind, black: PlayerKind) -> GameSetup {
+        GameSetup { white, black, ..Default::default() }
+    }
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for text in moves {
+            let mv = board.parse_uci_move(text).unwrap();
+            board.apply_move(mv);
+        }
+    }
+
+    fn moves(board: &Board) -> Vec<String> {
+        board.history.iter().map(|undo| undo.mv.to_uci()).collect()
+    }
+
+    #[test]
+    fn against_the_engine_both_moves_go() {
+        let setup = setup(PlayerKind::Human, PlayerKind::Engine);
+        let mut board = Board::new();
+        play(&mut board, &["e2e4", "e7e5", "g1f3", "b8c6"]);
+        let mut take_backs = TakeBacks::default();
+        assert!(take_backs.take_back(&mut board, &setup));
+        assert_eq!(moves(&board), ["e2e4", "e7e5"]);
+        assert!(take_backs.take_back(&mut board, &setup));
+        assert!(board.history.is_empty());
+        assert!(!take_backs.take_back(&mut board, &setup), "niente da ritirare");
+
+        // Redo stops at each human turn
+        assert!(take_backs.redo(&mut board, &setup));
+        assert_eq!(moves(&board), ["e2e4", "e7e5"]);
+        assert!(take_backs.redo(&mut board, &setup));
+        assert_eq!(moves(&board), ["e2e4", "e7e5", "g1f3", "b8c6"]);
+        assert!(!take_backs.can_redo(&board));
+    }
+
+    #[test]
+    fn between_humans_one_move_goes() {
+        let setup = setup(PlayerKind::Human, PlayerKind::Human);
+        let mut board = Board::new();
+        play(&mut board, &["d2d4", "d7d5"]);
+        let mut take_backs = TakeBacks::default();
+        assert!(take_backs.take_back(&mut board, &setup));
+        assert_eq!(moves(&board), ["d2d4"]);
+        assert!(take_backs.redo(&mut board, &setup));
+        assert_eq!(moves(&board), ["d2d4", "d7d5"]);
+    }
+
+    #[test]
+    fn the_engine_opening_move_stays() {
+        let setup = setup(PlayerKind::Engine, PlayerKind::Human);
+        let mut board = Board::new();
+        play(&mut board, &["e2e4"]);
+        let mut take_backs = TakeBacks::default();
+        assert!(!take_backs.take_back(&mut board, &setup));
+        assert_eq!(moves(&board), ["e2e4"]);
+        assert!(!take_backs.can_redo(&board));
+
+        // Later the engine's reply and the human's move go, and the engine's first move stays
+        play(&mut board, &["c7c5", "g1f3"]);
+        assert!(take_backs.take_back(&mut board, &setup));
+        assert_eq!(moves(&board), ["e2e4"]);
+        assert!(take_backs.redo(&mut board, &setup));
+        assert_eq!(moves(&board), ["e2e4", "c7c5", "g1f3"]);
+    }
+
+    #[test]
+    fn a_new_move_drops_the_redo_moves() {
+        let setup = setup(PlayerKind::Human, PlayerKind::Engine);
+        let mut board = Board::new();
+        play(&mut board, &["e2e4", "e7e5"]);
+        let mut take_backs = TakeBacks::default();
+        take_backs.take_back(&mut board, &setup);
+        assert!(take_backs.can_redo(&board));
+
+        play(&mut board, &["d2d4"]);
+        assert!(!take_backs.can_redo(&board));
+        assert!(!take_backs.redo(&mut board, &setup));
+        assert_eq!(moves(&board), ["d2d4"]);
+
+        // Taking the new moves back does not bring the old ones back
+        play(&mut board, &["d7d5"]);
+        take_backs.take_back(&mut board, &setup);
+        assert!(take_backs.redo(&mut board, &setup));
+        assert_eq!(moves(&board), ["d2d4", "d7d5"]);
+        assert!(!take_backs.can_redo(&board));
+
+        take_backs.take_back(&mut board, &setup);
+        take_backs.clear();
+        assert!(!take_backs.can_redo(&board));
+    }
+}
@@ -0,0 +1,657 @@
+//! Engine-vs-engine tournaments between UCI programs run as child processes: `gk_chess_engine tournament`
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::engine_player::format_score_pawns;
+use crate::pgn::{parse_pgn, pgn_date, write_game, PgnMove};
+use crate::selfplay::{opening_board, GameResult, MatchScore, MAX_GAME_PLIES, OPENINGS};
+use crate::sprt::{Sprt, SprtConfig, SprtVerdict};
+use crate::dtm::{Dtm, DtmTables};
+use crate::endgame;
+use crate::syzygy::Tablebases;
+use crate::uci_client::{EngineConfig, UciClient};
+use crate::Board;
+
+/// Late answers within this margin do not lose on time (process and pipe latency)
+const TIME_MARGIN: Duration = Duration::from_millis(100);
+/// Extra wait before an engine that does not answer at all is given up on
+const HANG_TIMEOUT: Duration = Duration::from_secs(5);
+/// Rounds (game pairs) of an SPRT run unless limited with `--rounds`
+const SPRT_MAX_ROUNDS: usize = 50_000;
+
+/// `[moves/]seconds[+increment]`, e.g. "40/60+0.5" or "10+0.1"
+#[derive(Copy, Clone, Debug)]
+struct TimeControl {
+    moves: Option<u32>,  // Moves per period; None = whole game
+    base: Duration,
+    increment: Duration,
+}
+
+impl TimeControl {
+    fn parse(text: &str) -> Option<TimeControl> {
+        let (moves, rest) = match text.split_once('/') {
+            Some((moves, rest)) => (Some(moves.parse().ok().filter(|&moves| moves > 0)?), rest),
+            None => (None, text),
+        };
+        let (base, increment) = rest.split_once('+').unwrap_or((rest, "0"));
+        Some(TimeControl {
+            moves,
+            base: Duration::try_from_secs_f64(base.parse().ok()?).ok()?,
+            increment: Duration::try_from_secs_f64(increment.parse().ok()?).ok()?,
+        })
+    }
+}
+
+/// How long each move may take
+#[derive(Copy, Clone, Debug)]
+enum MoveLimit {
+    Clock(TimeControl),
+    MoveTime(Duration),
+}
+
+/// Conditions under which a game is decided without being played out
+#[derive(Default)]
+struct Adjudication {
+    resign: Option<(i32, usize)>,       // A side at or below -score for this many of its moves, its opponent at or above +score for as many, loses
+    draw: Option<(u32, i32, usize)>,    // From move number, |score| at most this for this many consecutive plies
+    tablebases: Option<Tablebases>,     // Positions in the tables are decided by them
+    dtm: Option<DtmTables>,             // The same with the generated DTM tables
+    kpk: bool,                          // The same with the built-in king and pawn against king bitbase
+}
+
+/// Settings of a `tournament` run
+struct TournamentOptions {
+    engines: Vec<EngineConfig>,
+    gauntlet: bool,        // First engine against each of the others, instead of everyone against everyone
+    rounds: usize,         // Each round plays one opening twice per pairing, colours swapped
+    openings: Vec<Board>,
+    limit: MoveLimit,
+    adjudication: Adjudication,
+    concurrency: usize,
+    pgn: Option<String>,
+    event: String,
+    sprt: Option<SprtConfig>,  // Test the first engine against the second, stopping at a verdict
+}
+
+/// One game of the schedule
+#[derive(Copy, Clone, Debug)]
+struct Pairing {
+    round: usize,
+    white: usize,
+    black: usize,
+    opening: usize,
+}
+
+/// How a game ended: result, PGN Termination tag and a readable reason
+struct GameEnd {
+    result: GameResult,
+    termination: &'static str,
+    reason: String,
+}
+
+impl GameEnd {
+    fn new(result: GameResult, termination: &'static str, reason: impl Into<String>) -> GameEnd {
+        GameEnd { result, termination, reason: reason.into() }
+    }
+
+    /// The side `white` (true) or Black loses
+    fn loss(white: bool, termination: &'static str, reason: impl Into<String>) -> GameEnd {
+        GameEnd::new(if white { GameResult::BlackWins } else { GameResult::WhiteWins }, termination, reason)
+    }
+}
+
+/// Ends the game if the rules (or the tablebases) decide it
+fn rules_end(board: &Board, adjudication: &Adjudication) -> Option<GameEnd> {
+    let mover = if board.white_to_move { "Bianco" } else { "Nero" };
+    if board.legal_moves().is_empty() {
+        return Some(if board.in_check() {
+            GameEnd::loss(board.white_to_move, "normal", format!("{} subisce scacco matto", mover))
+        } else {
+            GameEnd::new(GameResult::Draw, "normal", "Stallo")
+        });
+    }
+    if board.repetition_count() >= 2 {
+        return Some(GameEnd::new(GameResult::Draw, "normal", "Triplice ripetizione"));
+    }
+    if board.has_insufficient_material() {
+        return Some(GameEnd::new(GameResult::Draw, "normal", "Materiale insufficiente"));
+    }
+    if board.is_draw_by_rule() {
+        return Some(GameEnd::new(GameResult::Draw, "normal", "Regola delle 50 mosse"));
+    }
+    tablebase_end(board, adjudication)
+}
+
+/// Ends the game if a tablebase or the KPK bitbase knows the outcome
+fn tablebase_end(board: &Board, adjudication: &Adjudication) -> Option<GameEnd> {
+    // Outcome for the side to move: positive = win, 0 = draw, negative = loss
+    let syzygy = || adjudication.tablebases.as_ref().filter(|tables| tables.can_probe(board))?.probe_wdl(board, 0);
+    let dtm = || match adjudication.dtm.as_ref()?.probe(board)? {
+        Dtm::Draw => Some(0),
+        Dtm::Win(_) => Some(1),
+        Dtm::Loss(_) => Some(-1),
+    };
+    let kpk = || if adjudication.kpk { endgame::kpk_outcome(board) } else { None };
+    let (outcome, source) = syzygy().map(|outcome| (outcome, "le tablebase"))
+        .or_else(|| dtm().map(|outcome| (outcome, "le tablebase DTM")))
+        .or_else(|| kpk().map(|outcome| (outcome, "la bitbase KPK")))?;
+    Some(match outcome.signum() {
+        0 => GameEnd::new(GameResult::Draw, "adjudication", format!("Patta secondo {}", source)),
+        sign => GameEnd::loss(board.white_to_move == (sign < 0), "adjudication", format!("Vittoria secondo {}", source)),
+    })
+}
+
+/// Counters of the score-based adjudication
+#[derive(Default)]
+struct ScoreTracker {
+    losing_moves: [usize; 2],   // Consecutive moves of each side (White, Black) reporting a lost score
+    winning_moves: [usize; 2],  // Consecutive moves of each side reporting a won score
+    drawish_plies: usize,       // Consecutive plies with a score close to zero
+}
+
+impl ScoreTracker {
+    /// Records the score of the side that just searched `board`
+    fn update(&mut self, adjudication: &Adjudication, board: &Board, score: Option<i32>) -> Option<GameEnd> {
+        let side = usize::from(!board.white_to_move);
+        let Some(score) = score else {
+            *self = ScoreTracker::default();
+            return None;
+        };
+        if let Some((threshold, moves)) = adjudication.resign {
+            self.losing_moves[side] = if score <= -threshold { self.losing_moves[side] + 1 } else { 0 };
+            self.winning_moves[side] = if score >= threshold { self.winning_moves[side] + 1 } else { 0 };
+            // Both engines must agree, so that one engine's evaluation bug cannot end the game
+            for loser in [side, 1 - side] {
+                if self.losing_moves[loser] >= moves && self.winning_moves[1 - loser] >= moves {
+                    return Some(GameEnd::loss(loser == 0, "adjudication", "Abbandono per punteggio"));
+                }
+            }
+        }
+        if let Some((from_move, threshold, plies)) = adjudication.draw {
+            let drawish = board.fullmove_number >= from_move && score.abs() <= threshold;
+            self.drawish_plies = if drawish { self.drawish_plies + 1 } else { 0 };
+            if self.drawish_plies >= plies {
+                return Some(GameEnd::new(GameResult::Draw, "adjudication", "Patta per punteggio"));
+            }
+        }
+        None
+    }
+}
+
+/// Remaining time of both sides
+struct Clocks {
+    remaining: [Duration; 2],
+    moves_made: [u32; 2],
+}
+
+impl Clocks {
+    fn go_command(&self, limit: MoveLimit, side: usize) -> String {
+        match limit {
+            MoveLimit::MoveTime(time) => format!("go movetime {}", time.as_millis()),
+            MoveLimit::Clock(tc) => {
+                let mut command = format!(
+                    "go wtime {} btime {} winc {} binc {}",
+                    self.remaining[0].as_millis(),
+                    self.remaining[1].as_millis(),
+                    tc.increment.as_millis(),
+                    tc.increment.as_millis()
+                );
+                if let Some(moves) = tc.moves {
+                    command.push_str(&format!(" movestogo {}", moves - self.moves_made[side] % moves));
+                }
+                command
+            },
+        }
+    }
+
+    /// Time the side may use before the answer counts as late
+    fn allowance(&self, limit: MoveLimit, side: usize) -> Duration {
+        match limit {
+            MoveLimit::MoveTime(time) => time + TIME_MARGIN,
+            MoveLimit::Clock(_) => self.remaining[side] + TIME_MARGIN,
+        }
+    }
+
+    /// Charges a move to the side's clock; false if its time ran out
+    fn charge(&mut self, limit: MoveLimit, side: usize, elapsed: Duration) -> bool {
+        if elapsed > self.allowance(limit, side) {
+            return false;
+        }
+        if let MoveLimit::Clock(tc) = limit {
+            self.remaining[side] = self.remaining[side].saturating_sub(elapsed) + tc.increment;
+            self.moves_made[side] += 1;
+            if tc.moves.is_some_and(|moves| self.moves_made[side].is_multiple_of(moves)) {
+                self.remaining[side] += tc.base;
+            }
+        }
+        true
+    }
+}
+
+/// Plays one game between two engine processes, returning how it ended and the moves with comments
+fn play_game(options: &TournamentOptions, pairing: Pairing) -> (GameEnd, Vec<PgnMove>, [String; 2]) {
+    let start = &options.openings[pairing.opening];
+    let mut names = [pairing.white, pairing.black].map(|index| options.engines[index].name.clone());
+    let mut engines = Vec::new();
+    for (side, index) in [pairing.white, pairing.black].into_iter().enumerate() {
+        match UciClient::start(&options.engines[index]).and_then(|mut client| client.new_game().map(|_| client)) {
+            Ok(client) => {
+                names[side] = client.name.clone();
+                engines.push(client);
+            },
+            Err(err) => return (GameEnd::loss(side == 0, "abandoned", err), Vec::new(), names),
+        }
+    }
+
+    let mut board = start.clone();
+    let mut moves = Vec::new();
+    let mut uci_moves = Vec::new();
+    let base = match options.limit {
+        MoveLimit::Clock(tc) => tc.base,
+        MoveLimit::MoveTime(_) => Duration::ZERO,
+    };
+    let mut clocks = Clocks { remaining: [base; 2], moves_made: [0; 2] };
+    let mut tracker = ScoreTracker::default();
+
+    let end = loop {
+        if let Some(end) = rules_end(&board, &options.adjudication) {
+            break end;
+        }
+        if moves.len() >= MAX_GAME_PLIES {
+            break GameEnd::new(GameResult::Draw, "adjudication", "Partita troppo lunga");
+        }
+        let side = usize::from(!board.white_to_move);
+        let position = format!("position fen {} moves {}", start.to_fen(), uci_moves.join(" "));
+        let timeout = clocks.allowance(options.limit, side) + HANG_TIMEOUT;
+        let answer = match engines[side].go(position.trim_end(), &clocks.go_command(options.limit, side), timeout) {
+            Ok(answer) => answer,
+            Err(err) => break GameEnd::loss(side == 0, "abandoned", err),
+        };
+        if !clocks.charge(options.limit, side, answer.elapsed) {
+            break GameEnd::loss(side == 0, "time forfeit", format!("{} perde per tempo", names[side]));
+        }
+        let Some(mv) = board.parse_uci_move(&answer.best_move) else {
+            break GameEnd::loss(side == 0, "rules infraction", format!("{} gioca la mossa illegale {}", names[side], answer.best_move));
+        };
+
+        let comment = match answer.score {
+            Some(score) => format!("{{{}/{} {:.2}s}}", format_score_pawns(score), answer.depth, answer.elapsed.as_secs_f64()),
+            None => format!("{{{:.2}s}}", answer.elapsed.as_secs_f64()),
+        };
+        let adjudicated = tracker.update(&options.adjudication, &board, answer.score);
+        moves.push(PgnMove { mv, annotation: comment });
+        uci_moves.push(answer.best_move);
+        board.apply_move(mv);
+        if let Some(end) = adjudicated {
+            break end;
+        }
+    };
+    (end, moves, names)
+}
+
+/// Every game of the tournament, pairs of games with swapped colours next to each other
+fn schedule(options: &TournamentOptions) -> Vec<Pairing> {
+    let count = options.engines.len();
+    let pairs: Vec<(usize, usize)> = if options.gauntlet {
+        (1..count).map(|other| (0, other)).collect()
+    } else {
+        (0..count).flat_map(|a| (a + 1..count).map(move |b| (a, b))).collect()
+    };
+    let mut games = Vec::new();
+    for round in 0..options.rounds {
+        for (index, &(a, b)) in pairs.iter().enumerate() {
+            let opening = (round * pairs.len() + index) % options.openings.len();
+            games.push(Pairing { round, white: a, black: b, opening });
+            games.push(Pairing { round, white: b, black: a, opening });
+        }
+    }
+    games
+}
+
+/// Scores of every engine against every other, from the row engine's point of view
+struct Crosstable {
+    names: Vec<String>,
+    scores: Vec<Vec<MatchScore>>,
+}
+
+impl Crosstable {
+    fn new(options: &TournamentOptions) -> Crosstable {
+        let count = options.engines.len();
+        let names = options.engines.iter().enumerate().map(|(index, engine)| {
+            if engine.name.is_empty() { format!("Motore {}", index + 1) } else { engine.name.clone() }
+        });
+        Crosstable { names: names.collect(), scores: vec![vec![MatchScore::default(); count]; count] }
+    }
+
+    /// Adds a finished game; `names` are those reported by the engines, for engines not named in the options
+    fn record(&mut self, options: &TournamentOptions, pairing: Pairing, result: GameResult, names: &[String; 2]) {
+        let (white, black) = (pairing.white, pairing.black);
+        for (engine, name) in [white, black].into_iter().zip(names) {
+            if options.engines[engine].name.is_empty() && !name.is_empty() {
+                self.names[engine] = name.clone();
+            }
+        }
+        match result {
+            GameResult::WhiteWins => {
+                self.scores[white][black].wins += 1;
+                self.scores[black][white].losses += 1;
+            },
+            GameResult::BlackWins => {
+                self.scores[white][black].losses += 1;
+                self.scores[black][white].wins += 1;
+            },
+            GameResult::Draw => {
+                self.scores[white][black].draws += 1;
+                self.scores[black][white].draws += 1;
+            },
+        }
+    }
+
+    fn total(&self, engine: usize) -> MatchScore {
+        self.scores[engine].iter().fold(MatchScore::default(), |total, score| total.combined(score))
+    }
+
+    /// Standings by points, with the score of each engine against each other
+    fn print(&self) {
+        let points = |score: &MatchScore| score.wins as f64 + 0.5 * score.draws as f64;
+        let mut order: Vec<usize> = (0..self.names.len()).collect();
+        order.sort_by(|&a, &b| points(&self.total(b)).total_cmp(&points(&self.total(a))));
+        let width = self.names.iter().map(String::len).max().unwrap_or(0).max(6);
+
+        print!("{:>3} {:<width$} {:>7} {:>7} {:>7} {:>11}", "N", "Motore", "Punti", "Partite", "Elo", "V/P/S", width = width);
+        for rank in 1..=order.len() {
+            print!(" {:>9}", rank);
+        }
+        println!();
+        for (rank, &engine) in order.iter().enumerate() {
+            let total = self.total(engine);
+            let record = format!("{}/{}/{}", total.wins, total.draws, total.losses);
+            print!("{:>3} {:<width$} {:>7.1} {:>7} {:>+7.0} {:>11}", rank + 1, self.names[engine], points(&total), total.games(), total.elo_difference(), record, width = width);
+            for &opponent in &order {
+                let score = &self.scores[engine][opponent];
+                let cell = if opponent == engine || score.games() == 0 { "-".to_string() } else { format!("{:.1}/{}", points(score), score.games()) };
+                print!(" {:>9}", cell);
+            }
+            println!();
+        }
+    }
+}
+
+/// SPRT fed with complete game pairs, whatever order the games finish in
+struct PairedTest {
+    test: Sprt,
+    first_games: HashMap<usize, usize>,  // Pair index -> half points of the first engine in the game already finished
+    verdict: Option<SprtVerdict>,
+}
+
+impl PairedTest {
+    /// Adds a finished game; true when the test has just reached its verdict
+    fn record(&mut self, game: usize, pairing: Pairing, result: GameResult) -> bool {
+        if self.verdict.is_some() {
+            return false;
+        }
+        let half_points = match (result, pairing.white == 0) {
+            (GameResult::Draw, _) => 1,
+            (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => 2,
+            _ => 0,
+        };
+        let Some(first) = self.first_games.remove(&(game / 2)) else {
+            self.first_games.insert(game / 2, half_points);
+            return false;
+        };
+        self.test.add_pair(first + half_points);
+        println!("{}", self.test.report());
+        self.verdict = self.test.verdict();
+        self.verdict.is_some()
+    }
+
+    fn print_verdict(&self) {
+        println!("{}", self.test.report());
+        println!("{}", match self.verdict {
+            Some(SprtVerdict::AcceptH1) => "SPRT concluso: H1 accettata, la modifica migliora il motore",
+            Some(SprtVerdict::AcceptH0) => "SPRT concluso: H0 accettata, la modifica non porta il guadagno cercato",
+            None => "SPRT non concluso: nessun limite raggiunto",
+        });
+    }
+}
+
+/// Plays the whole schedule on `concurrency` threads, printing each result and the final crosstable
+fn run_tournament(options: &TournamentOptions) -> Result<(), String> {
+    let games = schedule(options);
+    let mut pgn_file = match &options.pgn {
+        Some(path) => Some(OpenOptions::new().create(true).append(true).open(path).map_err(|err| format!("Impossibile aprire {}: {}", path, err))?),
+        None => None,
+    };
+    let crosstable = Mutex::new(Crosstable::new(options));
+    let output = Mutex::new((&mut pgn_file, 0));
+    let next_game = AtomicUsize::new(0);
+    let date = pgn_date();
+    let paired_test = options.sprt.map(|config| Mutex::new(PairedTest { test: Sprt::new(config), first_games: HashMap::new(), verdict: None }));
+
+    thread::scope(|scope| {
+        for _ in 0..options.concurrency.max(1) {
+            scope.spawn(|| loop {
+                let index = next_game.fetch_add(1, Ordering::SeqCst);
+                let Some(&pairing) = games.get(index) else { break };
+                let (end, moves, names) = play_game(options, pairing);
+                let result = end.result.pgn_text();
+
+                let mut crosstable = crosstable.lock().unwrap();
+                crosstable.record(options, pairing, end.result, &names);
+                if let Some(paired_test) = &paired_test {
+                    if paired_test.lock().unwrap().record(index, pairing, end.result) {
+                        // No new games; those being played still finish
+                        next_game.store(games.len(), Ordering::SeqCst);
+                    }
+                }
+                let mut output = output.lock().unwrap();
+                output.1 += 1;
+                println!("Partita {}/{}: {} - {} {} ({})", output.1, games.len(), names[0], names[1], result, end.reason);
+                if let Some(file) = output.0.as_mut() {
+                    let mut tags = vec![
+                        ("Event", options.event.clone()),
+                        ("Site", "?".to_string()),
+                        ("Date", date.clone()),
+                        ("Round", (pairing.round + 1).to_string()),
+                        ("White", names[0].clone()),
+                        ("Black", names[1].clone()),
+                        ("Result", result.to_string()),
+                        ("Termination", end.termination.to_string()),
+                    ];
+                    if let MoveLimit::Clock(tc) = options.limit {
+                        let seconds = |time: Duration| format!("{}", time.as_secs_f64());
+                        let period = format!("{}+{}", seconds(tc.base), seconds(tc.increment));
+                        tags.push(("TimeControl", tc.moves.map_or(period.clone(), |moves| format!("{}/{}", moves, period))));
+                    }
+                    let mut moves = moves;
+                    if let Some(last) = moves.last_mut() {
+                        last.annotation.push_str(&format!(" {{{}}}", end.reason));
+                    }
+                    let text = write_game(&tags, &options.openings[pairing.opening], &moves, result);
+                    if let Err(err) = file.write_all(text.as_bytes()) {
+                        eprintln!("Errore di scrittura del PGN: {}", err);
+                    }
+                }
+            });
+        }
+    });
+
+    println!();
+    crosstable.into_inner().unwrap().print();
+    if let Some(paired_test) = paired_test {
+        println!();
+        paired_test.into_inner().unwrap().print_verdict();
+    }
+    Ok(())
+}
+
+/// Starting positions from a PGN file (main lines, up to `plies` moves) or one FEN/EPD per line
+fn load_openings(path: &str, plies: usize) -> Result<Vec<Board>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("Impossibile leggere {}: {}", path, err))?;
+    let mut openings = Vec::new();
+    if path.to_lowercase().ends_with(".pgn") {
+        for game in parse_pgn(&text) {
+            let mut board = match game.tag("FEN") {
+                Some(fen) => Board::from_fen(fen)?,
+                None => Board::new(),
+            };
+            for san in game.moves.iter().take(plies) {
+                let mv = board.parse_san(san).ok_or_else(|| format!("Mossa non valida nelle aperture: {}", san))?;
+                board.apply_move(mv);
+            }
+            openings.push(board);
+        }
+    } else {
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            openings.push(Board::from_fen(line)?);
+        }
+    }
+    if openings.is_empty() {
+        return Err(format!("Nessuna apertura in {}", path));
+    }
+    Ok(openings)
+}
+
+/// `tournament --engine cmd=<path> [arg=..] [name=..] [option.X=..] --engine ... [options]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "Uso: gk_chess_engine tournament --engine cmd=<programma> [arg=<argomento>] [name=<nome>] [option.<Nome>=<valore>] \
+        --engine ... [--gauntlet] [--rounds N] [--openings file.pgn|file.epd] [--plies N] [--tc [mosse/]secondi[+incremento] | --movetime ms] \
+        [--resign cp mosse] [--draw mossa cp semimosse] [--syzygy percorso] [--dtm percorso] [--kpk] [--concurrency N] [--pgn uscita.pgn] [--event nome] \
+        [--sprt elo0 elo1 alfa beta]";
+    let mut options = TournamentOptions {
+        engines: Vec::new(),
+        gauntlet: false,
+        rounds: 0,
+        openings: Vec::new(),
+        limit: MoveLimit::Clock(TimeControl { moves: None, base: Duration::from_secs(10), increment: Duration::from_millis(100) }),
+        adjudication: Adjudication::default(),
+        concurrency: 1,
+        pgn: None,
+        event: "Torneo GK".to_string(),
+        sprt: None,
+    };
+    let mut openings_path = None;
+    let mut plies = usize::MAX;
+    let mut i = 0;
+    while i < args.len() {
+        // Values of an option run up to the next option
+        let values: Vec<String> = args[i + 1..].iter().take_while(|arg| !arg.starts_with("--")).cloned().collect();
+        let invalid = || format!("Valore non valido per {}", args[i]);
+        let number = |index: usize| values.get(index).and_then(|value| value.parse::<i64>().ok()).ok_or_else(invalid);
+        let real = |index: usize| values.get(index).and_then(|value| value.parse::<f64>().ok()).ok_or_else(invalid);
+        match args[i].as_str() {
+            "--engine" => options.engines.push(EngineConfig::parse(&values)?),
+            "--gauntlet" => options.gauntlet = true,
+            "--rounds" => options.rounds = number(0)? as usize,
+            "--openings" => openings_path = Some(values.first().ok_or_else(invalid)?.clone()),
+            "--plies" => plies = number(0)? as usize,
+            "--tc" => options.limit = MoveLimit::Clock(values.first().and_then(|tc| TimeControl::parse(tc)).ok_or_else(invalid)?),
+            "--movetime" => options.limit = MoveLimit::MoveTime(Duration::from_millis(number(0)? as u64)),
+            "--resign" => options.adjudication.resign = Some((number(0)? as i32, number(1)? as usize)),
+            "--draw" => options.adjudication.draw = Some((number(0)? as u32, number(1)? as i32, number(2)? as usize)),
+            "--syzygy" => options.adjudication.tablebases = Some(Tablebases::open(values.first().ok_or_else(invalid)?, 1, true)?),
+            "--dtm" => options.adjudication.dtm = Some(DtmTables::open(values.first().ok_or_else(invalid)?)?),
+            "--kpk" => options.adjudication.kpk = true,
+            "--concurrency" => options.concurrency = number(0)? as usize,
+            "--pgn" => options.pgn = Some(values.first().ok_or_else(invalid)?.clone()),
+            "--event" => options.event = values.join(" "),
+            "--sprt" => options.sprt = Some(SprtConfig { elo0: real(0)?, elo1: real(1)?, alpha: real(2)?, beta: real(3)? }),
+            other => return Err(format!("Opzione sconosciuta: {}\n{}", other, USAGE)),
+        }
+        i += 1 + values.len();
+    }
+    if options.engines.len() < 2 {
+        return Err(USAGE.to_string());
+    }
+    if let Some(config) = options.sprt {
+        if options.engines.len() != 2 {
+            return Err("L'SPRT confronta esattamente due motori: il primo è quello da provare".to_string());
+        }
+        let valid_rate = |rate: f64| rate > 0.0 && rate < 0.5;
+        if config.elo1 <= config.elo0 || !valid_rate(config.alpha) || !valid_rate(config.beta) {
+            return Err("SPRT: servono elo0 < elo1 e alfa, beta tra 0 e 0.5".to_string());
+        }
+    }
+    if options.rounds == 0 {
+        options.rounds = if options.sprt.is_some() { SPRT_MAX_ROUNDS } else { 1 };
+    }
+    options.openings = match openings_path {
+        Some(path) => load_openings(&path, plies)?,
+        None => OPENINGS.iter().map(|line| opening_board(line)).collect(),
+    };
+    run_tournament(&options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_controls() {
+        let tc = TimeControl::parse("40/60+0.5").unwrap();
+        assert_eq!((tc.moves, tc.base, tc.increment), (Some(40), Duration::from_secs(60), Duration::from_millis(500)));
+        let tc = TimeControl::parse("10").unwrap();
+        assert_eq!((tc.moves, tc.base, tc.increment), (None, Duration::from_secs(10), Duration::ZERO));
+        for invalid in ["0/60", "-1/60", "40/", "abc", "10+x"] {
+            assert!(TimeControl::parse(invalid).is_none(), "{}", invalid);
+        }
+    }
+
+    /// Scores reported alternately by White and Black, from the starting position
+    fn play_scores(adjudication: &Adjudication, scores: &[i32]) -> Option<GameEnd> {
+        let mut tracker = ScoreTracker::default();
+        let mut board = Board::new();
+        for &score in scores {
+            if let Some(end) = tracker.update(adjudication, &board, Some(score)) {
+                return Some(end);
+            }
+            let mv = board.legal_moves()[0];
+            board.apply_move(mv);
+        }
+        None
+    }
+
+    #[test]
+    fn resignation_needs_both_engines_to_agree() {
+        let adjudication = Adjudication { resign: Some((600, 3)), ..Default::default() };
+        // White sees itself losing for three moves and Black agrees
+        let end = play_scores(&adjudication, &[-700, 700, -650, 800, -900, 900]).unwrap();
+        assert_eq!(end.result, GameResult::BlackWins);
+        // Black thinks it is winning, but White does not agree
+        assert!(play_scores(&adjudication, &[20, 700, 30, 800, 10, 900, 0, 1000]).is_none());
+        // White is losing according to itself only
+        assert!(play_scores(&adjudication, &[-700, 0, -700, 0, -700, 0, -700]).is_none());
+        // A move out of the range starts the count again
+        assert!(play_scores(&adjudication, &[700, -700, 700, -100, 700, -700]).is_none());
+        assert_eq!(play_scores(&adjudication, &[700, -700, 700, -100, 700, -700, 700, -700, 700, -700]).unwrap().result, GameResult::WhiteWins);
+    }
+
+    #[test]
+    fn draw_adjudication_counts_plies() {
+        let adjudication = Adjudication { draw: Some((1, 10, 4)), ..Default::default() };
+        assert!(play_scores(&adjudication, &[5, -5, 0, 50, 5, -5, 0]).is_none());
+        assert_eq!(play_scores(&adjudication, &[5, -5, 0, 50, 5, -5, 0, 10]).unwrap().result, GameResult::Draw);
+        // Not before the given move number
+        let late = Adjudication { draw: Some((30, 10, 4)), ..Default::default() };
+        assert!(play_scores(&late, &[0; 8]).is_none());
+    }
+
+    #[test]
+    fn kpk_bitbase_decides_the_game() {
+        let adjudication = Adjudication { kpk: true, ..Default::default() };
+        let end = |fen: &str| rules_end(&Board::from_fen(fen).unwrap(), &adjudication).map(|end| end.result);
+        // Opposition: White to move draws, Black to move loses; the same with colours reversed
+        assert_eq!(end("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), Some(GameResult::Draw));
+        assert_eq!(end("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1"), Some(GameResult::WhiteWins));
+        assert_eq!(end("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1"), Some(GameResult::BlackWins));
+        // Other material is played out, and so is KPK without the option
+        assert_eq!(end("8/4k3/8/4K3/4P3/8/8/7R w - - 0 1"), None);
+        assert_eq!(rules_end(&Board::from_fen("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1").unwrap(), &Adjudication::default()).map(|end| end.result), None);
+    }
+}
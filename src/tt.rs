@@ -0,0 +1,96 @@
+//! Transposition table shared by successive searches
+
+use crate::movegen::Move;
+use crate::search::MATE_BOUND;
+
+/// Default table size in megabytes (UCI "Hash" option)
+pub const DEFAULT_HASH_MB: usize = 16;
+
+/// How the stored score relates to the true value of the position
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Bound {
+    Exact,  // Score inside the search window
+    Lower,  // Fail-high: true score >= stored score
+    Upper,  // Fail-low: true score <= stored score
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TTEntry {
+    pub key: u64,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Option<TTEntry>>,
+}
+
+impl TranspositionTable {
+    /// Allocates a table of roughly `megabytes` MB
+    pub fn new(megabytes: usize) -> Self {
+        Self::with_entries(megabytes.max(1) * 1024 * 1024 / std::mem::size_of::<Option<TTEntry>>())
+    }
+
+    /// Allocates a table of exactly `count` entries, whatever their size on this platform
+    pub fn with_entries(count: usize) -> Self {
+        TranspositionTable { entries: vec![None; count.max(1)] }
+    }
+
+    /// Forgets every stored position
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+
+    /// Looks up a position; mate scores are converted back to be relative to `ply`
+    pub fn probe(&self, key: u64, ply: usize) -> Option<TTEntry> {
+        let mut entry = self.entries[self.index(key)].filter(|entry| entry.key == key)?;
+        entry.score = score_from_tt(entry.score, ply);
+        Some(entry)
+    }
+
+    /// Stores a search result, preferring deeper entries for the same position
+    pub fn store(&mut self, key: u64, depth: i32, score: i32, bound: Bound, best_move: Option<Move>, ply: usize) {
+        let index = self.index(key);
+        if let Some(old) = self.entries[index] {
+            if old.key == key && old.depth > depth && bound != Bound::Exact {
+                return;
+            }
+        }
+        // Keep the previous best move when this search did not find one
+        let best_move = best_move.or_else(|| self.entries[index].filter(|old| old.key == key).and_then(|old| old.best_move));
+        self.entries[index] = Some(TTEntry { key, best_move, score: score_to_tt(score, ply), depth, bound });
+    }
+
+    /// Permille of the first 1000 slots in use (UCI "hashfull")
+    pub fn hashfull(&self) -> usize {
+        let sample = self.entries.len().min(1000);
+        self.entries[..sample].iter().filter(|entry| entry.is_some()).count() * 1000 / sample
+    }
+}
+
+/// Mate scores are stored relative to the node rather than to the root
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
@@ -0,0 +1,303 @@
+//! Texel tuning of the evaluation parameters from labelled positions: `gk_chess_engine tune`
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::thread;
+
+use crate::endgame;
+use crate::eval::{self, EvalSink, Score, MAX_PHASE, PARAM_COUNT, PARAM_SECTIONS};
+use crate::params::PARAMS;
+use crate::Board;
+
+/// Where the tuned parameters are written unless told otherwise; never the tracked `src/params.rs`, which
+/// is only replaced on purpose with `--output`
+const DEFAULT_OUTPUT: &str = "params_tuned.rs";
+
+/// Adam optimiser constants
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+/// A labelled position reduced to the parameters its evaluation depends on
+struct Sample {
+    features: Vec<(u16, f32)>,  // Parameter index and how many times it counts, White's minus Black's
+    phase: f64,                 // Middlegame share of the evaluation, 0.0 to 1.0
+    result: f64,                // 1.0 = White won, 0.5 = draw, 0.0 = Black won
+}
+
+/// Collects the evaluation of a position as (term, side, parameter, count) entries
+#[derive(Default)]
+struct Tracer {
+    entries: Vec<(usize, usize, usize, f64)>,
+}
+
+impl EvalSink for Tracer {
+    fn add(&mut self, term: usize, side: usize, param: usize, count: i32) {
+        if count != 0 {
+            self.entries.push((term, side, param, count as f64));
+        }
+    }
+
+    fn scale(&mut self, term: usize, side: usize, percent: i32) {
+        for entry in self.entries.iter_mut().filter(|entry| entry.0 == term && entry.1 == side) {
+            entry.3 *= percent as f64 / 100.0;
+        }
+    }
+}
+
+/// Game result written in the line: "1-0", "0-1", "1/2-1/2" (PGN style) or [1.0], [0.5], [0.0]
+fn parse_result(line: &str) -> Option<f64> {
+    [("1/2-1/2", 0.5), ("1-0", 1.0), ("0-1", 0.0), ("[1.0]", 1.0), ("[0.5]", 0.5), ("[0.0]", 0.0)]
+        .into_iter()
+        .find(|(text, _)| line.contains(text))
+        .map(|(_, result)| result)
+}
+
+/// Reads a line such as `<EPD> c9 "1-0";` or `<FEN> [0.5]`: the first four FEN fields, the move
+/// counters if present, then the result anywhere after them
+fn parse_line(line: &str) -> Option<(Board, f64)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let counters = fields.iter().skip(4).take(2).take_while(|field| field.parse::<u32>().is_ok()).count();
+    let board = Board::from_fen(&fields.get(..4 + counters)?.join(" ")).ok()?;
+    Some((board, parse_result(line)?))
+}
+
+/// Turns a position into a sample, or None if the evaluation does not depend on the parameters
+fn make_sample(board: &Board, result: f64) -> Option<Sample> {
+    if board.in_check() || endgame::evaluate(board).is_some() {
+        return None;
+    }
+    let mut tracer = Tracer::default();
+    eval::collect(board, &mut tracer);
+    let mut counts = [0.0; PARAM_COUNT];
+    for (_, side, param, count) in tracer.entries {
+        counts[param] += if side == 0 { count } else { -count };
+    }
+    let features = counts.iter().enumerate().filter(|(_, &count)| count != 0.0).map(|(param, &count)| (param as u16, count as f32)).collect();
+    let phase = eval::game_phase(board) as f64 / MAX_PHASE as f64;
+    Some(Sample { features, phase, result })
+}
+
+/// Loads the labelled positions, reporting how many lines were skipped
+fn load_samples(path: &str) -> Result<(Vec<Sample>, usize), String> {
+    let file = File::open(path).map_err(|err| format!("Impossibile leggere {}: {}", path, err))?;
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|err| format!("Errore di lettura da {}: {}", path, err))?;
+        match parse_line(&line).and_then(|(board, result)| make_sample(&board, result)) {
+            Some(sample) => samples.push(sample),
+            None if !line.trim().is_empty() => skipped += 1,
+            None => {},
+        }
+    }
+    Ok((samples, skipped))
+}
+
+/// Evaluation of a sample in centipawns from White's point of view; `params` holds (mg, eg) pairs
+fn evaluate(sample: &Sample, params: &[f64]) -> f64 {
+    let (mut mg, mut eg) = (0.0, 0.0);
+    for &(param, count) in &sample.features {
+        mg += params[2 * param as usize] * count as f64;
+        eg += params[2 * param as usize + 1] * count as f64;
+    }
+    mg * sample.phase + eg * (1.0 - sample.phase)
+}
+
+/// Expected score of White for an evaluation, with the scaling constant `k`
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+/// Mean squared error of the predictions and, if asked, its gradient; spread over `threads` threads
+fn error_and_gradient(samples: &[Sample], params: &[f64], k: f64, threads: usize, gradient: bool) -> (f64, Vec<f64>) {
+    let chunk = samples.len().div_ceil(threads.max(1)).max(1);
+    let partials: Vec<(f64, Vec<f64>)> = thread::scope(|scope| {
+        let handles: Vec<_> = samples
+            .chunks(chunk)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut error = 0.0;
+                    let mut grad = vec![0.0; if gradient { params.len() } else { 0 }];
+                    for sample in chunk {
+                        let predicted = sigmoid(evaluate(sample, params), k);
+                        let difference = predicted - sample.result;
+                        error += difference * difference;
+                        if gradient {
+                            // d(error)/d(score), then split between the middlegame and endgame halves
+                            let slope = difference * predicted * (1.0 - predicted) * k * std::f64::consts::LN_10 / 400.0;
+                            for &(param, count) in &sample.features {
+                                let weight = slope * count as f64;
+                                grad[2 * param as usize] += weight * sample.phase;
+                                grad[2 * param as usize + 1] += weight * (1.0 - sample.phase);
+                            }
+                        }
+                    }
+                    (error, grad)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+
+    let count = samples.len().max(1) as f64;
+    let mut total_gradient = vec![0.0; if gradient { params.len() } else { 0 }];
+    let mut total_error = 0.0;
+    for (error, grad) in partials {
+        total_error += error;
+        for (total, value) in total_gradient.iter_mut().zip(grad) {
+            *total += 2.0 * value / count;
+        }
+    }
+    (total_error / count, total_gradient)
+}
+
+/// Scaling constant that best fits the current evaluation to the results
+fn fit_k(samples: &[Sample], params: &[f64], threads: usize) -> f64 {
+    let mut best = 1.0;
+    let mut step = 0.1;
+    for _ in 0..3 {
+        let candidates = (-10..=10).map(|i| best + i as f64 * step).filter(|&k| k > 0.0);
+        best = candidates
+            .map(|k| (k, error_and_gradient(samples, params, k, threads, false).0))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(best, |(k, _)| k);
+        step /= 10.0;
+    }
+    best
+}
+
+/// Adam: gradient descent with a per-parameter step size
+struct Adam {
+    rate: f64,
+    steps: i32,
+    first_moment: Vec<f64>,
+    second_moment: Vec<f64>,
+}
+
+impl Adam {
+    fn new(rate: f64, params: usize) -> Adam {
+        Adam { rate, steps: 0, first_moment: vec![0.0; params], second_moment: vec![0.0; params] }
+    }
+
+    /// Moves every parameter against its gradient
+    fn step(&mut self, params: &mut [f64], gradient: &[f64]) {
+        self.steps += 1;
+        for (index, &grad) in gradient.iter().enumerate() {
+            self.first_moment[index] = BETA1 * self.first_moment[index] + (1.0 - BETA1) * grad;
+            self.second_moment[index] = BETA2 * self.second_moment[index] + (1.0 - BETA2) * grad * grad;
+            let corrected_first = self.first_moment[index] / (1.0 - BETA1.powi(self.steps));
+            let corrected_second = self.second_moment[index] / (1.0 - BETA2.powi(self.steps));
+            params[index] -= self.rate * corrected_first / (corrected_second.sqrt() + EPSILON);
+        }
+    }
+}
+
+/// Writes the parameters as the Rust source of the `params` module
+fn write_params(path: &str, params: &[Score]) -> Result<(), String> {
+    let mut source = String::from(
+        "//! Evaluation weights (middlegame, endgame) in the layout of `eval::PARAM_SECTIONS`,\n\
+         //! written by `gk_chess_engine tune`\n\n\
+         use crate::eval::{Score, PARAM_COUNT};\n\n\
+         const fn s(mg: i32, eg: i32) -> Score {\n    Score::new(mg, eg)\n}\n\n\
+         #[rustfmt::skip]\n\
+         pub const PARAMS: [Score; PARAM_COUNT] = [\n",
+    );
+    for (section, &(name, start)) in PARAM_SECTIONS.iter().enumerate() {
+        let end = PARAM_SECTIONS.get(section + 1).map_or(PARAM_COUNT, |&(_, next)| next);
+        source.push_str(&format!("    // {}\n", name));
+        for line in params[start..end].chunks(8) {
+            let values: Vec<String> = line.iter().map(|score| format!("s({:4}, {:4}),", score.mg, score.eg)).collect();
+            source.push_str(&format!("    {}\n", values.join(" ")));
+        }
+    }
+    source.push_str("];\n");
+    fs::write(path, source).map_err(|err| format!("Impossibile scrivere {}: {}", path, err))
+}
+
+/// `tune <positions.epd> [--iterations N] [--rate X] [--output params.rs]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "Uso: gk_chess_engine tune <posizioni.epd> [--iterations N] [--rate X] [--output params.rs]";
+    let mut input = None;
+    let mut iterations: i32 = 1000;
+    let mut rate = 1.0;
+    let mut output = DEFAULT_OUTPUT.to_string();
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).ok_or_else(|| format!("Valore mancante per {}", args[i]));
+        let invalid = || format!("Valore non valido per {}", args[i]);
+        match args[i].as_str() {
+            "--iterations" => iterations = value?.parse().map_err(|_| invalid())?,
+            "--rate" => rate = value?.parse().map_err(|_| invalid())?,
+            "--output" => output = value?.clone(),
+            path => {
+                input = Some(path);
+                i += 1;
+                continue;
+            },
+        }
+        i += 2;
+    }
+    let input = input.ok_or(USAGE)?;
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let (samples, skipped) = load_samples(input)?;
+    if samples.is_empty() {
+        return Err(format!("Nessuna posizione utilizzabile in {}", input));
+    }
+    println!("Posizioni: {} (scartate {}: illeggibili, sotto scacco o finali noti)", samples.len(), skipped);
+
+    let mut params: Vec<f64> = PARAMS.iter().flat_map(|score| [score.mg as f64, score.eg as f64]).collect();
+    let k = fit_k(&samples, &params, threads);
+    let (initial_error, _) = error_and_gradient(&samples, &params, k, threads, false);
+    println!("K = {:.3}, errore iniziale {:.6}", k, initial_error);
+
+    let mut adam = Adam::new(rate, params.len());
+    for iteration in 1..=iterations {
+        let (error, gradient) = error_and_gradient(&samples, &params, k, threads, true);
+        adam.step(&mut params, &gradient);
+        if iteration % 50 == 0 || iteration == iterations {
+            println!("Iterazione {}: errore {:.6}", iteration, error);
+        }
+    }
+
+    let tuned: Vec<Score> = params.chunks(2).map(|pair| Score::new(pair[0].round() as i32, pair[1].round() as i32)).collect();
+    let (final_error, _) = error_and_gradient(&samples, &params, k, threads, false);
+    write_params(&output, &tuned)?;
+    println!("Errore finale {:.6}; parametri scritti in {}", final_error, output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sample whose evaluation is `count` times parameter 0, fully in the middlegame
+    fn sample(count: f32, result: f64) -> Sample {
+        Sample { features: vec![(0, count)], phase: 1.0, result }
+    }
+
+    #[test]
+    fn fit_k_recovers_the_scale_of_the_results() {
+        // Results drawn exactly from the sigmoid with K = 1.37 at evaluations -300..300
+        let params = [100.0, 100.0];
+        let samples: Vec<Sample> = (-3..=3).map(|count| sample(count as f32, sigmoid(100.0 * count as f64, 1.37))).collect();
+        let k = fit_k(&samples, &params, 2);
+        assert!((k - 1.37).abs() < 0.002, "K = {}", k);
+    }
+
+    #[test]
+    fn adam_steps_against_the_gradient() {
+        // White won with an evaluation of 0: the parameter should grow
+        let samples = [sample(1.0, 1.0)];
+        let mut params = vec![0.0, 0.0];
+        let (error, gradient) = error_and_gradient(&samples, &params, 1.0, 1, true);
+        assert!(gradient[0] < 0.0 && gradient[1] == 0.0, "{:?}", gradient);
+        let mut adam = Adam::new(1.0, params.len());
+        adam.step(&mut params, &gradient);
+        // The first Adam step moves by the learning rate whatever the size of the gradient
+        assert!((params[0] - 1.0).abs() < 1e-4, "{:?}", params);
+        assert_eq!(params[1], 0.0);
+        assert!(error_and_gradient(&samples, &params, 1.0, 1, false).0 < error);
+    }
+}
@@ -0,0 +1,356 @@
+//! Universal Chess Interface (UCI) front end: `gk_chess_engine uci`
+
+use std::io::{self, BufRead};
+use std::sync::Arc;
+
+use crate::book::{polyglot_key, BookStrategy, OpeningBook, DEFAULT_BOOK_DEPTH};
+use crate::dtm::DtmTables;
+use crate::engine::{Engine, SearchEvent};
+use crate::eval::evaluate_trace;
+use crate::fen::START_FEN;
+use crate::movegen::Move;
+use crate::nnue::Network;
+use crate::rng::Rng;
+use crate::search::{format_score, SearchInfo, SearchLimits, SearchResult};
+use crate::skill::{self, Skill, MAX_SKILL_LEVEL};
+use crate::syzygy::Tablebases;
+use crate::tt::DEFAULT_HASH_MB;
+use crate::Board;
+
+/// Default for UCI_Elo when strength limiting is switched on
+const DEFAULT_LIMITED_ELO: i32 = 1500;
+
+/// Values of the UCI options that affect each search
+struct Options {
+    multipv: usize,
+    skill_level: u32,
+    limit_strength: bool,
+    elo: i32,
+    own_book: bool,
+    book: Option<OpeningBook>,
+    book_strategy: BookStrategy,
+    book_depth: u32,
+    book_rng: Rng,
+    syzygy_path: String,
+    syzygy_probe_depth: i32,
+    syzygy_rule50: bool,
+    network: Option<Arc<Network>>,  // Loaded from EvalFile, used only with UseNNUE
+    use_nnue: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        let (min_elo, max_elo) = skill::elo_range();
+        Options {
+            multipv: 1,
+            skill_level: MAX_SKILL_LEVEL,
+            limit_strength: false,
+            elo: DEFAULT_LIMITED_ELO.clamp(min_elo, max_elo),
+            own_book: false,
+            book: None,
+            book_strategy: BookStrategy::Weighted,
+            book_depth: DEFAULT_BOOK_DEPTH,
+            book_rng: Rng::new(Rng::time_seed()),
+            syzygy_path: String::new(),
+            syzygy_probe_depth: 1,
+            syzygy_rule50: true,
+            network: None,
+            use_nnue: false,
+        }
+    }
+}
+
+impl Options {
+    /// UCI_LimitStrength takes precedence over Skill Level
+    fn skill(&self) -> Option<Skill> {
+        let seed = Rng::time_seed();
+        if self.limit_strength {
+            Some(Skill::from_elo(self.elo, seed))
+        } else {
+            Skill::from_level(self.skill_level, seed)
+        }
+    }
+
+    /// Move from the opening book when OwnBook is on and the position is in it
+    fn book_move(&mut self, board: &Board) -> Option<Move> {
+        let book = self.book.as_ref().filter(|_| self.own_book)?;
+        book.choose(board, self.book_strategy, self.book_depth, &mut self.book_rng)
+    }
+
+    /// (Re)opens the tables in SyzygyPath with the current probing settings
+    fn load_tablebases(&self, engine: &mut Engine) {
+        if self.syzygy_path.is_empty() || self.syzygy_path == "<empty>" {
+            engine.set_tablebases(None);
+            return;
+        }
+        match Tablebases::open(&self.syzygy_path, self.syzygy_probe_depth, self.syzygy_rule50) {
+            Ok(tablebases) => {
+                println!("info string found {}-piece Syzygy tablebases", tablebases.max_pieces());
+                engine.set_tablebases(Some(tablebases));
+            },
+            Err(err) => {
+                println!("info string {}", err);
+                engine.set_tablebases(None);
+            },
+        }
+    }
+
+    /// Loads the network named by EvalFile; `<empty>` unloads it
+    fn load_network(&mut self, engine: &mut Engine, path: &str) {
+        self.network = None;
+        if !path.is_empty() && path != "<empty>" {
+            match Network::load(path) {
+                Ok(network) => {
+                    println!("info string NNUE {} loaded, {} hidden neurons", path, network.hidden_size());
+                    self.network = Some(Arc::new(network));
+                },
+                Err(err) => println!("info string {}", err),
+            }
+        }
+        self.apply_network(engine);
+    }
+
+    /// UseNNUE picks the network if one is loaded, otherwise the handcrafted evaluation stays
+    fn apply_network(&self, engine: &mut Engine) {
+        if self.use_nnue && self.network.is_none() {
+            println!("info string UseNNUE needs an EvalFile, using the handcrafted evaluation");
+        }
+        engine.set_network(self.network.clone().filter(|_| self.use_nnue));
+    }
+
+    /// Loads the book named by BookFile; `<empty>` unloads it
+    fn load_book(&mut self, path: &str) {
+        self.book = None;
+        if path.is_empty() || path == "<empty>" {
+            return;
+        }
+        match OpeningBook::open(path) {
+            Ok(book) => {
+                println!("info string book {} loaded, {} entries", path, book.len());
+                self.book = Some(book);
+            },
+            Err(err) => println!("info string {}", err),
+        }
+    }
+}
+
+/// Formats an `info` line for a completed iteration
+pub fn format_info(info: &SearchInfo) -> String {
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_uci()).collect();
+    format!(
+        "info depth {} seldepth {} multipv {} score {} nodes {} nps {} time {} hashfull {} tbhits {} pv {}",
+        info.depth,
+        info.seldepth,
+        info.multipv,
+        format_score(info.score),
+        info.nodes,
+        info.nps(),
+        info.time_ms,
+        info.hashfull,
+        info.tbhits,
+        pv.join(" ")
+    )
+}
+
+/// Formats the final `bestmove` line, including the expected reply to ponder on
+pub fn format_bestmove(result: &SearchResult) -> String {
+    match (result.best_move, result.ponder_move) {
+        (Some(best), Some(ponder)) => format!("bestmove {} ponder {}", best.to_uci(), ponder.to_uci()),
+        (Some(best), None) => format!("bestmove {}", best.to_uci()),
+        (None, _) => "bestmove 0000".to_string(),
+    }
+}
+
+/// Reads UCI commands from stdin until `quit`
+pub fn run() {
+    let mut engine = Engine::new(DEFAULT_HASH_MB);
+    let mut board = Board::new();
+    let mut options = Options::default();
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first().copied() {
+            Some("uci") => {
+                println!("id name GK Chess Engine {}", env!("CARGO_PKG_VERSION"));
+                println!("id author Gianmarco Simeoni");
+                println!("option name Hash type spin default {} min 1 max 1024", DEFAULT_HASH_MB);
+                println!("option name Ponder type check default false");
+                println!("option name MultiPV type spin default 1 min 1 max 64");
+                println!("option name Skill Level type spin default {} min 0 max {}", MAX_SKILL_LEVEL, MAX_SKILL_LEVEL);
+                let (min_elo, max_elo) = skill::elo_range();
+                println!("option name UCI_LimitStrength type check default false");
+                println!("option name UCI_Elo type spin default {} min {} max {}", options.elo, min_elo, max_elo);
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!("option name BookStrategy type combo default Weighted var Best var Weighted");
+                println!("option name BookDepth type spin default {} min 0 max 200", DEFAULT_BOOK_DEPTH);
+                println!("option name SyzygyPath type string default <empty>");
+                println!("option name SyzygyProbeDepth type spin default 1 min 1 max 100");
+                println!("option name Syzygy50MoveRule type check default true");
+                println!("option name DtmPath type string default <empty>");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name UseNNUE type check default false");
+                println!("uciok");
+            },
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                engine.new_game();
+                board = Board::new();
+            },
+            Some("setoption") => set_option(&mut engine, &mut options, &tokens),
+            Some("position") => match parse_position(&tokens) {
+                Ok(position) => board = position,
+                Err(err) => println!("info string {}", err),
+            },
+            Some("go") if tokens.get(1) == Some(&"perft") => {
+                let depth = tokens.get(2).and_then(|d| d.parse().ok()).unwrap_or(1);
+                let start = std::time::Instant::now();
+                let nodes = board.clone().perft(depth);
+                println!("perft {} nodes {} time {} ms", depth, nodes, start.elapsed().as_millis());
+            },
+            Some("go") => {
+                let mut limits = parse_go(&tokens);
+                // Book moves are played instantly; a ponder or infinite search must wait for the GUI
+                if !limits.ponder && !limits.infinite {
+                    if let Some(mv) = options.book_move(&board) {
+                        println!("bestmove {}", mv.to_uci());
+                        continue;
+                    }
+                }
+                limits.multipv = options.multipv;
+                limits.skill = options.skill();
+                engine.start(board.clone(), limits, |event| match event {
+                    SearchEvent::Info(info) => println!("{}", format_info(&info)),
+                    SearchEvent::Finished(result) => println!("{}", format_bestmove(&result)),
+                });
+            },
+            Some("d") => {
+                println!("{}", board.to_fen());
+                println!("Polyglot key: {:016x}", polyglot_key(&board));
+                if let Some(dtm) = engine.dtm_tables().and_then(|tables| tables.probe(&board)) {
+                    println!("DTM: {}", format_score(dtm.score(0)));
+                }
+            },
+            Some("eval") => {
+                println!("{}", evaluate_trace(&board));
+                if let Some(network) = engine.network() {
+                    let score = network.evaluate_board(&board);
+                    println!("NNUE evaluation: {:+} cp (white side)", if board.white_to_move { score } else { -score });
+                }
+            },
+            Some("ponderhit") => engine.ponderhit(),
+            Some("stop") => engine.stop(),
+            Some("quit") => break,
+            _ => {},
+        }
+    }
+    engine.stop();
+}
+
+/// Handles `setoption name <name> value <value>`
+fn set_option(engine: &mut Engine, options: &mut Options, tokens: &[&str]) {
+    let name_start = tokens.iter().position(|&t| t == "name").map_or(tokens.len(), |i| i + 1);
+    let value_start = tokens.iter().position(|&t| t == "value").unwrap_or(tokens.len());
+    let name = tokens[name_start..value_start.max(name_start)].join(" ").to_lowercase();
+    let value = tokens.get(value_start + 1..).map(|rest| rest.join(" ")).unwrap_or_default();
+    match name.as_str() {
+        "hash" => {
+            if let Ok(megabytes) = value.parse::<usize>() {
+                engine.set_hash_size(megabytes.clamp(1, 1024));
+            }
+        },
+        // The GUI decides when to send `go ponder`; nothing to configure on our side
+        "ponder" => {},
+        "multipv" => options.multipv = value.parse::<usize>().map_or(1, |lines| lines.clamp(1, 64)),
+        "skill level" => options.skill_level = value.parse::<u32>().map_or(MAX_SKILL_LEVEL, |level| level.min(MAX_SKILL_LEVEL)),
+        "uci_limitstrength" => options.limit_strength = value == "true",
+        "uci_elo" => {
+            let (min_elo, max_elo) = skill::elo_range();
+            options.elo = value.parse::<i32>().map_or(options.elo, |elo| elo.clamp(min_elo, max_elo));
+        },
+        "ownbook" => options.own_book = value == "true",
+        "bookfile" => options.load_book(&value),
+        "bookstrategy" => options.book_strategy = BookStrategy::from_name(&value).unwrap_or(options.book_strategy),
+        "bookdepth" => options.book_depth = value.parse().unwrap_or(options.book_depth),
+        "syzygypath" => {
+            options.syzygy_path = value;
+            options.load_tablebases(engine);
+        },
+        "syzygyprobedepth" => {
+            options.syzygy_probe_depth = value.parse::<i32>().map_or(1, |depth| depth.clamp(1, 100));
+            options.load_tablebases(engine);
+        },
+        "syzygy50moverule" => {
+            options.syzygy_rule50 = value == "true";
+            options.load_tablebases(engine);
+        },
+        "dtmpath" => load_dtm_tables(engine, &value),
+        "evalfile" => options.load_network(engine, &value),
+        "usennue" => {
+            options.use_nnue = value == "true";
+            options.apply_network(engine);
+        },
+        _ => println!("info string unknown option {}", name),
+    }
+}
+
+/// Opens the tables made by `gentb` in DtmPath; `<empty>` unloads them
+fn load_dtm_tables(engine: &mut Engine, path: &str) {
+    if path.is_empty() || path == "<empty>" {
+        engine.set_dtm_tables(None);
+        return;
+    }
+    match DtmTables::open(path) {
+        Ok(tables) => {
+            println!("info string found {} DTM tables", tables.len());
+            engine.set_dtm_tables(Some(Arc::new(tables)));
+        },
+        Err(err) => {
+            println!("info string {}", err);
+            engine.set_dtm_tables(None);
+        },
+    }
+}
+
+/// Parses `position [startpos | fen <fen>] [moves <m1> <m2> ...]`
+fn parse_position(tokens: &[&str]) -> Result<Board, String> {
+    let moves_index = tokens.iter().position(|&t| t == "moves").unwrap_or(tokens.len());
+    let mut board = match tokens.get(1).copied() {
+        Some("startpos") => Board::from_fen(START_FEN)?,
+        Some("fen") => Board::from_fen(&tokens[2..moves_index].join(" "))?,
+        _ => return Err("position: expected startpos or fen".to_string()),
+    };
+    for text in tokens.iter().skip(moves_index + 1) {
+        let mv = board.parse_uci_move(text).ok_or_else(|| format!("illegal move {}", text))?;
+        board.apply_move(mv);
+    }
+    Ok(board)
+}
+
+/// Parses the arguments of `go`
+fn parse_go(tokens: &[&str]) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut i = 1;
+    while i < tokens.len() {
+        let value = tokens.get(i + 1).and_then(|v| v.parse::<u64>().ok());
+        match tokens[i] {
+            "infinite" => limits.infinite = true,
+            "ponder" => limits.ponder = true,
+            "depth" => limits.depth = value.map(|v| v as u32),
+            "nodes" => limits.nodes = value,
+            "movetime" => limits.movetime = value,
+            "wtime" => limits.wtime = value,
+            "btime" => limits.btime = value,
+            "winc" => limits.winc = value,
+            "binc" => limits.binc = value,
+            "movestogo" => limits.movestogo = value.map(|v| v as u32),
+            _ => {
+                i += 1;
+                continue;
+            },
+        }
+        i += if value.is_some() { 2 } else { 1 };
+    }
+    limits
+}
@@ -0,0 +1,216 @@
+//! Driving other UCI engines as child processes (tournaments)
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::search::MATE_SCORE;
+
+/// How long an engine may take to answer `uci` and `isready`
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How to launch an engine: `cmd=<path> [arg=<argument>]... [name=<name>] [option.<Name>=<value>]...`
+#[derive(Clone, Debug, Default)]
+pub struct EngineConfig {
+    pub name: String,                    // Empty: the name the engine reports
+    pub command: String,
+    pub args: Vec<String>,
+    pub options: Vec<(String, String)>,  // Sent with setoption before every game
+}
+
+impl EngineConfig {
+    /// Parses the `key=value` tokens of an engine definition
+    pub fn parse(tokens: &[String]) -> Result<EngineConfig, String> {
+        let mut config = EngineConfig::default();
+        for token in tokens {
+            let (key, value) = token.split_once('=').ok_or_else(|| format!("Atteso chiave=valore: '{}'", token))?;
+            match key {
+                "cmd" => config.command = value.to_string(),
+                "arg" => config.args.push(value.to_string()),
+                "name" => config.name = value.to_string(),
+                _ => match key.strip_prefix("option.") {
+                    Some(option) => config.options.push((option.to_string(), value.to_string())),
+                    None => return Err(format!("Parametro del motore sconosciuto: '{}'", key)),
+                },
+            }
+        }
+        if config.command.is_empty() {
+            return Err("Motore senza cmd=<programma>".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// The answer to a `go` command
+#[derive(Clone, Debug)]
+pub struct EngineMove {
+    pub best_move: String,     // UCI notation
+    pub score: Option<i32>,    // Last reported score, side to move's point of view (search scale for mates)
+    pub depth: u32,            // Last reported depth
+    pub elapsed: Duration,     // From sending `go` to receiving `bestmove`
+}
+
+/// A running engine process
+pub struct UciClient {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,  // Output lines, read on a separate thread so that waits can time out
+}
+
+impl UciClient {
+    /// Launches the engine and completes the UCI handshake
+    pub fn start(config: &EngineConfig) -> Result<UciClient, String> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Impossibile avviare {}: {}", config.command, err))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = UciClient { name: config.name.clone(), child, stdin, lines };
+        client.send("uci")?;
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            let line = client.read_line(deadline)?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                if client.name.is_empty() {
+                    client.name = name.trim().to_string();
+                }
+            } else if line.trim() == "uciok" {
+                break;
+            }
+        }
+        for (name, value) in &config.options {
+            client.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        client.wait_ready()?;
+        Ok(client)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| format!("{}: il motore non risponde", self.name))
+    }
+
+    /// Next output line, or an error if the engine exits or stays silent past `deadline`
+    fn read_line(&self, deadline: Instant) -> Result<String, String> {
+        match self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(format!("{}: tempo di risposta scaduto", self.name)),
+            Err(RecvTimeoutError::Disconnected) => Err(format!("{}: il motore è terminato", self.name)),
+        }
+    }
+
+    fn wait_ready(&mut self) -> Result<(), String> {
+        self.send("isready")?;
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        while self.read_line(deadline)?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    /// Prepares the engine for a new game
+    pub fn new_game(&mut self) -> Result<(), String> {
+        self.send("ucinewgame")?;
+        self.wait_ready()
+    }
+
+    /// Sends `position` and `go` commands and waits for `bestmove` until `timeout` has passed
+    pub fn go(&mut self, position: &str, go: &str, timeout: Duration) -> Result<EngineMove, String> {
+        self.send(position)?;
+        self.send(go)?;
+        let started = Instant::now();
+        let deadline = started + timeout;
+        let (mut score, mut depth) = (None, 0);
+        loop {
+            let line = self.read_line(deadline)?;
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.first().copied() {
+                Some("info") => {
+                    let (line_depth, line_score) = main_line_info(&tokens);
+                    depth = line_depth.unwrap_or(depth);
+                    score = line_score.or(score);
+                },
+                Some("bestmove") => {
+                    let best_move = tokens.get(1).ok_or_else(|| format!("{}: bestmove senza mossa", self.name))?;
+                    return Ok(EngineMove { best_move: best_move.to_string(), score, depth, elapsed: started.elapsed() });
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+/// Depth and score of an info line about the best line; other MultiPV lines give nothing
+fn main_line_info(tokens: &[&str]) -> (Option<u32>, Option<i32>) {
+    if info_value(tokens, "multipv").is_some_and(|line| line != 1) || tokens.get(1) == Some(&"string") {
+        return (None, None);
+    }
+    let depth = info_value(tokens, "depth").map(|depth| depth as u32);
+    let score = match (info_value(tokens, "cp"), info_value(tokens, "mate")) {
+        (Some(cp), _) => Some(cp),
+        (None, Some(moves)) => Some(if moves > 0 { MATE_SCORE - 2 * moves + 1 } else { -MATE_SCORE - 2 * moves }),
+        (None, None) => None,
+    };
+    (depth, score)
+}
+
+/// Integer following `key` in an info line
+fn info_value(tokens: &[&str], key: &str) -> Option<i32> {
+    let index = tokens.iter().position(|&token| token == key)?;
+    tokens.get(index + 1)?.parse().ok()
+}
+
+impl Drop for UciClient {
+    fn drop(&mut self) {
+        // Ask politely, then make sure the process is gone
+        let _ = self.send("quit");
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(line: &str) -> (Option<u32>, Option<i32>) {
+        main_line_info(&line.split_whitespace().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn info_lines_of_the_best_line_only() {
+        assert_eq!(info("info depth 12 seldepth 18 multipv 1 score cp 35 nodes 1000 pv e2e4"), (Some(12), Some(35)));
+        assert_eq!(info("info depth 9 score cp -120 nodes 500 pv d2d4"), (Some(9), Some(-120)));
+        // The second and third MultiPV lines say nothing about the move that will be played
+        assert_eq!(info("info depth 12 seldepth 18 multipv 2 score cp -80 pv d2d4"), (None, None));
+        assert_eq!(info("info depth 12 multipv 3 score mate -2 pv g1f3"), (None, None));
+        assert_eq!(info("info string NNUE cp 50"), (None, None));
+        // Mate in 3 for the engine is 5 plies away, mated in 2 is 4
+        assert_eq!(info("info depth 20 score mate 3 pv h5f7"), (Some(20), Some(MATE_SCORE - 5)));
+        assert_eq!(info("info depth 20 multipv 1 score mate -2 pv a2a3"), (Some(20), Some(-MATE_SCORE + 4)));
+        assert_eq!(info("info currmove e2e4 currmovenumber 1"), (None, None));
+    }
+}
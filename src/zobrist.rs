@@ -0,0 +1,79 @@
+//! Zobrist hashing keys used for the transposition table and repetition detection
+
+use crate::{Board, Piece};
+
+/// 12 pieces x 64 squares, side to move, 4 castling rights, 8 en passant files
+const KEY_COUNT: usize = 12 * 64 + 1 + 4 + 8;
+const SIDE_OFFSET: usize = 12 * 64;
+const CASTLING_OFFSET: usize = SIDE_OFFSET + 1;
+const EN_PASSANT_OFFSET: usize = CASTLING_OFFSET + 4;
+
+/// Keys are generated at compile time from a fixed seed so hashes are identical across runs
+static KEYS: [u64; KEY_COUNT] = generate_keys(0x6B43_6865_7373_2021);
+
+/// SplitMix64 step, usable in const context
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_keys(seed: u64) -> [u64; KEY_COUNT] {
+    let mut keys = [0u64; KEY_COUNT];
+    let mut state = seed;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        let (next, key) = splitmix64(state);
+        state = next;
+        keys[i] = key;
+        i += 1;
+    }
+    keys
+}
+
+/// Key for a piece standing on a square
+pub fn piece_key(piece: Piece, row: usize, col: usize) -> u64 {
+    KEYS[piece.index() * 64 + row * 8 + col]
+}
+
+/// Key toggled when it is Black's turn
+pub fn side_key() -> u64 {
+    KEYS[SIDE_OFFSET]
+}
+
+/// Combined key for a castling rights mask (see `GameState::castling_rights`)
+pub fn castling_key(rights: u8) -> u64 {
+    (0..4)
+        .filter(|bit| rights & (1 << bit) != 0)
+        .fold(0, |key, bit| key ^ KEYS[CASTLING_OFFSET + bit])
+}
+
+/// Key for an en passant target on the given file
+pub fn en_passant_key(col: usize) -> u64 {
+    KEYS[EN_PASSANT_OFFSET + col]
+}
+
+impl Board {
+    /// Computes the Zobrist key of the position from scratch
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+        for row in 0..8 {
+            for col in 0..8 {
+                let piece = self.squares[row][col];
+                if !piece.is_empty() {
+                    hash ^= piece_key(piece, row, col);
+                }
+            }
+        }
+        if !self.white_to_move {
+            hash ^= side_key();
+        }
+        hash ^= castling_key(self.game_state.castling_rights());
+        if let Some((_, col)) = self.game_state.en_passant_target {
+            hash ^= en_passant_key(col);
+        }
+        hash
+    }
+}